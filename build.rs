@@ -1,4 +1,4 @@
-use hobbes_nnue_arch::{preprocess, Network, UntransposedNetwork};
+use hobbes_nnue_arch::{header, preprocess, Network, UntransposedNetwork};
 use std::env;
 use std::fs;
 use std::mem::size_of;
@@ -6,6 +6,7 @@ use std::path::PathBuf;
 
 const INPUT_NET_FILE: &str = "hobbes.nnue";
 const OUTPUT_NET_FILE: &str = "hobbes_converted.nnue";
+const NETWORK_NAME_FILE: &str = "network.txt";
 
 fn main() {
     // Load the raw network
@@ -13,8 +14,25 @@ fn main() {
     let src: Box<UntransposedNetwork> = load_network_from_bytes(&raw_net);
     let mut dst: Box<Network> = unsafe { boxed_and_zeroed() };
 
-    // Transpose and permute the net
-    preprocess::process_network(&src, &mut dst);
+    // Transpose and permute the net for the SIMD backend chosen at compile time. Portable x86-64
+    // builds select the backend at runtime, so the L0 permutation is applied at startup instead.
+    let target_features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    let has_feature = |feature: &str| target_features.split(',').any(|f| f == feature);
+    let order: &'static [u8] = if has_feature("avx512f") {
+        preprocess::AVX512_ORDER
+    } else if has_feature("avx2") {
+        preprocess::AVX2_ORDER
+    } else {
+        &[]
+    };
+    preprocess::process_network_with(&src, &mut dst, &preprocess::permute_config_for(order));
+
+    // Portable x86-64 builds include both the AVX-512 and AVX2 kernels and dispatch at runtime.
+    println!("cargo::rustc-check-cfg=cfg(simd_dispatch)");
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    if target_arch == "x86_64" && !has_feature("avx2") {
+        println!("cargo:rustc-cfg=simd_dispatch");
+    }
 
     // Write the processed network
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
@@ -23,6 +41,15 @@ fn main() {
 
     println!("cargo:rustc-env=NETWORK_PATH={}", network_path.display());
     println!("cargo:rerun-if-changed=hobbes.nnue");
+
+    // Record the name of the embedded network, for the build metadata reported by the tools.
+    let network_name = fs::read_to_string(NETWORK_NAME_FILE).unwrap_or_default();
+    let network_name = match network_name.trim() {
+        "" => "unknown",
+        name => name,
+    };
+    println!("cargo:rustc-env=NETWORK_NAME={}", network_name);
+    println!("cargo:rerun-if-changed={}", NETWORK_NAME_FILE);
 }
 
 fn read_network_bytes(path: &str) -> Vec<u8> {
@@ -34,13 +61,18 @@ fn write_network_bytes(path: &PathBuf, dst: &Network) {
 }
 
 fn load_network_from_bytes(bytes: &[u8]) -> Box<UntransposedNetwork> {
-    assert_eq!(
-        bytes.len(),
-        size_of::<UntransposedNetwork>(),
-        "Byte slice is {} bytes but UntransposedNetwork is {} bytes — wrong network file?",
-        bytes.len(),
-        size_of::<UntransposedNetwork>()
-    );
+    let bytes = match header::parse(bytes) {
+        Ok((Some(_), payload)) => payload,
+        Ok((None, payload)) => {
+            println!(
+                "cargo:warning={} has no network header, so only its size could be checked. \
+                 Stamp it with the stamp_network tool in hobbes-nnue-arch.",
+                INPUT_NET_FILE
+            );
+            payload
+        }
+        Err(e) => panic!("invalid network file {}: {}", INPUT_NET_FILE, e),
+    };
     unsafe {
         let mut b: Box<UntransposedNetwork> = boxed_and_zeroed();
         std::ptr::copy_nonoverlapping(bytes.as_ptr(), b.as_mut() as *mut _ as *mut u8, bytes.len());
@@ -0,0 +1,36 @@
+//! Stamp a raw Bullet network with a Hobbes network header, recording the architecture it was
+//! trained for and a checksum of its contents.
+//!
+//! Usage: cargo run --release --manifest-path hobbes-nnue-arch/Cargo.toml --bin stamp_network -- <input> <output>
+
+use hobbes_nnue_arch::header;
+use std::{env, fs, process};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <input> <output>", args[0]);
+        process::exit(1);
+    }
+    let (input, output) = (&args[1], &args[2]);
+
+    let raw = fs::read(input).unwrap_or_else(|e| {
+        eprintln!("could not read {}: {}", input, e);
+        process::exit(1);
+    });
+    let stamped = header::stamp(&raw).unwrap_or_else(|e| {
+        eprintln!("could not stamp {}: {}", input, e);
+        process::exit(1);
+    });
+    fs::write(output, &stamped).unwrap_or_else(|e| {
+        eprintln!("could not write {}: {}", output, e);
+        process::exit(1);
+    });
+
+    println!(
+        "stamped {} -> {} (arch hash {:016x})",
+        input,
+        output,
+        header::arch_hash()
+    );
+}
@@ -0,0 +1,281 @@
+//! Network files may be prefixed with a small header, identifying the architecture the network was
+//! trained for and guarding against corruption. The header is laid out as follows (all integers
+//! little-endian), and is immediately followed by the raw `UntransposedNetwork` bytes:
+//!
+//! | offset | size | field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 8    | magic (`HOBBESNN`)                     |
+//! | 8      | 4    | header version                         |
+//! | 12     | 4    | reserved (zero)                        |
+//! | 16     | 8    | architecture hash (see [`arch_hash`])  |
+//! | 24     | 8    | checksum of the network bytes          |
+//!
+//! Files without the magic are treated as raw Bullet outputs, which can only be validated by size.
+
+use crate::{
+    UntransposedNetwork, BUCKETS, L1_SIZE, L2_SIZE, L3_SIZE, OUTPUT_BUCKET_COUNT, Q, SCALE,
+};
+use std::fmt;
+use std::mem::size_of;
+
+pub const MAGIC: [u8; 8] = *b"HOBBESNN";
+pub const HEADER_VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkHeader {
+    pub version: u32,
+    pub arch_hash: u64,
+    pub checksum: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    UnsupportedVersion(u32),
+    ArchMismatch { expected: u64, found: u64 },
+    ChecksumMismatch { expected: u64, found: u64 },
+    SizeMismatch { expected: usize, found: usize },
+    AlreadyStamped,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "unsupported network header version {} (expected {})",
+                    v, HEADER_VERSION
+                )
+            }
+            HeaderError::ArchMismatch { expected, found } => write!(
+                f,
+                "network architecture mismatch: file has arch hash {:016x}, engine expects {:016x}",
+                found, expected
+            ),
+            HeaderError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "network checksum mismatch: header says {:016x}, contents hash to {:016x}",
+                expected, found
+            ),
+            HeaderError::SizeMismatch { expected, found } => write!(
+                f,
+                "network is {} bytes but UntransposedNetwork is {} bytes",
+                found, expected
+            ),
+            HeaderError::AlreadyStamped => write!(f, "network already has a header"),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+impl NetworkHeader {
+    /// Create the header for the given raw network bytes, for the current architecture.
+    pub fn for_payload(payload: &[u8]) -> Self {
+        NetworkHeader {
+            version: HEADER_VERSION,
+            arch_hash: arch_hash(),
+            checksum: checksum(payload),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..8].copy_from_slice(&MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.arch_hash.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Read the header from the start of a network file, or `None` if the file has no header.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE || bytes[0..8] != MAGIC {
+            return None;
+        }
+        Some(NetworkHeader {
+            version: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            arch_hash: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            checksum: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+        })
+    }
+}
+
+/// Validate a network file, returning its header (if it has one) and the raw network bytes. Files
+/// with a header must match the current architecture and checksum; files without one are only
+/// checked against the size of `UntransposedNetwork`.
+pub fn parse(bytes: &[u8]) -> Result<(Option<NetworkHeader>, &[u8]), HeaderError> {
+    let header = NetworkHeader::from_bytes(bytes);
+    let payload = if header.is_some() {
+        &bytes[HEADER_SIZE..]
+    } else {
+        bytes
+    };
+
+    let expected_size = size_of::<UntransposedNetwork>();
+    if payload.len() != expected_size {
+        return Err(HeaderError::SizeMismatch {
+            expected: expected_size,
+            found: payload.len(),
+        });
+    }
+
+    if let Some(header) = header {
+        if header.version != HEADER_VERSION {
+            return Err(HeaderError::UnsupportedVersion(header.version));
+        }
+        if header.arch_hash != arch_hash() {
+            return Err(HeaderError::ArchMismatch {
+                expected: arch_hash(),
+                found: header.arch_hash,
+            });
+        }
+        let found = checksum(payload);
+        if header.checksum != found {
+            return Err(HeaderError::ChecksumMismatch {
+                expected: header.checksum,
+                found,
+            });
+        }
+    }
+
+    Ok((header, payload))
+}
+
+/// Prefix a raw Bullet network with a header for the current architecture.
+pub fn stamp(payload: &[u8]) -> Result<Vec<u8>, HeaderError> {
+    if NetworkHeader::from_bytes(payload).is_some() {
+        return Err(HeaderError::AlreadyStamped);
+    }
+    let expected_size = size_of::<UntransposedNetwork>();
+    if payload.len() != expected_size {
+        return Err(HeaderError::SizeMismatch {
+            expected: expected_size,
+            found: payload.len(),
+        });
+    }
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(&NetworkHeader::for_payload(payload).to_bytes());
+    bytes.extend_from_slice(payload);
+    Ok(bytes)
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// A hash of the architecture constants, so that a network trained with a different bucket layout
+/// or layer sizes is rejected even if it happens to have the same size.
+pub const fn arch_hash() -> u64 {
+    let mut hash = FNV_OFFSET;
+    let mut i = 0;
+    while i < BUCKETS.len() {
+        hash = fnv_u64(hash, BUCKETS[i] as u64);
+        i += 1;
+    }
+    hash = fnv_u64(hash, L1_SIZE as u64);
+    hash = fnv_u64(hash, L2_SIZE as u64);
+    hash = fnv_u64(hash, L3_SIZE as u64);
+    hash = fnv_u64(hash, OUTPUT_BUCKET_COUNT as u64);
+    hash = fnv_u64(hash, Q as u64);
+    hash = fnv_u64(hash, SCALE as u64);
+    hash
+}
+
+/// A 64-bit FNV-1a checksum of the network bytes.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+const fn fnv_u64(mut hash: u64, value: u64) -> u64 {
+    let bytes = value.to_le_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(FNV_PRIME);
+        i += 1;
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A raw network of the right size, with some non-zero contents.
+    fn payload() -> Vec<u8> {
+        (0..size_of::<UntransposedNetwork>())
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_stamped_network() {
+        let payload = payload();
+        let stamped = stamp(&payload).unwrap();
+        assert_eq!(stamped.len(), HEADER_SIZE + payload.len());
+
+        let (header, parsed) = parse(&stamped).unwrap();
+        assert_eq!(header, Some(NetworkHeader::for_payload(&payload)));
+        assert_eq!(parsed, &payload[..]);
+        assert_eq!(stamp(&stamped), Err(HeaderError::AlreadyStamped));
+    }
+
+    #[test]
+    fn test_parse_mismatched_header() {
+        let payload = payload();
+        let stamped = stamp(&payload).unwrap();
+        let header = NetworkHeader::for_payload(&payload);
+
+        let mut wrong_version = stamped.clone();
+        wrong_version[8..12].copy_from_slice(&(HEADER_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            parse(&wrong_version),
+            Err(HeaderError::UnsupportedVersion(HEADER_VERSION + 1))
+        );
+
+        let mut wrong_arch = stamped.clone();
+        wrong_arch[16..24].copy_from_slice(&(arch_hash() ^ 1).to_le_bytes());
+        assert_eq!(
+            parse(&wrong_arch),
+            Err(HeaderError::ArchMismatch {
+                expected: arch_hash(),
+                found: arch_hash() ^ 1,
+            })
+        );
+
+        let mut corrupted = stamped.clone();
+        corrupted[HEADER_SIZE + 1000] ^= 0xff;
+        assert_eq!(
+            parse(&corrupted),
+            Err(HeaderError::ChecksumMismatch {
+                expected: header.checksum,
+                found: checksum(&corrupted[HEADER_SIZE..]),
+            })
+        );
+
+        let truncated = &stamped[..stamped.len() - 2];
+        assert_eq!(
+            parse(truncated),
+            Err(HeaderError::SizeMismatch {
+                expected: payload.len(),
+                found: payload.len() - 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_headerless_network() {
+        let payload = payload();
+        assert_eq!(parse(&payload), Ok((None, &payload[..])));
+
+        assert_eq!(
+            parse(&payload[1..]),
+            Err(HeaderError::SizeMismatch {
+                expected: payload.len(),
+                found: payload.len() - 1,
+            })
+        );
+    }
+}
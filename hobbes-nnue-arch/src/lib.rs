@@ -1,4 +1,5 @@
 pub mod arch;
+pub mod header;
 pub mod preprocess;
 
 pub use arch::*;
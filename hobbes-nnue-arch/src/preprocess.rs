@@ -42,11 +42,16 @@ const L0_ACTIVATIONS: [usize; L1_SIZE / 2] = [
 
 ];
 
+/// The order of the 128-bit chunks of each L0 block that cancels out the cross-lane behaviour of
+/// packus, for each SIMD backend.
+pub const AVX512_ORDER: &[u8] = &[0, 2, 4, 6, 1, 3, 5, 7];
+pub const AVX2_ORDER: &[u8] = &[0, 2, 1, 3];
+
 #[cfg(target_feature = "avx512f")]
-static ORDER: &[u8] = &[0, 2, 4, 6, 1, 3, 5, 7];
+static ORDER: &[u8] = AVX512_ORDER;
 
 #[cfg(all(target_feature = "avx2", not(target_feature = "avx512f")))]
-static ORDER: &[u8] = &[0, 2, 1, 3];
+static ORDER: &[u8] = AVX2_ORDER;
 
 #[cfg(not(any(target_feature = "avx512f", target_feature = "avx2")))]
 static ORDER: &[u8] = &[];
@@ -59,6 +64,14 @@ pub const fn permute_config() -> PermuteConfig {
     }
 }
 
+/// The permutation for a given chunk order, for when the SIMD backend is not known at compile time.
+pub const fn permute_config_for(order: &'static [u8]) -> PermuteConfig {
+    PermuteConfig {
+        needs_permuting: !order.is_empty(),
+        order,
+    }
+}
+
 /// Convert an `UntransposedNetwork` (the output format from Bullet) into a `Network` (the optimal
 /// format for inference).
 ///
@@ -69,6 +82,12 @@ pub const fn permute_config() -> PermuteConfig {
 /// 4. Reorders L2 weights: src[input][bucket][output] -> dst[bucket][input][output]
 /// 5. Reorders L3 weights: src[input][bucket] -> dst[bucket][input]
 pub fn process_network(src: &UntransposedNetwork, dst: &mut Network) {
+    process_network_with(src, dst, &permute_config());
+}
+
+/// Convert an `UntransposedNetwork` into a `Network`, using the given L0 permutation rather than
+/// the one for the compile-time target.
+pub fn process_network_with(src: &UntransposedNetwork, dst: &mut Network, config: &PermuteConfig) {
     let repermute = compute_repermute_indices();
 
     repermute_l0_biases(&mut dst.l0_biases, &src.l0_biases, &repermute);
@@ -79,24 +98,7 @@ pub fn process_network(src: &UntransposedNetwork, dst: &mut Network) {
 
     repermute_l0_weights(&mut dst.l0_threat_weights, &src.l0_threat_weights, &repermute);
 
-    let config = permute_config();
-    if config.needs_permuting {
-        let order = config.order;
-        let num_chunks = order.len();
-
-        let chunk_size: usize = 8; // 128 bits = 8 i16 values
-        let block_size = num_chunks * chunk_size;
-
-        // Permute L0 piece-square weights per bucket.
-        for bucket in dst.l0_psq_weights.iter_mut() {
-            permute(bucket, order, chunk_size, block_size);
-        }
-        // Permute L0 threat weights.
-        permute(&mut dst.l0_threat_weights, order, chunk_size, block_size);
-
-        // Permute L0 biases.
-        permute(&mut dst.l0_biases, order, chunk_size, block_size);
-    }
+    permute_l0(dst, config);
 
     for bucket in 0..OUTPUT_BUCKET_COUNT {
         for (tgt_input_idx, &src_input_idx) in repermute.iter().enumerate() {
@@ -135,6 +137,30 @@ pub fn process_network(src: &UntransposedNetwork, dst: &mut Network) {
     }
 }
 
+/// Permute the L0 weights and biases of a processed network to cancel out the cross-lane behaviour
+/// of packus.
+pub fn permute_l0(dst: &mut Network, config: &PermuteConfig) {
+    if !config.needs_permuting {
+        return;
+    }
+
+    let order = config.order;
+    let num_chunks = order.len();
+
+    let chunk_size: usize = 8; // 128 bits = 8 i16 values
+    let block_size = num_chunks * chunk_size;
+
+    // Permute L0 piece-square weights per bucket.
+    for bucket in dst.l0_psq_weights.iter_mut() {
+        permute(bucket, order, chunk_size, block_size);
+    }
+    // Permute L0 threat weights.
+    permute(&mut dst.l0_threat_weights, order, chunk_size, block_size);
+
+    // Permute L0 biases.
+    permute(&mut dst.l0_biases, order, chunk_size, block_size);
+}
+
 /// Compute the repermutation indices for sparsity optimisation.
 fn compute_repermute_indices() -> [usize; L1_SIZE / 2] {
     let mut indices: [usize; L1_SIZE / 2] = std::array::from_fn(|i| i);
@@ -11,6 +11,7 @@ pub mod observer;
 pub mod piece;
 pub mod rank;
 pub mod ray;
+pub mod san;
 pub mod side;
 pub mod square;
 pub mod zobrist;
@@ -46,9 +47,20 @@ pub mod setwise {
     #[cfg(not(any(
         target_feature = "avx512f",
         target_feature = "avx2",
-        target_feature = "neon"
+        target_feature = "neon",
+        simd_dispatch
     )))]
     pub use crate::board::setwise::scalar::*;
+
+    // Portable x86-64 builds compile every x86 implementation, and select one at runtime.
+    #[cfg(simd_dispatch)]
+    mod avx512;
+    #[cfg(simd_dispatch)]
+    mod avx2;
+    #[cfg(simd_dispatch)]
+    mod dispatch;
+    #[cfg(simd_dispatch)]
+    pub use crate::board::setwise::dispatch::*;
 }
 
 use crate::board::castling::Rights;
@@ -4,6 +4,7 @@ use crate::board::rank::Rank;
 use crate::board::side::Side;
 use crate::board::side::Side::{Black, White};
 use crate::board::square::Square;
+use crate::board::Board;
 
 // Check if the castling move is kingside or queenside
 pub fn is_kingside(from: Square, to: Square) -> bool {
@@ -39,6 +40,19 @@ pub fn king_to(side: Side, kingside: bool) -> Square {
     }
 }
 
+/// Whether the castling rights of the position can only be expressed in Fischer Random Chess,
+/// i.e. a side may castle with its king off the e-file or a rook off the a- or h-file.
+pub fn requires_frc(board: &Board) -> bool {
+    [White, Black].into_iter().any(|side| {
+        let king_file = board.king_sq(side).file();
+        let kingside = board.rights.kingside(side);
+        let queenside = board.rights.queenside(side);
+        (kingside.is_some() || queenside.is_some()) && king_file != File::E
+            || kingside.is_some_and(|file| file != File::H)
+            || queenside.is_some_and(|file| file != File::A)
+    })
+}
+
 // Packed representation of castling rights, inspired by Viridithas.
 // The starting file of each rook is required for DFRC-compatibility.
 // 8 possible starting files means 3 bits per rook. The bottom 4 bits
@@ -0,0 +1,257 @@
+use crate::board::file::File;
+use crate::board::movegen::MoveFilter;
+use crate::board::moves::{Move, MoveFlag, MoveList};
+use crate::board::observer::NullBoardObserver;
+use crate::board::piece::Piece;
+use crate::board::square::Square;
+use crate::board::Board;
+
+/// Format a legal move in Standard Algebraic Notation. The moving piece is disambiguated by file,
+/// then by rank, then by both, only as far as needed to tell it apart from the other pieces of the
+/// same type that can reach the same square. Castling is written as `O-O` or `O-O-O` depending on
+/// which of the rook files in the castling rights the king castles with, which also covers Fischer
+/// Random Chess, where the move itself is encoded as the king capturing its own rook.
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let mut san = match mv.flag() {
+        MoveFlag::CastleK => "O-O".to_string(),
+        MoveFlag::CastleQ => "O-O-O".to_string(),
+        _ => move_text(board, mv),
+    };
+
+    let mut after = *board;
+    after.make(&mv, &mut NullBoardObserver);
+    if !after.checkers.is_empty() {
+        san.push(if legal_moves(&after).is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+/// The SAN of a non-castling move, without any check suffix.
+fn move_text(board: &Board, mv: Move) -> String {
+    let piece = board.piece_at(mv.from()).unwrap();
+    let from = Move::uci_sq(mv.from());
+    let capture = if board.captured(&mv).is_some() {
+        "x"
+    } else {
+        ""
+    };
+    let to = Move::uci_sq(mv.to());
+    let promo = mv
+        .promo_piece()
+        .map_or(String::new(), |promo| format!("={}", piece_char(promo)));
+
+    if piece == Piece::Pawn {
+        let file = if capture.is_empty() { "" } else { &from[..1] };
+        return format!("{}{}{}{}", file, capture, to, promo);
+    }
+
+    let rivals: Vec<Square> = legal_moves(board)
+        .into_iter()
+        .filter(|other| {
+            !other.is_castle()
+                && other.to() == mv.to()
+                && other.from() != mv.from()
+                && board.piece_at(other.from()) == Some(piece)
+        })
+        .map(|other| other.from())
+        .collect();
+    let disambiguation = if rivals.is_empty() {
+        ""
+    } else if rivals.iter().all(|sq| sq.file() != mv.from().file()) {
+        &from[..1]
+    } else if rivals.iter().all(|sq| sq.rank() != mv.from().rank()) {
+        &from[1..]
+    } else {
+        &from
+    };
+    format!("{}{}{}{}", piece_char(piece), disambiguation, capture, to)
+}
+
+/// Parse a move in Standard Algebraic Notation (e.g. `Nbd7`, `exd5`, `e8=Q+`, `O-O`), resolving it
+/// against the legal moves in the given position. Check and annotation suffixes are ignored, as is
+/// a missing or superfluous capture sign. Castling resolves against the rook files in the castling
+/// rights, so it is written the same way in standard chess and Fischer Random Chess.
+pub fn parse_san(board: &Board, san: &str) -> Result<Move, String> {
+    let notation = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal = legal_moves(board);
+
+    let castle = match notation {
+        "O-O" | "0-0" => Some((MoveFlag::CastleK, board.rights.kingside(board.stm))),
+        "O-O-O" | "0-0-0" => Some((MoveFlag::CastleQ, board.rights.queenside(board.stm))),
+        _ => None,
+    };
+    if let Some((flag, rook_file)) = castle {
+        if rook_file.is_none() {
+            return Err(format!(
+                "illegal move '{}': no right to castle on that side",
+                san
+            ));
+        }
+        return legal
+            .into_iter()
+            .find(|mv| mv.flag() == flag)
+            .ok_or_else(|| format!("illegal move '{}': castling is blocked or in check", san));
+    }
+
+    let mut chars: Vec<char> = notation.chars().filter(|&c| c != 'x' && c != '-').collect();
+    let piece = match chars.first().and_then(|&c| parse_piece(c)) {
+        Some(piece) => {
+            chars.remove(0);
+            piece
+        }
+        None => Piece::Pawn,
+    };
+
+    // The promotion piece may be written with or without the '=' separator.
+    let promo = match chars.last().and_then(|&c| parse_piece(c)) {
+        Some(promo) if piece == Piece::Pawn => {
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+            Some(promo)
+        }
+        _ => None,
+    };
+
+    if chars.len() < 2 {
+        return Err(format!("invalid move '{}'", san));
+    }
+    let to = parse_square(chars[chars.len() - 2], chars[chars.len() - 1])
+        .ok_or_else(|| format!("invalid move '{}': no destination square", san))?;
+    let mut from_file = None;
+    let mut from_rank = None;
+    for &c in &chars[..chars.len() - 2] {
+        match c {
+            'a'..='h' => from_file = File::from_char(c),
+            '1'..='8' => from_rank = Some(c as u8 - b'1'),
+            _ => return Err(format!("invalid move '{}': unexpected '{}'", san, c)),
+        }
+    }
+
+    let candidates: Vec<Move> = legal
+        .into_iter()
+        .filter(|mv| {
+            !mv.is_castle()
+                && mv.to() == to
+                && board.piece_at(mv.from()) == Some(piece)
+                && mv.promo_piece() == promo
+                && from_file.is_none_or(|file| mv.from().file() == file)
+                && from_rank.is_none_or(|rank| mv.from().rank() as u8 == rank)
+        })
+        .collect();
+    match candidates.as_slice() {
+        [mv] => Ok(*mv),
+        [] => Err(format!("illegal move '{}' in {}", san, board.to_fen())),
+        _ => {
+            let options: Vec<String> = candidates.iter().map(|mv| to_san(board, *mv)).collect();
+            Err(format!(
+                "ambiguous move '{}', could be {}",
+                san,
+                options.join(" or ")
+            ))
+        }
+    }
+}
+
+fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = MoveList::new();
+    board.gen_moves(MoveFilter::All, &mut moves);
+    moves.iter().map(|entry| entry.mv).collect()
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+fn parse_piece(c: char) -> Option<Piece> {
+    match c {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+fn parse_square(file: char, rank: char) -> Option<Square> {
+    let file = File::from_char(file)?;
+    let rank = rank.to_digit(10).filter(|rank| (1..=8).contains(rank))?;
+    Some(Square((rank as u8 - 1) * 8 + file as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    fn board(fen: &str) -> Board {
+        init();
+        let mut board = Board::from_fen(fen).unwrap();
+        board.set_frc(crate::board::castling::requires_frc(&board));
+        board
+    }
+
+    #[test]
+    fn test_san_round_trip() {
+        let cases = [
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", "O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "a1a8", "Rxa8+"),
+            ("1n3n2/8/8/8/8/8/8/4K2k b - - 0 1", "b8d7", "Nbd7"),
+            ("k7/8/8/8/8/8/8/R4R1K w - - 0 1", "a1c1", "Rac1"),
+            ("7k/8/8/2N5/8/2N5/8/K7 w - - 0 1", "c5e4", "N5e4"),
+            ("k7/8/8/2Q1Q3/8/2Q5/8/7K w - - 0 1", "c5d4", "Qc5d4"),
+            ("6k1/5ppp/8/8/8/8/8/R6K w - - 0 1", "a1a8", "Ra8#"),
+            ("7k/1P6/8/8/8/8/8/K7 w - - 0 1", "b7b8q", "b8=Q+"),
+            ("7k/8/8/3pP3/8/8/8/K7 w - d6 0 1", "e5d6", "exd6"),
+            ("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1", "e1b1", "O-O-O"),
+        ];
+        for (fen, uci, expected) in cases {
+            let board = board(fen);
+            let mv = legal_moves(&board)
+                .into_iter()
+                .find(|mv| mv.to_uci() == uci)
+                .unwrap();
+            assert_eq!(to_san(&board, mv), expected, "{}", fen);
+            assert_eq!(parse_san(&board, expected), Ok(mv), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_parse_san_errors() {
+        let board = board("7k/8/8/2N5/8/2N5/8/K7 w - - 0 1");
+        let ambiguous = parse_san(&board, "Ne4").unwrap_err();
+        assert!(
+            ambiguous.contains("N5e4") && ambiguous.contains("N3e4"),
+            "{}",
+            ambiguous
+        );
+        assert!(parse_san(&board, "Nd4")
+            .unwrap_err()
+            .starts_with("illegal move"));
+        assert!(parse_san(&board, "O-O")
+            .unwrap_err()
+            .contains("no right to castle"));
+    }
+}
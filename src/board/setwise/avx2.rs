@@ -1,6 +1,7 @@
 use crate::board::bitboard::Bitboard;
 use std::arch::x86_64::*;
 
+#[inline(always)]
 pub fn knights_and_sliders_setwise(
     knights: Bitboard,
     orthos: Bitboard,
@@ -14,6 +15,7 @@ pub fn knights_and_sliders_setwise(
     }
 }
 
+#[inline(always)]
 fn knights_setwise(knights: Bitboard) -> [__m256i; 2] {
     let a = 0x0101010101010101i64;
     let b = a << 1;
@@ -33,6 +35,7 @@ fn knights_setwise(knights: Bitboard) -> [__m256i; 2] {
     }
 }
 
+#[inline(always)]
 fn sliders_setwise(orth: Bitboard, diag: Bitboard, blockers: Bitboard) -> [__m256i; 2] {
     let a = 0x0101010101010101i64;
     let h = a << 7;
@@ -13,6 +13,7 @@ const R2: i64 = Rank::Two.to_bb().0 as i64;
 const R7: i64 = Rank::Seven.to_bb().0 as i64;
 const R8: i64 = Rank::Eight.to_bb().0 as i64;
 
+#[inline(always)]
 fn knights_setwise(knights: Bitboard) -> __m512i {
     unsafe {
         // knight moves are done clockwise, starting at wnw.
@@ -37,6 +38,7 @@ fn knights_setwise(knights: Bitboard) -> __m512i {
     }
 }
 
+#[inline(always)]
 fn sliders_setwise(orth: Bitboard, diag: Bitboard, blockers: Bitboard) -> __m512i {
     unsafe {
         let (orth, diag) = (orth.0 as i64, diag.0 as i64);
@@ -74,6 +76,7 @@ fn sliders_setwise(orth: Bitboard, diag: Bitboard, blockers: Bitboard) -> __m512
     }
 }
 
+#[inline(always)]
 pub fn knights_and_sliders_setwise(
     knights: Bitboard,
     orthos: Bitboard,
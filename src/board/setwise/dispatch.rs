@@ -0,0 +1,40 @@
+use crate::board::bitboard::Bitboard;
+use crate::board::setwise::{avx2, avx512, scalar};
+use crate::tools::cpu::{simd_backend, SimdBackend};
+
+pub fn knights_and_sliders_setwise(
+    knights: Bitboard,
+    orthos: Bitboard,
+    diags: Bitboard,
+    blockers: Bitboard,
+) -> Bitboard {
+    match simd_backend() {
+        SimdBackend::Avx512 => unsafe {
+            knights_and_sliders_avx512(knights, orthos, diags, blockers)
+        },
+        SimdBackend::Avx2 => unsafe {
+            knights_and_sliders_avx2(knights, orthos, diags, blockers)
+        },
+        _ => scalar::knights_and_sliders_setwise(knights, orthos, diags, blockers),
+    }
+}
+
+#[target_feature(enable = "avx512f")]
+unsafe fn knights_and_sliders_avx512(
+    knights: Bitboard,
+    orthos: Bitboard,
+    diags: Bitboard,
+    blockers: Bitboard,
+) -> Bitboard {
+    avx512::knights_and_sliders_setwise(knights, orthos, diags, blockers)
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn knights_and_sliders_avx2(
+    knights: Bitboard,
+    orthos: Bitboard,
+    diags: Bitboard,
+    blockers: Bitboard,
+) -> Bitboard {
+    avx2::knights_and_sliders_setwise(knights, orthos, diags, blockers)
+}
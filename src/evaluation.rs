@@ -1,9 +1,21 @@
 mod accumulator;
 mod cache;
+#[cfg(simd_dispatch)]
+mod dispatch;
 pub mod feature;
 mod forward;
+/// The SIMD leaf kernels used by the accumulators and the forward pass, written against the
+/// backend-agnostic `simd` interface. When the SIMD backend is chosen at runtime, the kernels are
+/// instead compiled once per backend by the dispatcher.
+#[cfg(any(target_feature = "avx2", target_feature = "neon"))]
+mod kernels;
+/// The leaf kernels of the accumulators without SIMD, for CPUs which support none of the SIMD
+/// backends.
+#[cfg(not(any(target_feature = "avx2", target_feature = "neon")))]
+mod scalar_kernels;
 pub mod sparse;
 pub mod stats;
+pub mod texel;
 
 mod simd {
     #[cfg(target_feature = "avx512f")]
@@ -26,8 +38,19 @@ mod simd {
         not(any(target_feature = "avx2", target_feature = "avx512f"))
     ))]
     pub use neon::*;
+
+    // Portable x86-64 builds compile both backends, and select one at runtime.
+    #[cfg(simd_dispatch)]
+    pub mod avx512;
+    #[cfg(simd_dispatch)]
+    pub mod avx2;
 }
 
+#[cfg(simd_dispatch)]
+use dispatch as kernels;
+#[cfg(not(any(target_feature = "avx2", target_feature = "neon", simd_dispatch)))]
+use scalar_kernels as kernels;
+
 use crate::board::file::File;
 use crate::board::moves::Move;
 use crate::board::piece::Piece;
@@ -40,25 +63,134 @@ use crate::evaluation::accumulator::Accumulator;
 use crate::evaluation::cache::InputBucketCache;
 use crate::evaluation::forward::{inference, Forward};
 use crate::search::parameters::{
-    material_scaling_base, scale_value_bishop, scale_value_knight, scale_value_pawn,
-    scale_value_queen, scale_value_rook,
+    hm_scaling_base, material_scaling_base, scale_value_bishop, scale_value_knight,
+    scale_value_pawn, scale_value_queen, scale_value_rook,
 };
 use crate::search::MAX_PLY;
+use crate::tools::cpu::{simd_backend, SimdBackend};
 use crate::tools::utils::boxed_and_zeroed;
 use accumulator::{psq, threat};
+use hobbes_nnue_arch::preprocess::PermuteConfig;
 use hobbes_nnue_arch::{
-    Network, BUCKETS, L1_SIZE, L2_SIZE, L3_SIZE, OUTPUT_BUCKET_COUNT, Q, SCALE,
+    header, preprocess, Network, UntransposedNetwork, BUCKETS, L1_SIZE, L2_SIZE, L3_SIZE,
+    OUTPUT_BUCKET_COUNT, Q, SCALE,
 };
+use std::sync::{Mutex, OnceLock};
 
 pub const MAX_ACCUMULATORS: usize = MAX_PLY + 8;
 
-pub(crate) static NETWORK: Network =
+/// The network embedded into the binary at build time. When no other network is loaded, it is read
+/// straight from this static, at an address known at compile time.
+static NETWORK: Network =
     unsafe { std::mem::transmute(*include_bytes!(env!("NETWORK_PATH"))) };
 
+/// The network used in place of the embedded one: a network loaded at runtime via the EvalFile
+/// option, or in portable builds the embedded network permuted for the SIMD backend in use. Each
+/// search reads it once when it starts, and it is never freed, as searches keep a reference to it.
+static ACTIVE_NETWORK: Mutex<Option<&'static Network>> = Mutex::new(None);
+
+/// The embedded network permuted for the SIMD backend in use, in portable builds which need it.
+static PERMUTED_EMBEDDED_NETWORK: OnceLock<&'static Network> = OnceLock::new();
+
+/// Where the evaluation reads the network from: either the embedded network, or a network in
+/// memory. The accumulators and the forward pass are generic over it, so that the code reading the
+/// embedded network is compiled separately, against the address of its static.
+pub trait NetworkSource: Copy {
+    fn get(self) -> &'static Network;
+}
+
+#[derive(Clone, Copy)]
+struct Embedded;
+
+impl NetworkSource for Embedded {
+    #[inline(always)]
+    fn get(self) -> &'static Network {
+        &NETWORK
+    }
+}
+
+impl NetworkSource for &'static Network {
+    #[inline(always)]
+    fn get(self) -> &'static Network {
+        self
+    }
+}
+
+/// The network to use for the next search, or `None` for the embedded network.
+fn active_network() -> Option<&'static Network> {
+    *ACTIVE_NETWORK.lock().unwrap()
+}
+
+fn set_active_network(network: Option<&'static Network>) {
+    *ACTIVE_NETWORK.lock().unwrap() = network;
+}
+
+/// Load an untransposed network (the output format from Bullet) from a file, preprocess it into the
+/// inference format, and make it the active network from the next search on. If the file has a
+/// network header, it must match the engine's architecture and checksum.
+pub fn load_network(path: &str) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let (header, payload) = header::parse(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+    if header.is_none() {
+        println!("info string {} has no network header, only its size was checked", path);
+    }
+
+    let mut src: Box<UntransposedNetwork> = unsafe { boxed_and_zeroed() };
+    let mut dst: Box<Network> = unsafe { boxed_and_zeroed() };
+    unsafe {
+        let src_ptr = src.as_mut() as *mut UntransposedNetwork as *mut u8;
+        std::ptr::copy_nonoverlapping(payload.as_ptr(), src_ptr, payload.len());
+    }
+    preprocess::process_network_with(&src, &mut dst, &permute_config());
+
+    set_active_network(Some(Box::leak(dst)));
+    Ok(())
+}
+
+/// Switch back to the network embedded in the binary from the next search on.
+pub fn use_embedded_network() {
+    set_active_network(PERMUTED_EMBEDDED_NETWORK.get().copied());
+}
+
+/// Prepare the network for inference. Must be called at startup, after the SIMD backend has been
+/// detected and before any evaluation.
+pub fn init() {
+    // The embedded network is stored unpermuted in portable builds, so a copy of it is permuted for
+    // the SIMD backend detected at startup.
+    #[cfg(simd_dispatch)]
+    if matches!(simd_backend(), SimdBackend::Avx512 | SimdBackend::Avx2) {
+        let mut permuted: Box<Network> = unsafe { boxed_and_zeroed() };
+        unsafe { std::ptr::copy_nonoverlapping(&NETWORK, permuted.as_mut(), 1) };
+        preprocess::permute_l0(&mut permuted, &permute_config());
+        let permuted = *PERMUTED_EMBEDDED_NETWORK.get_or_init(|| Box::leak(permuted));
+        set_active_network(Some(permuted));
+    }
+}
+
+/// Load a network from a file and make it active for the duration of `f`, then switch back to the
+/// network which was active before. Must not be called while a search is running.
+pub fn with_network<T>(path: &str, f: impl FnOnce() -> T) -> Result<T, String> {
+    let previous = active_network();
+    let result = load_network(path).map(|()| f());
+    set_active_network(previous);
+    result
+}
+
+/// The L0 permutation matching the SIMD backend in use.
+fn permute_config() -> PermuteConfig {
+    match simd_backend() {
+        SimdBackend::Avx512 => preprocess::permute_config_for(preprocess::AVX512_ORDER),
+        SimdBackend::Avx2 => preprocess::permute_config_for(preprocess::AVX2_ORDER),
+        _ => preprocess::permute_config_for(&[]),
+    }
+}
+
 pub struct NNUE {
     pub stack: Box<[Accumulator; MAX_ACCUMULATORS]>,
     pub cache: InputBucketCache,
     pub current: usize,
+    /// The network loaded in place of the embedded one, taken when the accumulators are activated.
+    network: Option<&'static Network>,
 }
 
 impl Default for NNUE {
@@ -67,6 +199,7 @@ impl Default for NNUE {
             current: 0,
             cache: InputBucketCache::default(),
             stack: unsafe { boxed_and_zeroed() },
+            network: None,
         }
     }
 }
@@ -76,9 +209,32 @@ impl NNUE {
     /// with the pre-activations of L0 stored in the current accumulator. We activate L0 and propagate
     /// through L1, L2, and L3 to get the final output.
     pub fn evaluate(&mut self, board: &Board) -> i32 {
+        let output = self.evaluate_unscaled(board);
+        scale_evaluation(board, output)
+    }
+
+    /// The network output, before it is scaled by the material phase and the half-move clock.
+    pub fn evaluate_unscaled(&mut self, board: &Board) -> i32 {
+        match self.network {
+            Some(network) => self.forward_loaded(board, network),
+            None => self.forward(board, Embedded),
+        }
+    }
+
+    /// The forward pass through a loaded network, kept out of line so that it does not weigh on the
+    /// forward pass through the embedded network.
+    #[inline(never)]
+    fn forward_loaded(&mut self, board: &Board, network: &'static Network) -> i32 {
+        self.forward(board, network)
+    }
+
+    #[inline(always)]
+    fn forward(&mut self, board: &Board, source: impl NetworkSource) -> i32 {
+        let network = source.get();
+
         // Apply any pending updates to the PSQ and threat accumulators.
-        psq::apply_lazy_updates(self, board);
-        threat::apply_lazy_updates(self, board);
+        psq::apply_lazy_updates(self, board, source);
+        threat::apply_lazy_updates(self, board, source);
 
         // Arrange the features of both accumulators in (stm, nstm) order.
         let acc = &self.stack[self.current];
@@ -95,27 +251,28 @@ impl NNUE {
             inference::activate_l0(psq_us, threat_us, psq_them, threat_them, &mut l0_outputs);
             #[cfg(feature = "track_l0_activations")]
             sparse::track_activations(&l0_outputs);
-            inference::propagate_l1(&l0_outputs, output_bucket, &mut l1_outputs);
-            inference::propagate_l2(&l1_outputs, output_bucket, &mut l2_outputs);
-            inference::propagate_l3(&l2_outputs, output_bucket)
+            inference::propagate_l1(network, &l0_outputs, output_bucket, &mut l1_outputs);
+            inference::propagate_l2(network, &l1_outputs, output_bucket, &mut l2_outputs);
+            inference::propagate_l3(network, &l2_outputs, output_bucket)
         };
 
-        // Scale the eval and return
-        let output = raw as i64 * SCALE / (Q * Q * Q * Q);
-        scale_evaluation(board, output as i32)
+        (raw as i64 * SCALE / (Q * Q * Q * Q)) as i32
     }
 
     /// Activate the entire board from scratch. This initializes the accumulators based on the
     /// current board state, iterating over all pieces and their squares. Should be called only
-    /// at the top of search, and then efficiently updated with each move.
+    /// at the top of search, and then efficiently updated with each move. The active network is
+    /// picked up here, and used until the next activation.
     pub fn activate(&mut self, board: &Board) {
+        self.network = active_network();
+        let network = self.network.unwrap_or(&NETWORK);
         self.current = 0;
-        self.cache = InputBucketCache::default();
+        self.cache = InputBucketCache::new(network);
 
         let mut acc = Accumulator::default();
         for side in [White, Black] {
-            acc.psq.refresh(board, side, &mut self.cache);
-            acc.threat.refresh(board, side);
+            acc.psq.refresh(board, side, &mut self.cache, network);
+            acc.threat.refresh(board, side, network);
         }
         self.stack[self.current] = acc;
     }
@@ -198,7 +355,8 @@ fn should_mirror(king_sq: Square) -> bool {
 #[inline]
 fn scale_evaluation(board: &Board, eval: i32) -> i32 {
     let phase = material_phase(board);
-    eval * (material_scaling_base() + phase) / 32768 * (200 - board.hm as i32) / 200
+    let hm_base = hm_scaling_base();
+    eval * (material_scaling_base() + phase) / 32768 * (hm_base - board.hm as i32) / hm_base
 }
 
 #[inline]
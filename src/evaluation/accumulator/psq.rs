@@ -4,9 +4,10 @@ use crate::board::side::Side::{Black, White};
 use crate::board::Board;
 use crate::evaluation::cache::InputBucketCache;
 use crate::evaluation::feature::psq::PieceSquareFeature;
-use crate::evaluation::{king_bucket, should_mirror, simd, NETWORK, NNUE};
+use crate::evaluation::kernels::update_features;
+use crate::evaluation::{king_bucket, should_mirror, NetworkSource, NNUE};
 use arrayvec::ArrayVec;
-use hobbes_nnue_arch::{PieceSquareWeights, L1_SIZE};
+use hobbes_nnue_arch::{Network, PieceSquareWeights, L1_SIZE};
 
 /// The `PieceSquareAccumulator` holds the pre-activations of the first layer of the neural network.
 /// The input layer just encodes the positions of pieces on the board, from the perspective of both
@@ -26,7 +27,7 @@ pub struct PieceSquareAccumulator {
 impl Default for PieceSquareAccumulator {
     fn default() -> Self {
         PieceSquareAccumulator {
-            features: [NETWORK.l0_biases, NETWORK.l0_biases],
+            features: [[0; L1_SIZE]; 2],
             adds: ArrayVec::new(),
             subs: ArrayVec::new(),
             computed: [false, false],
@@ -56,9 +57,9 @@ impl PieceSquareAccumulator {
 
     /// Reset the features for the given perspective to the initial biases.
     #[inline]
-    pub fn reset(&mut self, perspective: Side) {
+    pub fn reset(&mut self, perspective: Side, network: &Network) {
         let feats = &mut self.features[perspective];
-        *feats = NETWORK.l0_biases;
+        *feats = network.l0_biases;
     }
 
     /// Copy the features from another accumulator into this one, for the given perspective.
@@ -79,6 +80,7 @@ impl PieceSquareAccumulator {
         }
     }
 
+    #[inline(always)]
     pub fn apply_update_inplace(
         &mut self,
         prev: &[i16; L1_SIZE],
@@ -95,7 +97,13 @@ impl PieceSquareAccumulator {
     /// Refresh the accumulator for the given perspective, mirror state, and bucket. Retrieves
     /// the cached state for this accumulator, bucket, and perspective, and refreshes only the
     /// features of the board that have changed since the last refresh.
-    pub fn refresh(&mut self, board: &Board, side: Side, cache: &mut InputBucketCache) {
+    pub fn refresh(
+        &mut self,
+        board: &Board,
+        side: Side,
+        cache: &mut InputBucketCache,
+        source: impl NetworkSource,
+    ) {
         let king_sq = board.king_sq(side);
         let mirror = should_mirror(king_sq);
         let bucket = king_bucket(king_sq, side);
@@ -124,7 +132,7 @@ impl PieceSquareAccumulator {
             }
         }
 
-        let weights = &NETWORK.l0_psq_weights[bucket];
+        let weights = &source.get().l0_psq_weights[bucket];
         let mirror = self.mirrored[side as usize];
 
         // Fuse together updates to the accumulator for efficiency using iterators.
@@ -173,7 +181,7 @@ impl PieceSquareAccumulator {
 /// backwards to find the nearest computed accumulator, and move forward applying all updates
 /// one by one. If at any point we encounter an accumulator that requires a refresh - due to
 /// bucket or mirror change - we bail out and perform a full refresh instead.
-pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board) {
+pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board, source: impl NetworkSource) {
     for side in [White, Black] {
         // If already up-to-date for this perspective, then there is nothing to do.
         if nnue.stack[nnue.current].psq.computed[side] {
@@ -187,7 +195,7 @@ pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board) {
         // If the current accumulator requires a full refresh, skip lazy updates and do a refresh.
         if nnue.stack[nnue.current].psq.needs_refresh[side] {
             let acc = &mut nnue.stack[nnue.current].psq;
-            acc.refresh(board, side, &mut nnue.cache);
+            acc.refresh(board, side, &mut nnue.cache, source);
             continue;
         }
 
@@ -204,10 +212,10 @@ pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board) {
         if nnue.stack[curr].psq.needs_refresh[side] {
             // If we found an accumulator that requires a full refresh, do that instead.
             let acc = &mut nnue.stack[nnue.current].psq;
-            acc.refresh(board, side, &mut nnue.cache);
+            acc.refresh(board, side, &mut nnue.cache, source);
         } else {
             // Otherwise, move forward through the stack applying all updates one by one.
-            let weights = &NETWORK.l0_psq_weights[bucket];
+            let weights = &source.get().l0_psq_weights[bucket];
             while curr < nnue.current {
                 let (front, back) = nnue.stack.split_at_mut(curr + 1);
                 let prev_fts = front.last().unwrap().psq.features(side);
@@ -222,6 +230,7 @@ pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board) {
 
 /// Apply the given update to the accumulator, modifying the output features in place.
 #[rustfmt::skip]
+#[inline(always)]
 pub fn apply_update(
     input: &[i16; L1_SIZE],
     output: &mut [i16; L1_SIZE],
@@ -269,7 +278,7 @@ pub fn sub1(
     unsafe { update_features::<0, 1>(in_ptr, out_ptr, [], [ws]) };
 }
 
-#[inline]
+#[inline(always)]
 pub fn add1_sub1(
     input_features: &[i16; L1_SIZE],
     output_features: &mut [i16; L1_SIZE],
@@ -286,7 +295,7 @@ pub fn add1_sub1(
     unsafe { update_features::<1, 1>(in_ptr, out_ptr, [wa], [ws]) };
 }
 
-#[inline]
+#[inline(always)]
 #[allow(clippy::too_many_arguments)]
 pub fn add1_sub2(
     input_features: &[i16; L1_SIZE],
@@ -306,7 +315,7 @@ pub fn add1_sub2(
     unsafe { update_features::<1, 2>(in_ptr, out_ptr, [wa], [ws1, ws2]) };
 }
 
-#[inline]
+#[inline(always)]
 #[allow(clippy::too_many_arguments)]
 pub fn add2_sub2(
     input_features: &[i16; L1_SIZE],
@@ -364,30 +373,6 @@ pub fn sub4(
     unsafe { update_features::<0, 4>(in_ptr, out_ptr, [], [ws1, ws2, ws3, ws4]) };
 }
 
-#[inline(always)]
-pub unsafe fn update_features<const ADDS: usize, const SUBS: usize>(
-    input: *const i16,
-    output: *mut i16,
-    adds: [*const i16; ADDS],
-    subs: [*const i16; SUBS],
-) {
-    let mut i = 0;
-    while i + 4 * simd::I16_LANES <= L1_SIZE {
-        for k in 0..4 {
-            let off = i + k * simd::I16_LANES;
-            let mut val = simd::load_i16(input.add(off));
-            for &a in adds.iter() {
-                val = simd::add_i16(val, simd::load_i16(a.add(off)));
-            }
-            for &s in subs.iter() {
-                val = simd::sub_i16(val, simd::load_i16(s.add(off)));
-            }
-            simd::store_i16(output.add(off), val);
-        }
-        i += 4 * simd::I16_LANES;
-    }
-}
-
 #[inline(always)]
 pub fn weight_ptr(
     weights: &PieceSquareWeights,
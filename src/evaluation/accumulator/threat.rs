@@ -6,21 +6,14 @@ use crate::board::side::Side::{Black, White};
 use crate::board::square::Square;
 use crate::board::{attacks, ray, Board};
 use crate::evaluation::feature::threat::ThreatFeature;
-use crate::evaluation::{simd, NETWORK, NNUE};
+use crate::evaluation::kernels::accumulate;
+use crate::evaluation::{NetworkSource, NNUE};
 use arrayvec::ArrayVec;
 use hobbes_nnue_arch::L1_SIZE;
 
 const MAX_DELTA_INDICES: usize = 80;
 const MAX_ACTIVE_INDICES: usize = 4096;
 
-#[cfg(target_feature = "avx512f")]
-const REGISTERS: usize = L1_SIZE / simd::I16_LANES;
-#[cfg(not(target_feature = "avx512f"))]
-const REGISTERS: usize = 8;
-
-const STEP: usize = REGISTERS * simd::I16_LANES;
-const _: () = assert!(L1_SIZE.is_multiple_of(STEP), "step must divide by the accumulator evenly");
-
 #[repr(C, align(64))]
 pub struct ThreatAccumulator {
     features: [[i16; L1_SIZE]; 2],
@@ -54,14 +47,20 @@ impl ThreatAccumulator {
         &mut self.features[perspective]
     }
 
-    pub fn refresh(&mut self, board: &Board, pov: Side) {
+    pub fn refresh(&mut self, board: &Board, pov: Side, source: impl NetworkSource) {
         let mut adds = ArrayVec::<u32, MAX_ACTIVE_INDICES>::new();
         Self::collect_threat_indices(board, pov, &mut adds);
-        unsafe { accumulate(&mut self.features[pov], None, &adds, &[]) };
+        unsafe { accumulate(source.get(), &mut self.features[pov], None, &adds, &[]) };
         self.computed[pov] = true;
     }
 
-    pub fn apply(&mut self, parent: &ThreatAccumulator, king_sq: Square, pov: Side) {
+    pub fn apply(
+        &mut self,
+        parent: &ThreatAccumulator,
+        king_sq: Square,
+        pov: Side,
+        source: impl NetworkSource,
+    ) {
         let mut adds = ArrayVec::<u32, MAX_DELTA_INDICES>::new();
         let mut subs = ArrayVec::<u32, MAX_DELTA_INDICES>::new();
 
@@ -79,6 +78,7 @@ impl ThreatAccumulator {
 
         unsafe {
             accumulate(
+                source.get(),
                 &mut self.features[pov],
                 Some(&parent.features[pov]),
                 &adds,
@@ -253,7 +253,7 @@ impl ThreatAccumulator {
     }
 }
 
-pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board) {
+pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board, source: impl NetworkSource) {
     for pov in [White, Black] {
         if nnue.stack[nnue.current].threat.computed[pov] {
             continue;
@@ -261,7 +261,7 @@ pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board) {
 
         if nnue.stack[nnue.current].threat.needs_refresh[pov] {
             let threat = &mut nnue.stack[nnue.current].threat;
-            threat.refresh(board, pov);
+            threat.refresh(board, pov, source);
             threat.needs_refresh[pov] = false;
             threat.computed[pov] = true;
             continue;
@@ -277,77 +277,9 @@ pub fn apply_lazy_updates(nnue: &mut NNUE, board: &Board) {
             let (parents, currents) = nnue.stack.split_at_mut(curr + 1);
             let parent = &parents[curr].threat;
             let child = &mut currents[0].threat;
-            child.apply(parent, king_sq, pov);
+            child.apply(parent, king_sq, pov, source);
             child.computed[pov] = true;
             curr += 1;
         }
     }
 }
-
-#[inline(always)]
-unsafe fn accumulate(
-    out: &mut [i16; L1_SIZE],
-    parent: Option<&[i16; L1_SIZE]>,
-    adds: &[u32],
-    subs: &[u32],
-) {
-    let weights = NETWORK.l0_threat_weights.as_ptr();
-    let out_ptr = out.as_mut_ptr();
-
-    for offset in (0..L1_SIZE).step_by(STEP) {
-        let mut regs = [simd::zero_i16(); REGISTERS];
-        if let Some(p) = parent {
-            let in_ptr = p.as_ptr();
-            for (i, reg) in regs.iter_mut().enumerate() {
-                *reg = simd::load_i16(in_ptr.add(offset + i * simd::I16_LANES));
-            }
-        }
-
-        let (mut added, mut subtracted) = (0, 0);
-
-        while added < adds.len() && subtracted < subs.len() {
-            let add_row = weights.add(adds[added] as usize * L1_SIZE + offset);
-            let sub_row = weights.add(subs[subtracted] as usize * L1_SIZE + offset);
-            for (i, reg) in regs.iter_mut().enumerate() {
-                let lane = i * simd::I16_LANES;
-                let add_w = simd::load_i8_as_i16(add_row.add(lane));
-                let sub_w = simd::load_i8_as_i16(sub_row.add(lane));
-                *reg = simd::add_i16(*reg, simd::sub_i16(add_w, sub_w));
-            }
-            added += 1;
-            subtracted += 1;
-        }
-
-        while added + 1 < adds.len() {
-            let row1 = weights.add(adds[added] as usize * L1_SIZE + offset);
-            let row2 = weights.add(adds[added + 1] as usize * L1_SIZE + offset);
-            for (i, reg) in regs.iter_mut().enumerate() {
-                let lane = i * simd::I16_LANES;
-                let w1 = simd::load_i8_as_i16(row1.add(lane));
-                let w2 = simd::load_i8_as_i16(row2.add(lane));
-                *reg = simd::add_i16(*reg, simd::add_i16(w1, w2));
-            }
-            added += 2;
-        }
-
-        while added < adds.len() {
-            let row = weights.add(adds[added] as usize * L1_SIZE + offset);
-            for (i, reg) in regs.iter_mut().enumerate() {
-                *reg = simd::add_i16(*reg, simd::load_i8_as_i16(row.add(i * simd::I16_LANES)));
-            }
-            added += 1;
-        }
-
-        while subtracted < subs.len() {
-            let row = weights.add(subs[subtracted] as usize * L1_SIZE + offset);
-            for (i, reg) in regs.iter_mut().enumerate() {
-                *reg = simd::sub_i16(*reg, simd::load_i8_as_i16(row.add(i * simd::I16_LANES)));
-            }
-            subtracted += 1;
-        }
-
-        for (i, reg) in regs.iter().enumerate() {
-            simd::store_i16(out_ptr.add(offset + i * simd::I16_LANES), *reg);
-        }
-    }
-}
@@ -1,7 +1,6 @@
 use crate::board::bitboard::Bitboard;
 use crate::board::side::Side;
-use crate::evaluation::NETWORK;
-use hobbes_nnue_arch::{INPUT_BUCKET_COUNT, L1_SIZE};
+use hobbes_nnue_arch::{Network, INPUT_BUCKET_COUNT, L1_SIZE};
 
 /// Whenever the king changes bucket, a costly full refresh of the accumulator is required. This
 /// service implements a technique to improve the performance of this refresh known as 'Finny tables'.
@@ -26,7 +25,7 @@ pub struct CacheEntry {
 impl Default for CacheEntry {
     fn default() -> Self {
         CacheEntry {
-            features: NETWORK.l0_biases,
+            features: [0; L1_SIZE],
             pieces: [Bitboard::empty(); 6],
             colours: [Bitboard::empty(); 2],
         }
@@ -34,6 +33,16 @@ impl Default for CacheEntry {
 }
 
 impl InputBucketCache {
+    /// An empty cache for the given network, where every entry holds the empty board and therefore
+    /// just the network's biases.
+    pub fn new(network: &Network) -> Self {
+        let mut cache = InputBucketCache::default();
+        for entry in cache.entries.iter_mut().flatten().flatten() {
+            entry.features = network.l0_biases;
+        }
+        cache
+    }
+
     pub fn get(&mut self, perspective: Side, mirror: bool, bucket: usize) -> &mut CacheEntry {
         &mut self.entries[perspective][mirror as usize][bucket]
     }
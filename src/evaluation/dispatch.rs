@@ -0,0 +1,155 @@
+//! Runtime SIMD dispatch for portable x86-64 builds. The SIMD kernels are compiled once for each
+//! backend, and each entry point forwards to the kernels of the backend detected at startup, or to
+//! the scalar kernels on CPUs without AVX2. The kernels are always inlined, so they are compiled
+//! with the target features of the entry point.
+
+use crate::evaluation::forward::Forward;
+use crate::tools::cpu::{simd_backend, SimdBackend};
+use hobbes_nnue_arch::{Network, L1_SIZE, L2_SIZE, L3_SIZE};
+
+mod avx512 {
+    pub(super) use crate::evaluation::simd::avx512 as simd;
+    pub mod kernels {
+        include!("kernels.rs");
+    }
+    pub mod vectorised {
+        include!("forward/vectorised.rs");
+    }
+    pub use vectorised::Vectorised as Inference;
+}
+
+mod avx2 {
+    pub(super) use crate::evaluation::simd::avx2 as simd;
+    pub mod kernels {
+        include!("kernels.rs");
+    }
+    pub mod vectorised {
+        include!("forward/vectorised.rs");
+    }
+    pub use vectorised::Vectorised as Inference;
+}
+
+mod scalar {
+    pub(super) use crate::evaluation::forward::scalar::Scalar as Inference;
+    pub(super) use crate::evaluation::scalar_kernels as kernels;
+}
+
+/// Generate an entry point for a kernel, which dispatches to the AVX-512, AVX2 or scalar version of
+/// the kernel depending on the backend detected at startup.
+macro_rules! dispatch {
+    (
+        $(#[$attr:meta])*
+        fn $name:ident$(<$(const $generic:ident: usize),*>)?($($arg:ident: $ty:ty),* $(,)?)
+        $(-> $ret:ty)? => $($kernel:ident)::+
+    ) => {
+        $(#[$attr])*
+        #[inline]
+        pub unsafe fn $name$(<$(const $generic: usize),*>)?($($arg: $ty),*) $(-> $ret)? {
+            #[target_feature(enable = "avx512f,avx512bw")]
+            unsafe fn with_avx512$(<$(const $generic: usize),*>)?($($arg: $ty),*) $(-> $ret)? {
+                avx512::$($kernel)::+$(::<$($generic),*>)?($($arg),*)
+            }
+
+            #[target_feature(enable = "avx2")]
+            unsafe fn with_avx2$(<$(const $generic: usize),*>)?($($arg: $ty),*) $(-> $ret)? {
+                avx2::$($kernel)::+$(::<$($generic),*>)?($($arg),*)
+            }
+
+            match simd_backend() {
+                SimdBackend::Avx512 => with_avx512$(::<$($generic),*>)?($($arg),*),
+                SimdBackend::Avx2 => with_avx2$(::<$($generic),*>)?($($arg),*),
+                SimdBackend::Scalar | SimdBackend::Neon => {
+                    scalar::$($kernel)::+$(::<$($generic),*>)?($($arg),*)
+                }
+            }
+        }
+    };
+}
+
+dispatch! {
+    fn update_features<const ADDS: usize, const SUBS: usize>(
+        input: *const i16,
+        output: *mut i16,
+        adds: [*const i16; ADDS],
+        subs: [*const i16; SUBS],
+    ) => kernels::update_features
+}
+
+dispatch! {
+    fn accumulate(
+        network: &Network,
+        out: &mut [i16; L1_SIZE],
+        parent: Option<&[i16; L1_SIZE]>,
+        adds: &[u32],
+        subs: &[u32],
+    ) => kernels::accumulate
+}
+
+dispatch! {
+    fn activate_l0(
+        us_psq: &[i16; L1_SIZE],
+        us_threat: &[i16; L1_SIZE],
+        them_psq: &[i16; L1_SIZE],
+        them_threat: &[i16; L1_SIZE],
+        output: &mut [u8; L1_SIZE],
+    ) => Inference::activate_l0
+}
+
+dispatch! {
+    fn propagate_l1(
+        network: &Network,
+        input: &[u8; L1_SIZE],
+        output_bucket: usize,
+        output: &mut [i32; L2_SIZE * 2],
+    ) => Inference::propagate_l1
+}
+
+dispatch! {
+    fn propagate_l2(
+        network: &Network,
+        input: &[i32; L2_SIZE * 2],
+        output_bucket: usize,
+        output: &mut [i32; L3_SIZE],
+    ) => Inference::propagate_l2
+}
+
+dispatch! {
+    fn propagate_l3(network: &Network, input: &[i32; L3_SIZE], output_bucket: usize) -> i32
+        => Inference::propagate_l3
+}
+
+pub struct Dispatch;
+
+impl Forward for Dispatch {
+    unsafe fn activate_l0(
+        us_psq: &[i16; L1_SIZE],
+        us_threat: &[i16; L1_SIZE],
+        them_psq: &[i16; L1_SIZE],
+        them_threat: &[i16; L1_SIZE],
+        output: &mut [u8; L1_SIZE],
+    ) {
+        activate_l0(us_psq, us_threat, them_psq, them_threat, output)
+    }
+
+    unsafe fn propagate_l1(
+        network: &Network,
+        input: &[u8; L1_SIZE],
+        output_bucket: usize,
+        output: &mut [i32; L2_SIZE * 2],
+    ) {
+        propagate_l1(network, input, output_bucket, output)
+    }
+
+    unsafe fn propagate_l2(
+        network: &Network,
+        input: &[i32; L2_SIZE * 2],
+        output_bucket: usize,
+        output: &mut [i32; L3_SIZE],
+    ) {
+        propagate_l2(network, input, output_bucket, output)
+    }
+
+    unsafe fn propagate_l3(network: &Network, input: &[i32; L3_SIZE], output_bucket: usize) -> i32 {
+        propagate_l3(network, input, output_bucket)
+    }
+}
@@ -1,8 +1,9 @@
-use hobbes_nnue_arch::{L1_SIZE, L2_SIZE, L3_SIZE};
+use hobbes_nnue_arch::{Network, L1_SIZE, L2_SIZE, L3_SIZE};
 
 /// Trait grouping for four forward pass functions in Hobbes' multilayer NNUE inference. There
 /// is both a `Vectorised` and `Scalar` implementation of this trait; the appropriate one is
-/// selected at compile time.
+/// selected at compile time, or at runtime by the `Dispatch` implementation in portable builds. The
+/// layers read their weights from the given network.
 pub trait Forward {
     unsafe fn activate_l0(
         us_psq: &[i16; L1_SIZE],
@@ -13,26 +14,35 @@ pub trait Forward {
     );
 
     unsafe fn propagate_l1(
+        network: &Network,
         input: &[u8; L1_SIZE],
         output_bucket: usize,
         output: &mut [i32; L2_SIZE * 2],
     );
 
     unsafe fn propagate_l2(
+        network: &Network,
         input: &[i32; L2_SIZE * 2],
         output_bucket: usize,
         output: &mut [i32; L3_SIZE],
     );
 
-    unsafe fn propagate_l3(input: &[i32; L3_SIZE], output_bucket: usize) -> i32;
+    unsafe fn propagate_l3(network: &Network, input: &[i32; L3_SIZE], output_bucket: usize) -> i32;
 }
 
+#[cfg(any(target_feature = "avx2", target_feature = "neon"))]
+use crate::evaluation::{kernels, simd};
 #[cfg(any(target_feature = "avx2", target_feature = "neon"))]
 mod vectorised;
 #[cfg(any(target_feature = "avx2", target_feature = "neon"))]
 pub use vectorised::Vectorised as inference;
 
+// When the SIMD backend is selected at runtime, `vectorised` is compiled once per backend inside
+// the dispatcher instead.
+#[cfg(simd_dispatch)]
+pub use crate::evaluation::dispatch::Dispatch as inference;
+
 #[cfg(not(any(target_feature = "avx2", target_feature = "neon")))]
-mod scalar;
-#[cfg(not(any(target_feature = "avx2", target_feature = "neon")))]
+pub mod scalar;
+#[cfg(not(any(target_feature = "avx2", target_feature = "neon", simd_dispatch)))]
 pub use scalar::Scalar as inference;
@@ -1,114 +1,109 @@
-// use crate::evaluation::forward::Forward;
-// use crate::evaluation::NETWORK;
-// use hobbes_nnue_arch::{L0_QUANT, L0_SHIFT, L1_SHIFT, L1_SIZE, L2_SIZE, L3_SIZE, Q, Q_BITS};
-//
-// pub struct Scalar;
-//
-// impl Forward for Scalar {
-//     /// L0 ('feature transformer') activation
-//     /// We are in [0, 255] space, we want to end up in [0, 127] space for the next layer.
-//     #[inline(always)]
-//     unsafe fn activate_l0(us: &[i16; L1_SIZE], them: &[i16; L1_SIZE], output: &mut [u8; L1_SIZE]) {
-//         for (side, feats) in [us, them].into_iter().enumerate() {
-//             let base = side * (L1_SIZE / 2);
-//             for i in 0..(L1_SIZE / 2) {
-//                 // Load the pair of inputs to be multiplied.
-//                 let left: i16 = feats[i];
-//                 let right: i16 = feats[i + (L1_SIZE / 2)];
-//
-//                 // Clipped ReLU activation, in [0, 255] space.
-//                 let l_clamped: u8 = left.clamp(0, L0_QUANT as i16) as u8;
-//                 let r_clamped: u8 = right.clamp(0, L0_QUANT as i16) as u8;
-//
-//                 // Pairwise multiplication of left and right input.
-//                 let multiplied: i32 = l_clamped as i32 * r_clamped as i32;
-//
-//                 // Downshift back into [0, 127] space.
-//                 // Note: this is equivalent to the << 7 >> 16 that mulhi does.
-//                 let result: u8 = (multiplied >> L0_SHIFT).clamp(0, 255) as u8;
-//                 output[base + i] = result;
-//             }
-//         }
-//     }
-//
-//     /// L1 propagation
-//     #[inline(always)]
-//     unsafe fn propagate_l1(
-//         input: &[u8; L1_SIZE],
-//         output_bucket: usize,
-//         output: &mut [i32; L2_SIZE * 2],
-//     ) {
-//         let weights = &NETWORK.l1_weights[output_bucket];
-//         let biases = &NETWORK.l1_biases[output_bucket];
-//
-//         // Unactivated L1 outputs in the quantized space L0_QUANT * L1_QUANT
-//         let mut intermediate: [i32; L2_SIZE] = [0; L2_SIZE];
-//
-//         // L1 matrix multiplication
-//         for output_idx in 0..L2_SIZE {
-//             for input_idx in 0..L1_SIZE {
-//                 let in_block = input_idx / 4;
-//                 let k = input_idx % 4;
-//                 let weight: i32 = weights[in_block][output_idx * 4 + k] as i32;
-//                 intermediate[output_idx] += input[input_idx] as i32 * weight;
-//             }
-//         }
-//
-//         // Re-quantise, add biases and activate L1 outputs
-//         for i in 0..L2_SIZE {
-//             let bias: i32 = biases[i];
-//             let mut out: i32 = intermediate[i];
-//
-//             // Down-shift into L1 Q space
-//             out >>= L1_SHIFT;
-//
-//             // Add the bias
-//             out += bias;
-//
-//             // crelu activation: clamp(x, 0, Q)
-//             let crelu: i32 = out.clamp(0, Q as i32) << Q_BITS;
-//
-//             // csrelu activation: clamp(x^2, 0, Q^2)
-//             let csrelu: i32 = (out * out).clamp(0, (Q * Q) as i32);
-//
-//             output[i] = crelu;
-//             output[i + L2_SIZE] = csrelu;
-//         }
-//     }
-//
-//     /// L2 propagation
-//     #[inline(always)]
-//     unsafe fn propagate_l2(
-//         input: &[i32; L2_SIZE * 2],
-//         output_bucket: usize,
-//         output: &mut [i32; L3_SIZE],
-//     ) {
-//         let weights = &NETWORK.l2_weights[output_bucket];
-//
-//         let mut out = NETWORK.l2_biases[output_bucket];
-//         for input_idx in 0..(L2_SIZE * 2) {
-//             let input_val = input[input_idx];
-//             for output_idx in 0..L3_SIZE {
-//                 let weight = weights[input_idx][output_idx];
-//                 // This multiplication moves us into [0, Q^3] space
-//                 out[output_idx] += input_val * weight;
-//             }
-//         }
-//         *output = out;
-//     }
-//
-//     /// L3 propagation
-//     #[inline(always)]
-//     unsafe fn propagate_l3(input: &[i32; L3_SIZE], output_bucket: usize) -> i32 {
-//         let weights = &NETWORK.l3_weights[output_bucket];
-//         let bias = NETWORK.l3_biases[output_bucket];
-//
-//         let mut output: i32 = bias;
-//         for (&input, &weight) in input.iter().zip(weights.iter()) {
-//             let clamped = input.clamp(0, (Q * Q * Q) as i32);
-//             // This multiplication moves us into [0, Q^4] space
-//             output += clamped * weight;
-//         }
-//         output
-//     }
-// }
+use crate::evaluation::forward::Forward;
+use hobbes_nnue_arch::{
+    Network, L0_QUANT, L0_SHIFT, L1_SHIFT, L1_SIZE, L2_SIZE, L3_SIZE, Q, Q_BITS,
+};
+
+/// The forward pass without SIMD, for CPUs which support neither AVX2 nor AVX-512. It expects the
+/// network with its L0 weights unpermuted, and computes the same outputs as `Vectorised`, wrapping
+/// on overflow where the SIMD instructions do.
+pub struct Scalar;
+
+impl Forward for Scalar {
+    /// L0 ('feature transformer') activation
+    /// We are in [0, 255] space, we want to end up in [0, 127] space for the next layer.
+    #[inline(always)]
+    unsafe fn activate_l0(
+        us_psq: &[i16; L1_SIZE],
+        us_threat: &[i16; L1_SIZE],
+        them_psq: &[i16; L1_SIZE],
+        them_threat: &[i16; L1_SIZE],
+        output: &mut [u8; L1_SIZE],
+    ) {
+        for (side, (psq_feats, threat_feats)) in [(us_psq, us_threat), (them_psq, them_threat)]
+            .into_iter()
+            .enumerate()
+        {
+            let base = side * (L1_SIZE / 2);
+            for i in 0..L1_SIZE / 2 {
+                let j = i + L1_SIZE / 2;
+                let left = psq_feats[i]
+                    .wrapping_add(threat_feats[i])
+                    .clamp(0, L0_QUANT as i16);
+                let right = psq_feats[j]
+                    .wrapping_add(threat_feats[j])
+                    .clamp(0, L0_QUANT as i16);
+
+                // Pairwise multiplication, and downshift back into [0, 127] space.
+                let product = (left as i32 * right as i32) >> L0_SHIFT;
+                output[base + i] = product.clamp(0, 255) as u8;
+            }
+        }
+    }
+
+    /// L1 propagation
+    #[inline(always)]
+    unsafe fn propagate_l1(
+        network: &Network,
+        input: &[u8; L1_SIZE],
+        output_bucket: usize,
+        output: &mut [i32; L2_SIZE * 2],
+    ) {
+        let weights = &network.l1_weights[output_bucket];
+        let biases = &network.l1_biases[output_bucket];
+
+        // The weights are grouped in blocks of four inputs, as the SIMD kernels consume them.
+        let mut sums = [0i32; L2_SIZE];
+        for (block, block_weights) in weights.iter().enumerate() {
+            let inputs = &input[block * 4..block * 4 + 4];
+            if inputs.iter().all(|&x| x == 0) {
+                continue;
+            }
+            for (sum, row) in sums.iter_mut().zip(block_weights.chunks_exact(4)) {
+                for (&x, &w) in inputs.iter().zip(row) {
+                    *sum = sum.wrapping_add(x as i32 * w as i32);
+                }
+            }
+        }
+
+        for (i, (&sum, &bias)) in sums.iter().zip(biases.iter()).enumerate() {
+            let out = (sum >> L1_SHIFT).wrapping_add(bias);
+            output[i] = out.clamp(0, Q as i32) << Q_BITS;
+            output[i + L2_SIZE] = out.wrapping_mul(out).clamp(0, (Q * Q) as i32);
+        }
+    }
+
+    /// L2 propagation
+    #[inline(always)]
+    unsafe fn propagate_l2(
+        network: &Network,
+        input: &[i32; L2_SIZE * 2],
+        output_bucket: usize,
+        output: &mut [i32; L3_SIZE],
+    ) {
+        let weights = &network.l2_weights[output_bucket];
+
+        let mut out = network.l2_biases[output_bucket];
+        for (&input_val, row) in input.iter().zip(weights.iter()) {
+            for (out, &weight) in out.iter_mut().zip(row.iter()) {
+                *out = out.wrapping_add(input_val.wrapping_mul(weight));
+            }
+        }
+        *output = out;
+    }
+
+    /// L3 propagation
+    #[inline(always)]
+    unsafe fn propagate_l3(network: &Network, input: &[i32; L3_SIZE], output_bucket: usize) -> i32 {
+        let weights = &network.l3_weights[output_bucket];
+        let bias = network.l3_biases[output_bucket];
+
+        let sum = input
+            .iter()
+            .zip(weights.iter())
+            .fold(0i32, |sum, (&input, &weight)| {
+                let clamped = input.clamp(0, (Q * Q * Q) as i32);
+                sum.wrapping_add(clamped.wrapping_mul(weight))
+            });
+        sum.wrapping_add(bias)
+    }
+}
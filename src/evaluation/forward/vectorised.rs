@@ -1,12 +1,13 @@
+use super::{kernels, simd};
 use crate::evaluation::forward::Forward;
-use crate::evaluation::{simd, sparse, NETWORK};
-use hobbes_nnue_arch::{L0_QUANT, L1_SHIFT, L1_SIZE, L2_SIZE, L3_SIZE, Q, Q_BITS};
+use hobbes_nnue_arch::{Network, L0_QUANT, L1_SHIFT, L1_SIZE, L2_SIZE, L3_SIZE, Q, Q_BITS};
 
 pub struct Vectorised;
 
 impl Forward for Vectorised {
     /// L0 ('feature transformer') activation
     /// We are in [0, 255] space, we want to end up in [0, 127] space for the next layer.
+    #[inline(always)]
     unsafe fn activate_l0(
         us_psq: &[i16; L1_SIZE],
         us_threat: &[i16; L1_SIZE],
@@ -53,7 +54,9 @@ impl Forward for Vectorised {
     }
 
     /// L1 propagation
+    #[inline(always)]
     unsafe fn propagate_l1(
+        network: &Network,
         input: &[u8; L1_SIZE],
         output_bucket: usize,
         output: &mut [i32; L2_SIZE * 2],
@@ -65,9 +68,9 @@ impl Forward for Vectorised {
         let zero = simd::splat_i32(0);
         let mut acc = [[zero; UNROLL]; ACC_LANES];
 
-        let (nonzero_indices, num_nonzero_indices) = sparse::find_nonzero_indices(input);
+        let (nonzero_indices, num_nonzero_indices) = kernels::find_nonzero_indices(input);
         let input_i32 = input.as_ptr() as *const i32;
-        let w_base = NETWORK.l1_weights[output_bucket].as_ptr() as *const i8;
+        let w_base = network.l1_weights[output_bucket].as_ptr() as *const i8;
 
         let mut nnz = 0;
         while nnz + 2 * UNROLL <= num_nonzero_indices {
@@ -97,7 +100,7 @@ impl Forward for Vectorised {
             nnz += 1;
         }
 
-        let bias_ptr = NETWORK.l1_biases[output_bucket].as_ptr() as *const simd::VecI32;
+        let bias_ptr = network.l1_biases[output_bucket].as_ptr() as *const simd::VecI32;
         let lo = simd::splat_i32(0);
         let hi = simd::splat_i32(Q as i32);
         let hi2 = simd::splat_i32((Q * Q) as i32);
@@ -121,14 +124,16 @@ impl Forward for Vectorised {
     }
 
     /// L2 propagation
+    #[inline(always)]
     unsafe fn propagate_l2(
+        network: &Network,
         input: &[i32; L2_SIZE * 2],
         output_bucket: usize,
         output: &mut [i32; L3_SIZE],
     ) {
         const LANES: usize = L3_SIZE / simd::I32_LANES;
-        let weights = &NETWORK.l2_weights[output_bucket];
-        let biases = &NETWORK.l2_biases[output_bucket];
+        let weights = &network.l2_weights[output_bucket];
+        let biases = &network.l2_biases[output_bucket];
 
         let mut acc = [simd::splat_i32(0); LANES];
         for (lane, acc_lane) in acc.iter_mut().enumerate() {
@@ -165,11 +170,12 @@ impl Forward for Vectorised {
     }
 
     /// L3 propagation
-    unsafe fn propagate_l3(input: &[i32; L3_SIZE], output_bucket: usize) -> i32 {
+    #[inline(always)]
+    unsafe fn propagate_l3(network: &Network, input: &[i32; L3_SIZE], output_bucket: usize) -> i32 {
         const LANES: usize = L3_SIZE / simd::I32_LANES;
 
-        let weights = NETWORK.l3_weights[output_bucket].as_ptr();
-        let bias = NETWORK.l3_biases[output_bucket];
+        let weights = network.l3_weights[output_bucket].as_ptr();
+        let bias = network.l3_biases[output_bucket];
         let lo = simd::splat_i32(0);
         let hi = simd::splat_i32((Q * Q * Q) as i32);
 
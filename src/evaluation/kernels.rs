@@ -0,0 +1,144 @@
+use super::simd;
+use crate::evaluation::sparse::NNZ_TABLE;
+use hobbes_nnue_arch::{Network, L1_SIZE};
+
+/// The number of registers used to accumulate threat features. With AVX-512 the whole accumulator
+/// fits into registers; otherwise it is processed in chunks of 8 registers.
+const REGISTERS: usize = if simd::I16_LANES >= 32 {
+    L1_SIZE / simd::I16_LANES
+} else {
+    8
+};
+
+const STEP: usize = REGISTERS * simd::I16_LANES;
+const _: () = assert!(L1_SIZE.is_multiple_of(STEP), "step must divide by the accumulator evenly");
+
+#[inline(always)]
+pub unsafe fn update_features<const ADDS: usize, const SUBS: usize>(
+    input: *const i16,
+    output: *mut i16,
+    adds: [*const i16; ADDS],
+    subs: [*const i16; SUBS],
+) {
+    let mut i = 0;
+    while i + 4 * simd::I16_LANES <= L1_SIZE {
+        for k in 0..4 {
+            let off = i + k * simd::I16_LANES;
+            let mut val = simd::load_i16(input.add(off));
+            for &a in adds.iter() {
+                val = simd::add_i16(val, simd::load_i16(a.add(off)));
+            }
+            for &s in subs.iter() {
+                val = simd::sub_i16(val, simd::load_i16(s.add(off)));
+            }
+            simd::store_i16(output.add(off), val);
+        }
+        i += 4 * simd::I16_LANES;
+    }
+}
+
+#[inline(always)]
+pub unsafe fn accumulate(
+    network: &Network,
+    out: &mut [i16; L1_SIZE],
+    parent: Option<&[i16; L1_SIZE]>,
+    adds: &[u32],
+    subs: &[u32],
+) {
+    let weights = network.l0_threat_weights.as_ptr();
+    let out_ptr = out.as_mut_ptr();
+
+    for offset in (0..L1_SIZE).step_by(STEP) {
+        let mut regs = [simd::zero_i16(); REGISTERS];
+        if let Some(p) = parent {
+            let in_ptr = p.as_ptr();
+            for (i, reg) in regs.iter_mut().enumerate() {
+                *reg = simd::load_i16(in_ptr.add(offset + i * simd::I16_LANES));
+            }
+        }
+
+        let (mut added, mut subtracted) = (0, 0);
+
+        while added < adds.len() && subtracted < subs.len() {
+            let add_row = weights.add(adds[added] as usize * L1_SIZE + offset);
+            let sub_row = weights.add(subs[subtracted] as usize * L1_SIZE + offset);
+            for (i, reg) in regs.iter_mut().enumerate() {
+                let lane = i * simd::I16_LANES;
+                let add_w = simd::load_i8_as_i16(add_row.add(lane));
+                let sub_w = simd::load_i8_as_i16(sub_row.add(lane));
+                *reg = simd::add_i16(*reg, simd::sub_i16(add_w, sub_w));
+            }
+            added += 1;
+            subtracted += 1;
+        }
+
+        while added + 1 < adds.len() {
+            let row1 = weights.add(adds[added] as usize * L1_SIZE + offset);
+            let row2 = weights.add(adds[added + 1] as usize * L1_SIZE + offset);
+            for (i, reg) in regs.iter_mut().enumerate() {
+                let lane = i * simd::I16_LANES;
+                let w1 = simd::load_i8_as_i16(row1.add(lane));
+                let w2 = simd::load_i8_as_i16(row2.add(lane));
+                *reg = simd::add_i16(*reg, simd::add_i16(w1, w2));
+            }
+            added += 2;
+        }
+
+        while added < adds.len() {
+            let row = weights.add(adds[added] as usize * L1_SIZE + offset);
+            for (i, reg) in regs.iter_mut().enumerate() {
+                *reg = simd::add_i16(*reg, simd::load_i8_as_i16(row.add(i * simd::I16_LANES)));
+            }
+            added += 1;
+        }
+
+        while subtracted < subs.len() {
+            let row = weights.add(subs[subtracted] as usize * L1_SIZE + offset);
+            for (i, reg) in regs.iter_mut().enumerate() {
+                *reg = simd::sub_i16(*reg, simd::load_i8_as_i16(row.add(i * simd::I16_LANES)));
+            }
+            subtracted += 1;
+        }
+
+        for (i, reg) in regs.iter().enumerate() {
+            simd::store_i16(out_ptr.add(offset + i * simd::I16_LANES), *reg);
+        }
+    }
+}
+
+#[inline(always)]
+pub unsafe fn find_nonzero_indices(input: &[u8; L1_SIZE]) -> ([u16; L1_SIZE / 4], usize) {
+    const UNROLL: usize = if simd::I32_LANES < 8 {
+        8 / simd::I32_LANES
+    } else {
+        1
+    };
+    const NUM_CHUNKS: usize = UNROLL * simd::I32_LANES / 8;
+
+    let mut indices = [0u16; L1_SIZE / 4];
+    let indices_ptr = indices.as_mut_ptr();
+    let mut count = 0usize;
+    let mut base = simd::splat_u16(0);
+    let step = simd::splat_u16(8);
+
+    let mut i = 0;
+    while i < L1_SIZE {
+        let mut mask: u64 = 0;
+        for j in 0..UNROLL {
+            let nonzero_mask = simd::nonzero_mask_u8(input.as_ptr().add(i)) as u64;
+            mask |= nonzero_mask << (j * simd::I32_LANES);
+            i += simd::U8_LANES;
+        }
+
+        for chunk in 0..NUM_CHUNKS {
+            let byte = (mask >> (chunk * 8)) as u8;
+            let entry = &NNZ_TABLE.table[byte as usize];
+            let actual_indices = simd::add_u16(simd::load_u16(entry.indices.as_ptr()), base);
+            simd::store_u16(indices_ptr.add(count), actual_indices);
+            count += byte.count_ones() as usize;
+            base = simd::add_u16(base, step);
+        }
+    }
+
+    (indices, count)
+}
@@ -0,0 +1,47 @@
+use hobbes_nnue_arch::{Network, L1_SIZE};
+
+#[inline(always)]
+pub unsafe fn update_features<const ADDS: usize, const SUBS: usize>(
+    input: *const i16,
+    output: *mut i16,
+    adds: [*const i16; ADDS],
+    subs: [*const i16; SUBS],
+) {
+    for i in 0..L1_SIZE {
+        let mut val = *input.add(i);
+        for &a in adds.iter() {
+            val = val.wrapping_add(*a.add(i));
+        }
+        for &s in subs.iter() {
+            val = val.wrapping_sub(*s.add(i));
+        }
+        *output.add(i) = val;
+    }
+}
+
+#[inline(always)]
+pub unsafe fn accumulate(
+    network: &Network,
+    out: &mut [i16; L1_SIZE],
+    parent: Option<&[i16; L1_SIZE]>,
+    adds: &[u32],
+    subs: &[u32],
+) {
+    let weights = &network.l0_threat_weights;
+    let row = |feature: u32| {
+        let start = feature as usize * L1_SIZE;
+        &weights[start..start + L1_SIZE]
+    };
+
+    *out = parent.copied().unwrap_or([0; L1_SIZE]);
+    for &add in adds {
+        for (feat, &w) in out.iter_mut().zip(row(add)) {
+            *feat = feat.wrapping_add(w as i16);
+        }
+    }
+    for &sub in subs {
+        for (feat, &w) in out.iter_mut().zip(row(sub)) {
+            *feat = feat.wrapping_sub(w as i16);
+        }
+    }
+}
@@ -7,8 +7,6 @@ pub const I32_LANES: usize = size_of::<__m256i>() / size_of::<i32>();
 
 pub type VecI32 = __m256i;
 
-pub type VecI16 = __m256i;
-
 #[inline(always)]
 pub unsafe fn zero_i16() -> __m256i {
     _mm256_setzero_si256()
@@ -7,8 +7,6 @@ pub const I32_LANES: usize = size_of::<__m512i>() / size_of::<i32>();
 
 pub type VecI32 = __m512i;
 
-pub type VecI16 = __m512i;
-
 #[inline(always)]
 pub unsafe fn zero_i16() -> __m512i {
     _mm512_setzero_si512()
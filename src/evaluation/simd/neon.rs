@@ -7,8 +7,6 @@ pub const I32_LANES: usize = size_of::<int32x4_t>() / size_of::<i32>();
 
 pub type VecI32 = int32x4_t;
 
-pub type VecI16 = int16x8_t;
-
 #[inline(always)]
 pub unsafe fn zero_i16() -> int16x8_t {
     vdupq_n_s16(0)
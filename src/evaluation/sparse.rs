@@ -1,4 +1,3 @@
-use crate::evaluation::simd;
 use hobbes_nnue_arch::L1_SIZE;
 
 // Credit to Pawnocchio and Viridithas authors from whom this implementation was shamelessly yoinked.
@@ -6,7 +5,7 @@ use hobbes_nnue_arch::L1_SIZE;
 #[derive(Debug, Clone, Copy)]
 #[repr(C, align(16))]
 pub struct NNZEntry {
-    indices: [u16; 8],
+    pub indices: [u16; 8],
 }
 
 pub struct NNZTable {
@@ -30,43 +29,6 @@ pub const NNZ_TABLE: NNZTable = {
     NNZTable { table }
 };
 
-#[inline(always)]
-pub unsafe fn find_nonzero_indices(input: &[u8; L1_SIZE]) -> ([u16; L1_SIZE / 4], usize) {
-    const UNROLL: usize = if 8 / simd::I32_LANES > 1 {
-        8 / simd::I32_LANES
-    } else {
-        1
-    };
-    const NUM_CHUNKS: usize = UNROLL * simd::I32_LANES / 8;
-
-    let mut indices = [0u16; L1_SIZE / 4];
-    let indices_ptr = indices.as_mut_ptr();
-    let mut count = 0usize;
-    let mut base = simd::splat_u16(0);
-    let step = simd::splat_u16(8);
-
-    let mut i = 0;
-    while i < L1_SIZE {
-        let mut mask: u64 = 0;
-        for j in 0..UNROLL {
-            let nonzero_mask = simd::nonzero_mask_u8(input.as_ptr().add(i)) as u64;
-            mask |= nonzero_mask << (j * simd::I32_LANES);
-            i += simd::U8_LANES;
-        }
-
-        for chunk in 0..NUM_CHUNKS {
-            let byte = (mask >> (chunk * 8)) as u8;
-            let entry = &NNZ_TABLE.table[byte as usize];
-            let actual_indices = simd::add_u16(simd::load_u16(entry.indices.as_ptr()), base);
-            simd::store_u16(indices_ptr.add(count), actual_indices);
-            count += byte.count_ones() as usize;
-            base = simd::add_u16(base, step);
-        }
-    }
-
-    (indices, count)
-}
-
 #[cfg(feature = "track_l0_activations")]
 mod bench {
     use super::L1_SIZE;
@@ -1,70 +1,468 @@
+use crate::board::side::Side::White;
 use crate::board::Board;
+use crate::evaluation::{get_output_bucket, scale_evaluation, with_network};
 use crate::search::thread::ThreadData;
-use hobbes_nnue_arch::SCALE;
+use crate::tools::dataformat::{GameResult, TrainingPosition};
+use crate::tools::json::{build_info, Json};
+use hobbes_nnue_arch::{OUTPUT_BUCKET_COUNT, SCALE};
 use std::fs;
 use std::path::Path;
 
-// Implementation yoinked from Viridithas
-pub fn eval_stats(td: &mut ThreadData, input: &Path) {
-    let mut total = 0i128;
-    let mut count = 0i128;
-    let mut abs_total = 0i128;
-    let mut min = i32::MAX;
-    let mut max = i32::MIN;
-    let mut sq_total = 0i128;
-
-    let binding = fs::read_to_string(input).unwrap();
-    let lines = binding.lines();
-    let file_len = lines.clone().count();
-    for (i, line) in lines.into_iter().enumerate() {
-        let parts: Vec<&str> = line.split("[").collect();
-        let fen = parts[0].trim();
-        let board = Board::from_fen(fen).unwrap();
-        td.nnue.activate(&board);
-        if board.threats.contains(board.our_king_sq()) {
-            continue;
-        }
-        let eval = td.nnue.evaluate(&board);
-
-        count += 1;
-        total += i128::from(eval);
-        abs_total += i128::from(eval.abs());
-        sq_total += i128::from(eval) * i128::from(eval);
-        if eval < min {
-            min = eval;
-        }
-        if eval > max {
-            max = eval;
-        }
+/// The scale which maps evaluations to win probabilities in the loss, matching bullet's.
+pub(super) const EVAL_SCALE: f64 = 400.0;
+
+/// The calibration table splits evaluations into buckets of this width, covering the range
+/// `-CALIBRATION_LIMIT..CALIBRATION_LIMIT`, with the outermost buckets absorbing anything beyond.
+const CALIBRATION_WIDTH: i32 = 100;
+const CALIBRATION_LIMIT: i32 = 800;
+const CALIBRATION_BUCKETS: usize = (2 * CALIBRATION_LIMIT / CALIBRATION_WIDTH) as usize;
+
+pub struct StatsOptions {
+    /// A second network to evaluate the same positions with, for comparison.
+    pub compare: Option<String>,
+    /// The weight of the game result in the loss target, with the remainder given to the score
+    /// label. Only used for positions which have a score label.
+    pub wdl_lambda: f64,
+    /// Print a single JSON document in place of the human-readable output.
+    pub json: bool,
+}
+
+/// A position from the input file, with its labels from white's perspective.
+pub(crate) struct Sample {
+    pub board: Board,
+    pub result: Option<GameResult>,
+    pub score: Option<i32>,
+}
+
+/// Parse a line in one of the supported formats: `<fen>`, `<fen> [<result>]`, optionally followed
+/// by a score, or the `<fen> | <score> | <result>` text format used for training data.
+fn parse_sample(line: &str) -> Result<Sample, String> {
+    if line.contains('|') {
+        let pos = TrainingPosition::from_text(line)?;
+        return Ok(Sample {
+            board: pos.board,
+            result: Some(pos.result),
+            score: Some(pos.score as i32),
+        });
+    }
+    let Some((fen, label)) = line.split_once('[') else {
+        return Ok(Sample {
+            board: Board::from_fen(line.trim())?,
+            result: None,
+            score: None,
+        });
+    };
+    let (result, score) = label.split_once(']').unwrap_or((label, ""));
+    let result = GameResult::parse(result).ok_or(format!("invalid result '{}'", result))?;
+    let score = match score.trim() {
+        "" => None,
+        score => Some(
+            score
+                .parse()
+                .map_err(|_| format!("invalid score '{}'", score))?,
+        ),
+    };
+    Ok(Sample {
+        board: Board::from_fen(fen.trim())?,
+        result: Some(result),
+        score,
+    })
+}
+
+/// Read every non-empty line of the file as a sample.
+pub(crate) fn read_samples(input: &Path) -> Result<Vec<Sample>, String> {
+    let contents = fs::read_to_string(input)
+        .map_err(|e| format!("could not read {}: {}", input.display(), e))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            parse_sample(line).map_err(|e| format!("{} line {}: {}", input.display(), i + 1, e))
+        })
+        .collect()
+}
+
+/// Compute the target scaling factor to achieve the same absolute mean as the master network.
+fn target_scale(abs_mean: f64) -> f64 {
+    let master_abs_mean = 1233.83;
+    master_abs_mean / abs_mean * f64::from(SCALE as i32)
+}
+
+#[inline]
+pub(super) fn sigmoid(eval: f64) -> f64 {
+    1.0 / (1.0 + (-eval / EVAL_SCALE).exp())
+}
+
+#[derive(Clone, Copy, Default)]
+struct Calibration {
+    count: u64,
+    predicted: f64,
+    actual: f64,
+}
+
+#[derive(Clone, Copy, Default)]
+struct BucketStats {
+    count: u64,
+    total: i128,
+    abs_total: i128,
+    loss_total: f64,
+    loss_count: u64,
+}
+
+/// The evaluation of a position from the side to move's perspective: as used in search, and the
+/// network output before scaling, which is what the network was trained on.
+#[derive(Clone, Copy)]
+struct Eval {
+    scaled: i32,
+    raw: i32,
+}
+
+/// The statistics for one network over the whole file.
+struct NetStats {
+    count: i128,
+    total: i128,
+    abs_total: i128,
+    sq_total: i128,
+    min: i32,
+    max: i32,
+    loss_total: f64,
+    loss_count: u64,
+    calibration: [Calibration; CALIBRATION_BUCKETS],
+    buckets: [BucketStats; OUTPUT_BUCKET_COUNT],
+}
+
+impl NetStats {
+    fn new(samples: &[Sample], evals: &[Option<Eval>], wdl_lambda: f64) -> NetStats {
+        let mut stats = NetStats {
+            count: 0,
+            total: 0,
+            abs_total: 0,
+            sq_total: 0,
+            min: i32::MAX,
+            max: i32::MIN,
+            loss_total: 0.0,
+            loss_count: 0,
+            calibration: [Calibration::default(); CALIBRATION_BUCKETS],
+            buckets: [BucketStats::default(); OUTPUT_BUCKET_COUNT],
+        };
+        for (sample, eval) in samples.iter().zip(evals) {
+            let Some(Eval { scaled: eval, raw }) = *eval else {
+                continue;
+            };
+            stats.count += 1;
+            stats.total += i128::from(eval);
+            stats.abs_total += i128::from(eval.abs());
+            stats.sq_total += i128::from(eval) * i128::from(eval);
+            stats.min = stats.min.min(eval);
+            stats.max = stats.max.max(eval);
 
-        if i % 1024 == 0 {
-            print!("\rPROCESSED {:>10}/{}.", i + 1, file_len);
+            let bucket = &mut stats.buckets[get_output_bucket(&sample.board)];
+            bucket.count += 1;
+            bucket.total += i128::from(eval);
+            bucket.abs_total += i128::from(eval.abs());
+
+            let Some(result) = sample.result else {
+                continue;
+            };
+            // The loss and calibration use the network output before scaling, which is what the
+            // network was trained on. Evaluations are relative to the side to move, while the
+            // labels are from white's.
+            let stm = sample.board.stm;
+            let white_raw = if stm == White { raw } else { -raw };
+            let mut target = result.score(White);
+            if let Some(score) = sample.score {
+                target = wdl_lambda * target + (1.0 - wdl_lambda) * sigmoid(score as f64);
+            }
+            let loss = (sigmoid(white_raw as f64) - target).powi(2);
+            stats.loss_total += loss;
+            stats.loss_count += 1;
+            bucket.loss_total += loss;
+            bucket.loss_count += 1;
+
+            let clamped = raw.clamp(-CALIBRATION_LIMIT, CALIBRATION_LIMIT - 1);
+            let index = ((clamped + CALIBRATION_LIMIT) / CALIBRATION_WIDTH) as usize;
+            let calibration = &mut stats.calibration[index];
+            calibration.count += 1;
+            calibration.predicted += sigmoid(raw as f64);
+            calibration.actual += result.score(stm);
         }
+        stats
     }
 
-    println!("\rPROCESSED {file_len:>10}/{file_len}.");
+    fn loss(&self) -> Option<f64> {
+        (self.loss_count > 0).then(|| self.loss_total / self.loss_count as f64)
+    }
 
-    println!(" EVALUATION STATISTICS:");
+    #[expect(clippy::cast_precision_loss)]
+    fn to_json(&self) -> Json {
+        let count = self.count as f64;
+        let has_evals = self.count > 0;
+        let mean = self.total as f64 / count;
+        let calibration = self
+            .calibration
+            .iter()
+            .enumerate()
+            .filter(|(_, calibration)| calibration.count > 0)
+            .map(|(i, calibration)| {
+                let lower = -CALIBRATION_LIMIT + i as i32 * CALIBRATION_WIDTH;
+                let n = calibration.count as f64;
+                Json::object([
+                    ("min", (i > 0).then_some(lower).into()),
+                    (
+                        "max",
+                        (i < CALIBRATION_BUCKETS - 1)
+                            .then_some(lower + CALIBRATION_WIDTH)
+                            .into(),
+                    ),
+                    ("count", calibration.count.into()),
+                    ("predicted", (calibration.predicted / n).into()),
+                    ("actual", (calibration.actual / n).into()),
+                ])
+            })
+            .collect::<Vec<Json>>();
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, bucket)| bucket.count > 0)
+            .map(|(i, bucket)| {
+                let n = bucket.count as f64;
+                let loss =
+                    (bucket.loss_count > 0).then(|| bucket.loss_total / bucket.loss_count as f64);
+                Json::object([
+                    ("bucket", i.into()),
+                    ("count", bucket.count.into()),
+                    ("mean", (bucket.total as f64 / n).into()),
+                    ("abs_mean", (bucket.abs_total as f64 / n).into()),
+                    ("loss", loss.into()),
+                ])
+            })
+            .collect::<Vec<Json>>();
+        Json::object([
+            ("count", (self.count as u64).into()),
+            ("mean", has_evals.then_some(mean).into()),
+            (
+                "abs_mean",
+                has_evals.then(|| self.abs_total as f64 / count).into(),
+            ),
+            (
+                "stddev",
+                has_evals
+                    .then(|| ((self.sq_total as f64 / count) - mean * mean).sqrt())
+                    .into(),
+            ),
+            (
+                "target_scaling_factor",
+                has_evals
+                    .then(|| target_scale(self.abs_total as f64 / count))
+                    .into(),
+            ),
+            ("min", has_evals.then_some(self.min).into()),
+            ("max", has_evals.then_some(self.max).into()),
+            ("loss", self.loss().into()),
+            ("labelled", self.loss_count.into()),
+            ("calibration", calibration.into()),
+            ("output_buckets", buckets.into()),
+        ])
+    }
 
-    println!("    COUNT: {count:>7}");
     #[expect(clippy::cast_precision_loss)]
-    if count > 0 {
-        let mean = total as f64 / count as f64;
-        let abs_mean = abs_total as f64 / count as f64;
+    fn print(&self) {
+        println!(" EVALUATION STATISTICS:");
+
+        println!("    COUNT: {:>7}", self.count);
+        if self.count == 0 {
+            return;
+        }
+        let count = self.count as f64;
+        let mean = self.total as f64 / count;
+        let abs_mean = self.abs_total as f64 / count;
         let mean_squared = mean * mean;
-        let variance = (sq_total as f64 / count as f64) - mean_squared;
+        let variance = (self.sq_total as f64 / count) - mean_squared;
         let stddev = variance.sqrt();
-        let min = f64::from(min);
-        let max = f64::from(max);
+        let min = f64::from(self.min);
+        let max = f64::from(self.max);
         println!("     MEAN: {mean:>10.2}");
         println!(" ABS MEAN: {abs_mean:>10.2}");
         println!("   STDDEV: {stddev:>10.2}");
         println!("      MIN: {min:>10.2}");
         println!("      MAX: {max:>10.2}");
 
-        // Compute the target scaling factor to achieve the same absolute mean as the master network
-        let master_abs_mean = 1233.83;
-        let scale = master_abs_mean / abs_mean * f64::from(SCALE as i32);
+        let scale = target_scale(abs_mean);
         println!("  TARGET SCALING FACTOR: {scale:.6}");
+
+        println!(" OUTPUT BUCKETS (COUNT, MEAN, ABS MEAN, LOSS):");
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            if bucket.count == 0 {
+                continue;
+            }
+            let n = bucket.count as f64;
+            let loss = match bucket.loss_count {
+                0 => String::from("-"),
+                c => format!("{:.6}", bucket.loss_total / c as f64),
+            };
+            println!(
+                "  {i:>10}: {:>8} {:>10.2} {:>10.2} {loss:>10}",
+                bucket.count,
+                bucket.total as f64 / n,
+                bucket.abs_total as f64 / n
+            );
+        }
+
+        let Some(loss) = self.loss() else {
+            println!(" NO RESULT LABELS, SKIPPING LOSS AND CALIBRATION");
+            return;
+        };
+        println!(" VALIDATION LOSS: {loss:.6} ({} LABELLED)", self.loss_count);
+
+        println!(" CALIBRATION (STM RAW EVAL, PREDICTED, ACTUAL):");
+        for (i, calibration) in self.calibration.iter().enumerate() {
+            if calibration.count == 0 {
+                continue;
+            }
+            let lower = -CALIBRATION_LIMIT + i as i32 * CALIBRATION_WIDTH;
+            let range = match i {
+                0 => format!("< {}", lower + CALIBRATION_WIDTH),
+                _ if i == CALIBRATION_BUCKETS - 1 => format!(">= {}", lower),
+                _ => format!("{}..{}", lower, lower + CALIBRATION_WIDTH),
+            };
+            let n = calibration.count as f64;
+            println!(
+                "  {range:>10}: {:>8} {:>8.3} {:>8.3}",
+                calibration.count,
+                calibration.predicted / n,
+                calibration.actual / n
+            );
+        }
+    }
+}
+
+/// Evaluate every position, skipping those where the side to move is in check.
+fn evaluate_all(td: &mut ThreadData, samples: &[Sample], progress: bool) -> Vec<Option<Eval>> {
+    let mut evals = Vec::with_capacity(samples.len());
+    for (i, sample) in samples.iter().enumerate() {
+        let board = &sample.board;
+        if board.threats.contains(board.our_king_sq()) {
+            evals.push(None);
+            continue;
+        }
+        td.nnue.activate(board);
+        let raw = td.nnue.evaluate_unscaled(board);
+        let scaled = scale_evaluation(board, raw);
+        evals.push(Some(Eval { scaled, raw }));
+
+        if progress && i % 1024 == 0 {
+            print!("\rPROCESSED {:>10}/{}.", i + 1, samples.len());
+        }
+    }
+    if progress {
+        println!("\rPROCESSED {:>10}/{}.", samples.len(), samples.len());
+    }
+    evals
+}
+
+/// How the evaluations of a second network differ from the first.
+struct Comparison {
+    mean_abs_diff: Option<f64>,
+    sign_agreement: Option<f64>,
+    loss_diff: Option<f64>,
+}
+
+impl Comparison {
+    #[expect(clippy::cast_precision_loss)]
+    fn new(
+        evals: &[Option<Eval>],
+        other_evals: &[Option<Eval>],
+        stats: &NetStats,
+        other: &NetStats,
+    ) -> Self {
+        let mut diff_total = 0i128;
+        let mut agree = 0u64;
+        let mut count = 0u64;
+        for (a, b) in evals.iter().zip(other_evals) {
+            if let (Some(Eval { scaled: a, .. }), Some(Eval { scaled: b, .. })) = (a, b) {
+                diff_total += i128::from((a - b).abs());
+                agree += u64::from(a.signum() == b.signum());
+                count += 1;
+            }
+        }
+        let n = count as f64;
+        Comparison {
+            mean_abs_diff: (count > 0).then(|| diff_total as f64 / n),
+            sign_agreement: (count > 0).then(|| 100.0 * agree as f64 / n),
+            loss_diff: stats.loss().zip(other.loss()).map(|(a, b)| b - a),
+        }
+    }
+
+    fn print(&self) {
+        println!(" COMPARISON:");
+        if let Some(diff) = self.mean_abs_diff {
+            println!("  MEAN ABS DIFF: {:>10.2}", diff);
+        }
+        if let Some(agreement) = self.sign_agreement {
+            println!(" SIGN AGREEMENT: {:>9.2}%", agreement);
+        }
+        if let Some(diff) = self.loss_diff {
+            println!("      LOSS DIFF: {:>+10.6}", diff);
+        }
+    }
+}
+
+// Implementation yoinked from Viridithas
+pub fn eval_stats(td: &mut ThreadData, input: &Path, options: &StatsOptions) {
+    let samples = match read_samples(input) {
+        Ok(samples) => samples,
+        Err(e) => {
+            println!("info error: {}", e);
+            return;
+        }
+    };
+
+    let progress = !options.json;
+    let evals = evaluate_all(td, &samples, progress);
+    let stats = NetStats::new(&samples, &evals, options.wdl_lambda);
+    let mut doc = Json::object([
+        ("build", build_info()),
+        ("file", input.display().to_string().into()),
+        ("positions", samples.len().into()),
+        ("wdl_lambda", options.wdl_lambda.into()),
+        ("stats", stats.to_json()),
+    ]);
+    if !options.json {
+        stats.print();
+    }
+
+    if let Some(compare) = &options.compare {
+        if !options.json {
+            println!(" COMPARING WITH {compare}:");
+        }
+        let other_evals = match with_network(compare, || evaluate_all(td, &samples, progress)) {
+            Ok(evals) => evals,
+            Err(e) => {
+                println!("info error: {}", e);
+                return;
+            }
+        };
+        let other = NetStats::new(&samples, &other_evals, options.wdl_lambda);
+        let comparison = Comparison::new(&evals, &other_evals, &stats, &other);
+        if !options.json {
+            other.print();
+            comparison.print();
+        }
+        doc.push(
+            "compare",
+            Json::object([
+                ("network", compare.as_str().into()),
+                ("stats", other.to_json()),
+                ("mean_abs_diff", comparison.mean_abs_diff.into()),
+                ("sign_agreement", comparison.sign_agreement.into()),
+                ("loss_diff", comparison.loss_diff.into()),
+            ]),
+        );
+    }
+
+    if options.json {
+        println!("{}", doc);
     }
 }
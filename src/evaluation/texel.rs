@@ -0,0 +1,228 @@
+use crate::board::piece::Piece::{Bishop, Knight, Pawn, Queen, Rook};
+use crate::board::side::Side::White;
+use crate::evaluation::stats::{read_samples, sigmoid, EVAL_SCALE};
+use crate::search::parameters::{params, set_param};
+use crate::search::thread::ThreadData;
+use crate::tools::utils::TunableParam;
+use std::path::Path;
+
+/// The parameters of `scale_evaluation`, in the order of the model's weights: the material value of
+/// each piece type, then the base of the material scaling, then the half-move damping base.
+const SCALING_PARAMS: [&str; 7] = [
+    "scale_value_pawn",
+    "scale_value_knight",
+    "scale_value_bishop",
+    "scale_value_rook",
+    "scale_value_queen",
+    "material_scaling_base",
+    "hm_scaling_base",
+];
+const BASE: usize = 5;
+const HM_BASE: usize = 6;
+
+/// The decay rates of Adam's moment estimates.
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+pub struct TexelOptions {
+    pub epochs: usize,
+    /// The step size of the optimiser, as a fraction of each parameter's declared range.
+    pub learning_rate: f64,
+    /// The weight of the game result in the loss target, as in `eval_stats`.
+    pub wdl_lambda: f64,
+}
+
+impl Default for TexelOptions {
+    fn default() -> Self {
+        TexelOptions {
+            epochs: 1000,
+            learning_rate: 0.002,
+            wdl_lambda: 1.0,
+        }
+    }
+}
+
+/// A labelled position, reduced to what the scaling of its evaluation depends on.
+struct Entry {
+    /// The unscaled network output, from white's perspective.
+    raw: f64,
+    counts: [f64; 5],
+    hm: f64,
+    target: f64,
+}
+
+impl Entry {
+    fn material(&self, weights: &[f64; 7]) -> f64 {
+        weights[BASE] + (0..5).map(|i| weights[i] * self.counts[i]).sum::<f64>()
+    }
+
+    /// The scaled evaluation, as in `scale_evaluation` but without rounding.
+    fn eval(&self, weights: &[f64; 7]) -> f64 {
+        let damping = (weights[HM_BASE] - self.hm) / weights[HM_BASE];
+        self.raw * self.material(weights) / 32768.0 * damping
+    }
+}
+
+/// Fit the parameters of `scale_evaluation`, which scale the network output by the material left
+/// on the board and damp it as the half-move clock grows, to the labelled positions in a file. The
+/// network output is computed once per position, and the parameters are then fitted by full-batch
+/// gradient descent with Adam on the mean squared error between the predicted and the actual
+/// result, with each parameter kept within its declared range. The fitted values are applied to
+/// the engine and printed as a block for `search/parameters.rs`.
+pub fn run_texel(td: &mut ThreadData, input: &Path, options: &TexelOptions) {
+    let samples = match read_samples(input) {
+        Ok(samples) => samples,
+        Err(e) => {
+            println!("info error: {}", e);
+            return;
+        }
+    };
+
+    let mut entries = Vec::with_capacity(samples.len());
+    for sample in &samples {
+        let board = &sample.board;
+        let Some(result) = sample.result else {
+            continue;
+        };
+        if board.threats.contains(board.our_king_sq()) {
+            continue;
+        }
+        td.nnue.activate(board);
+        let raw = td.nnue.evaluate_unscaled(board) as f64;
+        let mut target = result.score(White);
+        if let Some(score) = sample.score {
+            target =
+                options.wdl_lambda * target + (1.0 - options.wdl_lambda) * sigmoid(score as f64);
+        }
+        entries.push(Entry {
+            raw: if board.stm == White { raw } else { -raw },
+            counts: [Pawn, Knight, Bishop, Rook, Queen].map(|pc| board.pieces(pc).count() as f64),
+            hm: board.hm as f64,
+            target,
+        });
+    }
+    if entries.is_empty() {
+        println!(
+            "info error: {} has no positions with a result",
+            input.display()
+        );
+        return;
+    }
+    println!("tuning on {} of {} positions", entries.len(), samples.len());
+
+    let declared: Vec<TunableParam> = params()
+        .into_iter()
+        .filter(|param| SCALING_PARAMS.contains(&param.name.as_str()))
+        .collect();
+    let declared: [&TunableParam; 7] = SCALING_PARAMS.map(|name| {
+        declared
+            .iter()
+            .find(|param| param.name == name)
+            .expect("scaling parameter is not declared")
+    });
+    let range = declared.map(|param| (param.max - param.min) as f64);
+    let initial = declared.map(|param| param.value as f64);
+    let initial_loss = loss(&entries, &initial);
+    println!("epoch {:>5} loss {:.6}", 0, initial_loss);
+
+    // The optimiser works on each parameter normalised to its range, so that a single learning
+    // rate suits values as different as the pawn's and the material base's.
+    let mut weights = initial;
+    let mut m = [0.0; 7];
+    let mut v = [0.0; 7];
+    for epoch in 1..=options.epochs {
+        let gradient = gradient(&entries, &weights);
+        for i in 0..7 {
+            let g = gradient[i] * range[i];
+            m[i] = BETA1 * m[i] + (1.0 - BETA1) * g;
+            v[i] = BETA2 * v[i] + (1.0 - BETA2) * g * g;
+            let m_hat = m[i] / (1.0 - BETA1.powi(epoch as i32));
+            let v_hat = v[i] / (1.0 - BETA2.powi(epoch as i32));
+            let step = options.learning_rate * m_hat / (v_hat.sqrt() + 1e-8);
+            weights[i] = (weights[i] - step * range[i])
+                .clamp(declared[i].min as f64, declared[i].max as f64);
+        }
+        if epoch % 100 == 0 || epoch == options.epochs {
+            println!("epoch {:>5} loss {:.6}", epoch, loss(&entries, &weights));
+        }
+    }
+
+    let tuned = weights.map(f64::round);
+    println!("loss {:.6} -> {:.6}", initial_loss, loss(&entries, &tuned));
+    for (param, value) in declared.iter().zip(tuned) {
+        set_param(&param.name, value as i32);
+        println!(
+            "    {:<29}= {:<24}{};",
+            param.name,
+            format!("{}, {}..={},", value, param.min, param.max),
+            param.spsa
+        );
+    }
+}
+
+fn loss(entries: &[Entry], weights: &[f64; 7]) -> f64 {
+    let total: f64 = entries
+        .iter()
+        .map(|entry| (sigmoid(entry.eval(weights)) - entry.target).powi(2))
+        .sum();
+    total / entries.len() as f64
+}
+
+/// The gradient of the loss with respect to each parameter.
+fn gradient(entries: &[Entry], weights: &[f64; 7]) -> [f64; 7] {
+    let mut gradient = [0.0; 7];
+    let hm_base = weights[HM_BASE];
+    for entry in entries {
+        let p = sigmoid(entry.eval(weights));
+        let d_eval = 2.0 * (p - entry.target) * p * (1.0 - p) / EVAL_SCALE;
+        let damping = (hm_base - entry.hm) / hm_base;
+        let d_material = d_eval * entry.raw / 32768.0 * damping;
+        for (g, count) in gradient.iter_mut().zip(entry.counts) {
+            *g += d_material * count;
+        }
+        gradient[BASE] += d_material;
+        gradient[HM_BASE] +=
+            d_eval * entry.raw * entry.material(weights) / 32768.0 * entry.hm / (hm_base * hm_base);
+    }
+    gradient.map(|g| g / entries.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient_matches_finite_differences() {
+        let entries = [
+            Entry {
+                raw: 350.0,
+                counts: [12.0, 3.0, 2.0, 4.0, 1.0],
+                hm: 10.0,
+                target: 1.0,
+            },
+            Entry {
+                raw: -120.0,
+                counts: [6.0, 1.0, 1.0, 2.0, 0.0],
+                hm: 60.0,
+                target: 0.5,
+            },
+        ];
+        let weights = [18.0, 479.0, 426.0, 615.0, 1263.0, 13706.0, 200.0];
+        let gradient = gradient(&entries, &weights);
+        for i in 0..7 {
+            let h = 1e-3;
+            let (mut up, mut down) = (weights, weights);
+            up[i] += h;
+            down[i] -= h;
+            let numeric = (loss(&entries, &up) - loss(&entries, &down)) / (2.0 * h);
+            let tolerance = 1e-6 * numeric.abs().max(1e-9) + 1e-12;
+            assert!(
+                (gradient[i] - numeric).abs() < tolerance,
+                "{}: {} vs {}",
+                SCALING_PARAMS[i],
+                gradient[i],
+                numeric
+            );
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::board::{cuckoo, magics};
 use crate::evaluation::feature::threat;
+use crate::tools::cpu;
 use crate::tools::uci::UCI;
 use board::ray;
 
@@ -24,6 +25,10 @@ pub mod search;
 pub mod tools;
 
 fn main() {
+    // Detect the SIMD backend and prepare the network
+    cpu::init();
+    evaluation::init();
+
     // Initialise static data
     magics::init();
     ray::init();
@@ -6,9 +6,11 @@ pub mod node;
 pub mod parameters;
 pub mod score;
 pub mod see;
+pub mod skill;
 pub mod thread;
 pub mod time;
 pub mod tt;
+pub mod wdl;
 pub mod lmr;
 
 use crate::board::movegen::MoveFilter;
@@ -21,7 +23,8 @@ use crate::search::movepicker::Stage::{BadNoisies, GoodNoisies};
 use crate::search::node::{NodeType, NonPV, Root, PV};
 use crate::search::score::{is_defined, is_mated, mate_in, mated_in};
 use crate::search::see::{see, SeeType};
-use crate::search::thread::ThreadData;
+use crate::search::skill::SKILL_MULTI_PV;
+use crate::search::thread::{Iteration, RootLine, ThreadData};
 use crate::search::time::LimitType::{Hard, Soft};
 use crate::search::tt::TTFlag;
 use crate::search::tt::TTFlag::{Exact, Lower, Upper};
@@ -33,7 +36,7 @@ use std::sync::atomic::Ordering::Relaxed;
 use SeeType::{Ordering, Pruning};
 use uci::print_search_info;
 use crate::tools::uci;
-use crate::tools::uci::{handle_no_legal_moves, handle_one_legal_move};
+use crate::tools::uci::{handle_emergency_move, handle_no_legal_moves, handle_one_legal_move};
 
 pub const MAX_PLY: usize = 256;
 
@@ -47,73 +50,110 @@ pub fn search(board: &Board, td: &mut ThreadData) -> (Move, i32) {
     td.nnue.activate(board);
     td.lmr.init();
     td.limits.init();
+    td.root_material = wdl::material(board);
 
     let mut root_moves = MoveList::new();
     board.gen_moves(MoveFilter::All, &mut root_moves);
 
+    // If the search is restricted to a subset of the root moves, discard the rest.
+    if !td.limits.search_moves.is_empty() {
+        let search_moves = &td.limits.search_moves;
+        root_moves.list.retain(|entry| search_moves.contains(&entry.mv));
+    }
+
     match root_moves.len() {
         0 => return handle_no_legal_moves(board, td),
         1 => return handle_one_legal_move(board, td, &root_moves),
         _ => {}
     }
 
-    let mut alpha = score::MIN;
-    let mut beta = score::MAX;
-    let mut score = 0;
-    let mut bound = Exact;
-    let mut delta = asp_delta();
-    let mut reduction = 0;
+    // Emergency Mode
+    // With almost no time left on the clock, play the TT move instantly if there is one. Otherwise,
+    // the search stops as soon as the first iteration is complete.
+    if td.limits.emergency {
+        if let Some(result) = handle_emergency_move(board, td, &root_moves) {
+            return result;
+        }
+    }
+
     let mut prev_mv = Move::NONE;
     let mut prev_score: i32 = 0;
 
+    // MultiPV
+    // Search the N best root moves, each with its own aspiration window. Each subsequent line is
+    // searched with the best moves of the previous lines excluded from the root move loop. At a
+    // reduced skill level, the move to play is picked from several lines, of which only the number
+    // of lines asked for are reported.
+    let skill_lines = if td.skill.is_some() { SKILL_MULTI_PV } else { 1 };
+    let multi_pv = td.multi_pv.max(skill_lines).clamp(1, root_moves.len());
+
+    // The lines of the last iteration which was not aborted, from which the move is picked at a
+    // reduced skill level. The lines of an aborted iteration may be partial and out of order.
+    let mut completed_lines: Vec<RootLine> = Vec::new();
+
     // Iterative Deepening
     // Search the position to a fixed depth, increasing the depth each iteration until the maximum
     // depth is reached or the search is aborted.
-    while td.depth < MAX_PLY as i32 && !td.should_stop(Soft) {
-        // Aspiration Windows
-        // Use the score from the previous iteration to guess the score from the current iteration.
-        // Based on this guess, we narrow the alpha-beta window around the previous score, causing
-        // more cut-offs and thus speeding up the search. If the true score is outside the window,
-        // a costly re-search is required.
-        if td.depth >= asp_min_depth() {
-            alpha = score::clamp(score - delta);
-            beta = score::clamp(score + delta);
-        }
-
-        loop {
-            let search_depth = td.depth - reduction;
-            score = alpha_beta::<Root>(board, td, search_depth, 0, alpha, beta, false);
-            bound = TTFlag::from_score(score, alpha, beta);
+    'id: while td.depth < MAX_PLY as i32 && !td.should_stop(Soft) {
+        for pv_idx in 0..multi_pv {
+            td.pv_idx = pv_idx;
+
+            let mut alpha = score::MIN;
+            let mut beta = score::MAX;
+            let mut score = td.root_lines.get(pv_idx).map_or(0, |line| line.score);
+            let mut delta = asp_delta() + score * score / asp_prev_score_div();
+            let mut reduction = 0;
+
+            // Aspiration Windows
+            // Use the score from the previous iteration to guess the score from the current iteration.
+            // Based on this guess, we narrow the alpha-beta window around the previous score, causing
+            // more cut-offs and thus speeding up the search. If the true score is outside the window,
+            // a costly re-search is required.
+            if td.depth >= asp_min_depth() {
+                alpha = score::clamp(score - delta);
+                beta = score::clamp(score + delta);
+            }
 
-            print_search_info(td, score.clamp(alpha, beta), bound, false);
-            td.update_tm_heuristics(prev_mv, prev_score, score);
+            loop {
+                let search_depth = td.depth - reduction;
+                score = alpha_beta::<Root>(board, td, search_depth, 0, alpha, beta, false);
+                let bound = TTFlag::from_score(score, alpha, beta);
 
-            prev_mv = td.best_move;
-            prev_score = score;
+                td.record_root_line(score, score.clamp(alpha, beta), bound);
+                if pv_idx < td.multi_pv {
+                    print_search_info(td, pv_idx, false);
+                }
 
-            if td.should_stop(Hard) || is_mate(score) {
-                break;
-            }
+                if pv_idx == 0 {
+                    td.update_tm_heuristics(prev_mv, prev_score, score);
+                    prev_mv = td.best_move;
+                    prev_score = score;
+                }
 
-            // Adjust the aspiration window in case the score fell outside the current window.
-            match score {
-                s if s <= alpha => {
-                    beta = (alpha + beta) / 2;
-                    alpha = score::clamp(score - delta);
-                    delta += (delta * 100) / asp_alpha_widening_factor();
-                    reduction = 0;
+                if td.should_stop(Hard) || is_mate(score) {
+                    break;
                 }
-                s if s >= beta => {
-                    beta = score::clamp(score + delta);
-                    delta += (delta * 100) / asp_beta_widening_factor();
-                    reduction = (reduction + 1).min(3);
+
+                // Adjust the aspiration window in case the score fell outside the current window.
+                match score {
+                    s if s <= alpha => {
+                        beta = (alpha + beta) / 2;
+                        alpha = score::clamp(score - delta);
+                        delta += (delta * 100) / asp_alpha_widening_factor();
+                        reduction = 0;
+                    }
+                    s if s >= beta => {
+                        beta = score::clamp(score + delta);
+                        delta += (delta * 100) / asp_beta_widening_factor();
+                        reduction = (reduction + 1).min(3);
+                    }
+                    _ => break,
                 }
-                _ => break,
             }
-        }
 
-        if td.should_stop(Hard) {
-            break;
+            if td.should_stop(Hard) {
+                break 'id;
+            }
         }
 
         // Only count this depth as completed if the search wasn't aborted part-way through.
@@ -121,13 +161,39 @@ pub fn search(board: &Board, td: &mut ThreadData) -> (Move, i32) {
             td.completed_depth = td.depth;
         }
 
-        delta = asp_delta() + score * score / asp_prev_score_div();
-        reduction = 0;
+        // Order the lines by score, so that the best line is always reported first.
+        td.root_lines[..multi_pv].sort_by_key(|line| -line.score);
+        td.best_move = td.root_lines[0].best_move();
+        td.best_score = td.root_lines[0].score;
+
+        if !td.abort.load(Relaxed) {
+            td.iterations.push(Iteration {
+                depth: td.depth,
+                time_ms: td.time(),
+                nodes: td.nodes(),
+                best_move: td.best_move,
+            });
+            if td.skill.is_some() {
+                completed_lines = td.root_lines[..multi_pv].to_vec();
+            }
+        }
+
         td.depth += 1;
     }
 
     // Print the final search stats
-    print_search_info(td, score.clamp(alpha, beta), bound, true);
+    for pv_idx in 0..td.root_lines.len().min(multi_pv).min(td.multi_pv) {
+        print_search_info(td, pv_idx, true);
+    }
+
+    // Skill Level
+    // When playing at a reduced strength, the main thread picks a possibly weaker move to play.
+    if let Some(skill) = td.skill.filter(|_| td.main) {
+        let mv = skill.pick_move(&completed_lines, &mut rand::rng());
+        if mv.exists() {
+            td.best_move = mv;
+        }
+    }
 
     (td.best_move, td.best_score)
 }
@@ -153,6 +219,9 @@ fn alpha_beta<NODE: NodeType>(
     // The root node is the first node in the search tree, and is thus also always a PV node.
     let root_node = NODE::ROOT;
 
+    // In a mate search, the pruning heuristics which risk missing a forced mate are disabled.
+    let mate_search = td.limits.mate.is_some();
+
     debug_assert!(ply <= MAX_PLY);
     debug_assert!(score::MIN <= alpha && alpha < beta && beta <= score::MAX);
     debug_assert!(pv_node || alpha == beta - 1);
@@ -350,19 +419,24 @@ fn alpha_beta<NODE: NodeType>(
             - rfp_improving_scale() * improving as i32
             - rfp_opp_worsening_scale() * opponent_worsening as i32
             - rfp_tt_move_noisy_scale() * tt_move_noisy as i32;
-        if depth <= rfp_max_depth() + 2 * improving as i32 && static_eval - futility_margin >= beta {
+        if !mate_search
+            && depth <= rfp_max_depth() + 2 * improving as i32
+            && static_eval - futility_margin >= beta {
             return lerp(beta, static_eval, rfp_lerp_factor());
         }
 
         // Razoring
         // Drop into q-search for nodes where the eval is far below alpha, and will likely fail low.
-        if !pv_node && static_eval < alpha - razor_base() - razor_scale() * depth * depth {
+        if !pv_node
+            && !mate_search
+            && static_eval < alpha - razor_base() - razor_scale() * depth * depth {
             return qs(board, td, alpha, beta, ply);
         }
 
         // Null Move Pruning
         // Skip nodes where giving the opponent an extra move (making a 'null move') still fails high.
         if depth >= nmp_min_depth()
+            && !mate_search
             && static_eval >= beta + nmp_margin()
             && ply as i32 > td.nmp_min_ply
             && board.has_non_pawns()
@@ -431,6 +505,7 @@ fn alpha_beta<NODE: NodeType>(
     if !pv_node
         && !singular_search
         && !in_check
+        && !mate_search
         && is_defined(tt_score)
         && !is_mate(tt_score)
         && !is_mate(beta)
@@ -530,6 +605,10 @@ fn alpha_beta<NODE: NodeType>(
 
     while let Some(mv) = move_picker.next(board, td) {
 
+        if root_node && td.is_excluded_root_move(mv) {
+            continue;
+        }
+
         legal_moves += 1;
 
         if singular.is_some_and(|s| s == mv) {
@@ -563,6 +642,7 @@ fn alpha_beta<NODE: NodeType>(
             - (tt_hit && tt_flag == Upper) as i32 * fp_tt_upper();
         if !root_node
             && !in_check
+            && !mate_search
             && is_quiet
             && lmr_depth < fp_max_depth()
             && !is_mated
@@ -575,6 +655,7 @@ fn alpha_beta<NODE: NodeType>(
         // Skip quiet moves ordered very late in the list.
         if !pv_node
             && !root_node
+            && !mate_search
             && !is_mated
             && is_quiet
             && depth <= lmp_max_depth()
@@ -585,6 +666,7 @@ fn alpha_beta<NODE: NodeType>(
         // History Pruning
         // Skip quiet moves that have a bad history score.
         if !root_node
+            && !mate_search
             && !is_mated
             && !is_killer
             && is_quiet
@@ -756,7 +838,7 @@ fn alpha_beta<NODE: NodeType>(
 
             if pv_node {
                 td.pv.update(ply, mv);
-                if root_node {
+                if root_node && td.pv_idx == 0 {
                     td.best_move = mv;
                     td.best_score = score;
                 }
@@ -905,12 +987,15 @@ fn alpha_beta<NODE: NodeType>(
         td.correction_history.update(board, &td.stack, depth, ply, static_eval, best_score);
     }
 
-    // Store the best move and score in the transposition table
-    if !singular_search && !td.should_stop(Hard) {
+    // Store the best move and score in the transposition table. Secondary multi-PV lines are not
+    // stored, since their best move is only the best of the non-excluded root moves.
+    let secondary_pv_line = root_node && td.pv_idx > 0;
+    if !singular_search && !secondary_pv_line && !td.should_stop(Hard) {
         td.tt().insert(board.hash_with_50mr_bucket(), best_move, best_score, raw_eval, depth, ply, flag, tt_pv);
     }
 
-    debug_assert!(best_score > score::MIN && best_score < score::MAX);
+    // An aborted search may break out of the move loop before any move's score is recorded.
+    debug_assert!(td.abort.load(Relaxed) || (best_score > score::MIN && best_score < score::MAX));
 
     best_score
 }
@@ -1146,7 +1231,8 @@ fn qs(board: &Board, td: &mut ThreadData, mut alpha: i32, beta: i32, ply: usize)
         );
     }
 
-    debug_assert!(best_score > score::MIN && best_score < score::MAX);
+    // An aborted search may break out of the move loop before any move's score is recorded.
+    debug_assert!(td.abort.load(Relaxed) || (best_score > score::MIN && best_score < score::MAX));
 
     best_score
 }
@@ -1228,3 +1314,189 @@ fn calc_opponent_worsening(td: &ThreadData, ply: usize, static_eval: i32, in_che
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use crate::search::engine::Engine;
+    use crate::search::thread::SharedContext;
+    use crate::search::time::{FischerTime, SearchLimits, EMERGENCY_TIME_MS, MAX_MOVE_OVERHEAD_MS};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Once};
+    use std::time::{Duration, Instant};
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    #[test]
+    fn test_mate_search_solves_mate_in_n() {
+        init();
+        let shared = Arc::new(SharedContext::new(16));
+        let mut td = Box::new(ThreadData::new(0, true, shared, Arc::new(AtomicBool::new(false))));
+        td.quiet = true;
+
+        for (fen, moves, solution) in [
+            ("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", 2, "d5f6"),
+            ("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1", 3, "f6a6"),
+            ("2r3k1/p4p2/3Rp2p/1p2P1pK/8/1P4P1/P3Q2P/1q6 b - - 0 1", 3, "b1g6"),
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            td.keys.clear();
+            td.keys.push(board.hash());
+            td.root_ply = 0;
+            td.reset();
+            td.tt().clear();
+            td.abort.store(false, Relaxed);
+
+            // The node limit is only a backstop: the search should stop once the mate is proven.
+            let hard_nodes = 20_000_000;
+            td.limits = SearchLimits::new(None, None, None, Some(hard_nodes), None, 0, 0);
+            td.limits.mate = Some(moves);
+            td.start_time = Instant::now();
+            let (mv, score) = search(&board, &mut td);
+
+            assert_eq!(mv.to_uci(), solution, "{}", fen);
+            assert_eq!(score, mate_in(2 * moves as usize - 1), "{}", fen);
+            assert!(td.nodes() < hard_nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_emergency_mode() {
+        init();
+        let clock = |time| {
+            Some(FischerTime {
+                time,
+                inc: 0,
+                moves_to_go: None,
+            })
+        };
+        let limits =
+            |time, overhead| SearchLimits::new(clock(time), None, None, None, None, 0, overhead);
+
+        // Emergency mode starts once the clock minus the move overhead drops below the threshold.
+        assert!(limits(EMERGENCY_TIME_MS + 49, 50).emergency);
+        assert!(!limits(EMERGENCY_TIME_MS + 50, 50).emergency);
+        assert!(limits(1000, 950).emergency);
+
+        let mut engine = Engine::new();
+        engine.set_move_overhead(MAX_MOVE_OVERHEAD_MS + 1000);
+        assert_eq!(engine.move_overhead(), MAX_MOVE_OVERHEAD_MS);
+
+        let shared = Arc::new(SharedContext::new(16));
+        let mut td = Box::new(ThreadData::new(0, true, shared, Arc::new(AtomicBool::new(false))));
+        td.quiet = true;
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let board = Board::from_fen(fen).unwrap();
+        let emergency_search = |td: &mut ThreadData| {
+            td.keys.clear();
+            td.keys.push(board.hash());
+            td.reset();
+            td.limits = limits(10, 50);
+            td.start_time = Instant::now();
+            search(&board, td)
+        };
+
+        // Without a TT move, only the first iteration is searched.
+        let (mv, _) = emergency_search(&mut td);
+        assert!(board.is_pseudo_legal(&mv) && board.is_legal(&mv));
+        assert_eq!(td.completed_depth, 1);
+
+        // With a TT move, it is played without searching.
+        let tt_move = td.tt().probe(board.hash_with_50mr_bucket()).unwrap().best_move();
+        let (mv, _) = emergency_search(&mut td);
+        assert_eq!(mv, tt_move);
+        assert_eq!(td.local_nodes(), 0);
+    }
+
+    #[test]
+    fn test_multi_pv_lines() {
+        init();
+        let shared = Arc::new(SharedContext::new(16));
+        let mut td = Box::new(ThreadData::new(0, true, shared, Arc::new(AtomicBool::new(false))));
+        td.quiet = true;
+        td.multi_pv = 3;
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        td.keys.push(board.hash());
+        td.reset();
+        td.limits = SearchLimits::new(None, None, None, None, Some(6), 0, 0);
+        td.start_time = Instant::now();
+        let (mv, score) = search(&board, &mut td);
+
+        let lines = &td.root_lines[..3];
+        for (i, line) in lines.iter().enumerate() {
+            assert!(!line.best_move().is_null());
+            assert!(lines[..i].iter().all(|other| other.best_move() != line.best_move()));
+        }
+        assert!(lines.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!((mv, score), (lines[0].best_move(), lines[0].score));
+    }
+
+    #[test]
+    fn test_search_moves() {
+        init();
+        let shared = Arc::new(SharedContext::new(16));
+        let mut td = Box::new(ThreadData::new(0, true, shared, Arc::new(AtomicBool::new(false))));
+        td.quiet = true;
+        // Without the restriction, Ra6 mates in three.
+        let board = Board::from_fen("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1").unwrap();
+        let mut legal_moves = MoveList::new();
+        board.gen_moves(MoveFilter::All, &mut legal_moves);
+
+        for names in [vec!["h2h3", "h2h4", "e5d4"], vec!["h2h4"]] {
+            let search_moves: Vec<Move> = legal_moves
+                .iter()
+                .map(|entry| entry.mv)
+                .filter(|mv| names.contains(&mv.to_uci().as_str()))
+                .collect();
+            assert_eq!(search_moves.len(), names.len());
+            td.keys.clear();
+            td.keys.push(board.hash());
+            td.reset();
+            td.tt().clear();
+            td.limits = SearchLimits::new(None, None, None, None, Some(6), 0, 0);
+            td.limits.search_moves = search_moves.clone();
+            td.start_time = Instant::now();
+            let (mv, _) = search(&board, &mut td);
+
+            assert!(search_moves.contains(&mv), "{}", mv.to_uci());
+        }
+    }
+
+    #[test]
+    fn test_ponder_waits_for_ponderhit_or_stop() {
+        init();
+        let board = Board::new();
+        let mut engine = Engine::new();
+        engine.td_mut().quiet = true;
+
+        for ponderhit in [true, false] {
+            engine.td_mut().keys.clear();
+            engine.td_mut().keys.push(board.hash());
+            let mut limits = SearchLimits::new(None, Some(20), None, None, Some(2), 0, 0);
+            limits.ponder = true;
+            engine.go(board, limits);
+
+            // The time and depth limits are long exceeded, but a ponder search keeps going.
+            std::thread::sleep(Duration::from_millis(200));
+            engine.try_reclaim();
+            assert!(engine.searching());
+
+            if ponderhit {
+                engine.ponderhit();
+            } else {
+                engine.stop();
+            }
+            engine.join();
+            assert!(!engine.searching());
+            assert!(engine.td().best_move.exists());
+        }
+    }
+}
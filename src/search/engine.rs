@@ -1,14 +1,17 @@
+use crate::board::moves::Move;
+use crate::board::observer::NullBoardObserver;
 use crate::board::Board;
 use crate::search::parameters::thread_weight_score_offset;
 use crate::search::score;
 use crate::search::search;
-use crate::search::thread::{SharedContext, ThreadData};
-use crate::search::time::SearchLimits;
+use crate::search::skill::Skill;
+use crate::search::thread::{PonderState, SharedContext, ThreadData};
+use crate::search::time::{SearchLimits, DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
 use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 pub const MAX_THREADS: usize = 256;
 
@@ -16,6 +19,10 @@ pub struct Engine {
     threads: Option<Vec<Box<ThreadData>>>,
     num_threads: usize,
     abort: Arc<AtomicBool>,
+    ponder: Arc<PonderState>,
+    ponder_enabled: bool,
+    move_overhead: u64,
+    start_time: Instant,
     handle: Option<JoinHandle<Vec<Box<ThreadData>>>>,
 }
 
@@ -29,10 +36,15 @@ impl Engine {
     pub fn new() -> Self {
         let main = Box::new(ThreadData::default());
         let abort = Arc::clone(&main.abort);
+        let ponder = Arc::clone(&main.ponder);
         Engine {
             threads: Some(vec![main]),
             num_threads: 1,
             abort,
+            ponder,
+            ponder_enabled: false,
+            move_overhead: DEFAULT_MOVE_OVERHEAD_MS,
+            start_time: Instant::now(),
             handle: None,
         }
     }
@@ -65,6 +77,16 @@ impl Engine {
         self.threads.as_mut().unwrap()[0].minimal_output = value;
     }
 
+    /// Set whether the engine should print nothing at all during a search, including the best move.
+    pub fn set_quiet(&mut self, value: bool) {
+        self.threads.as_mut().unwrap()[0].quiet = value;
+    }
+
+    /// Set whether win-draw-loss probabilities are included in the search info (UCI_ShowWDL).
+    pub fn set_show_wdl(&mut self, value: bool) {
+        self.threads.as_mut().unwrap()[0].show_wdl = value;
+    }
+
     pub fn set_use_soft_nodes(&mut self, value: bool) {
         self.threads.as_mut().unwrap()[0].use_soft_nodes = value;
     }
@@ -73,6 +95,33 @@ impl Engine {
         self.threads.as_ref().unwrap()[0].use_soft_nodes
     }
 
+    /// Set the number of principal variations to search and report (the UCI MultiPV option).
+    pub fn set_multi_pv(&mut self, value: usize) {
+        self.threads.as_mut().unwrap()[0].multi_pv = value;
+    }
+
+    /// Set the reduced playing strength, or `None` to play at full strength (the UCI Skill Level
+    /// option).
+    pub fn set_skill(&mut self, skill: Option<Skill>) {
+        self.threads.as_mut().unwrap()[0].skill = skill.filter(|skill| !skill.is_full_strength());
+    }
+
+    /// Set whether pondering is enabled (the UCI Ponder option). If so, the expected reply is sent
+    /// alongside the best move.
+    pub fn set_ponder(&mut self, value: bool) {
+        self.ponder_enabled = value;
+    }
+
+    /// Set the time in milliseconds reserved per move for communication latency with the GUI (the
+    /// UCI Move Overhead option), up to `MAX_MOVE_OVERHEAD_MS`.
+    pub fn set_move_overhead(&mut self, value: u64) {
+        self.move_overhead = value.min(MAX_MOVE_OVERHEAD_MS);
+    }
+
+    pub fn move_overhead(&self) -> u64 {
+        self.move_overhead
+    }
+
     /// Immutable access to the main thread's data.
     pub fn td(&self) -> &ThreadData {
         &self.threads.as_ref().unwrap()[0]
@@ -91,7 +140,7 @@ impl Engine {
 
     /// Start a new search on the given `board` with the given `limits`. Spawns a coordinator
     /// thread that runs the main search alongside `num_threads - 1` helpers.
-    pub fn go(&mut self, board: Board, limits: SearchLimits) {
+    pub fn go(&mut self, board: Board, mut limits: SearchLimits) {
         self.sync_thread_pool();
 
         let mut threads = self.threads.take().unwrap();
@@ -101,6 +150,17 @@ impl Engine {
         let root_ply = threads[0].root_ply;
         let minimal = threads[0].minimal_output;
         let use_soft_nodes = threads[0].use_soft_nodes;
+        let multi_pv = threads[0].multi_pv;
+
+        // A reduced skill level limits the nodes of every search. The limit is checked between
+        // iterations, so that the move is picked from completely searched lines, with a hard limit
+        // as a backstop.
+        if let Some(nodes) = threads[0].skill.and_then(|skill| skill.nodes()) {
+            let limit =
+                |current: Option<u64>, nodes: u64| Some(current.map_or(nodes, |n| n.min(nodes)));
+            limits.soft_nodes = limit(limits.soft_nodes, nodes);
+            limits.hard_nodes = limit(limits.hard_nodes, 4 * nodes);
+        }
 
         // Configure the main thread.
         threads[0].reset();
@@ -113,6 +173,7 @@ impl Engine {
             helper.root_ply = root_ply;
             helper.minimal_output = minimal;
             helper.use_soft_nodes = use_soft_nodes;
+            helper.multi_pv = multi_pv;
             helper.reset_local();
             helper.start_time = start_time;
             helper.limits = limits.clone();
@@ -121,6 +182,12 @@ impl Engine {
         // Age the TT and clear the abort flag.
         threads[0].tt().birthday();
         self.abort.store(false, Relaxed);
+        self.ponder.start(limits.ponder);
+        self.start_time = start_time;
+
+        let ponder = Arc::clone(&self.ponder);
+        let ponder_enabled = self.ponder_enabled;
+        let infinite = limits.infinite;
 
         self.handle = Some(std::thread::spawn(move || {
             std::thread::scope(|s| {
@@ -137,8 +204,25 @@ impl Engine {
                 search(&board, main_td);
             });
 
+            // A ponder search must not report its best move until 'ponderhit' or 'stop' is received,
+            // and an infinite search not until 'stop' is received.
+            while (infinite || ponder.is_pondering()) && !threads[0].abort.load(Relaxed) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+
+            if threads[0].quiet {
+                return threads;
+            }
             let best_idx = select_best_thread(&threads);
-            println!("bestmove {}", threads[best_idx].best_move.to_uci());
+            let best_td = &threads[best_idx];
+            match ponder_move(&board, best_td).filter(|_| ponder_enabled) {
+                Some(ponder_mv) => println!(
+                    "bestmove {} ponder {}",
+                    best_td.best_move.to_uci(),
+                    ponder_mv.to_uci()
+                ),
+                None => println!("bestmove {}", best_td.best_move.to_uci()),
+            }
             threads
         }));
     }
@@ -148,6 +232,12 @@ impl Engine {
         self.abort.store(true, Relaxed);
     }
 
+    /// The opponent played the move we were pondering on. The ponder search continues as a normal
+    /// search, with the time spent so far credited towards it.
+    pub fn ponderhit(&self) {
+        self.ponder.ponderhit(self.start_time.elapsed());
+    }
+
     /// Block until the current search finishes and reclaim the thread vec.
     pub fn join(&mut self) {
         if let Some(handle) = self.handle.take() {
@@ -157,6 +247,7 @@ impl Engine {
                     eprintln!("info string error: main thread panicked, reinitialising");
                     let main = Box::new(ThreadData::default());
                     self.abort = Arc::clone(&main.abort);
+                    self.ponder = Arc::clone(&main.ponder);
                     self.threads = Some(vec![main]);
                 }
             }
@@ -192,6 +283,7 @@ impl Engine {
 
         let shared = Arc::clone(&threads[0].shared);
         let abort = Arc::clone(&threads[0].abort);
+        let ponder = Arc::clone(&threads[0].ponder);
 
         while threads.len() < self.num_threads {
             let id = threads.len();
@@ -208,6 +300,7 @@ impl Engine {
         for helper in threads[1..].iter_mut() {
             helper.shared = Arc::clone(&shared);
             helper.abort = Arc::clone(&abort);
+            helper.ponder = Arc::clone(&ponder);
         }
 
         self.abort = abort;
@@ -216,8 +309,10 @@ impl Engine {
 
 /// Select the best thread to use for the final bestmove. Each thread contributes its weight to the
 /// total score for its best move. The thread whose best_move has the highest accumulated score wins.
+/// In a multi-PV search the main thread's lines are the ones reported, so its best move is used, as
+/// it is at a reduced skill level, where the main thread picks the move to play.
 fn select_best_thread(threads: &[Box<ThreadData>]) -> usize {
-    if threads.len() == 1 {
+    if threads.len() == 1 || threads[0].multi_pv > 1 || threads[0].skill.is_some() {
         return 0;
     }
 
@@ -249,6 +344,26 @@ fn select_best_thread(threads: &[Box<ThreadData>]) -> usize {
         .unwrap_or(0)
 }
 
+/// Select the move to ponder on: the expected reply to our best move. This is the second move of the
+/// principal variation, or the TT move in the resulting position if the PV is too short.
+fn ponder_move(board: &Board, td: &ThreadData) -> Option<Move> {
+    if td.best_move.is_null() {
+        return None;
+    }
+    if let Some(line) = td.root_lines.first() {
+        if line.best_move() == td.best_move && line.pv.len() >= 2 {
+            return Some(line.pv[1]);
+        }
+    }
+
+    let mut board = *board;
+    board.make(&td.best_move, &mut NullBoardObserver);
+    td.tt()
+        .probe(board.hash_with_50mr_bucket())
+        .map(|entry| entry.best_move())
+        .filter(|mv| mv.exists() && board.is_pseudo_legal(mv) && board.is_legal(mv))
+}
+
 /// A guard that sets the global abort flag to true if the thread panics, designed to prevent stalling.
 struct AbortOnPanic(Arc<AtomicBool>);
 
@@ -252,6 +252,7 @@ tunable_params! {
     scale_value_rook             = 615, 500..=700,         true;
     scale_value_queen            = 1263, 1000..=1400,      true;
     material_scaling_base        = 13706, 10000..=40000,   true;
+    hm_scaling_base              = 200, 120..=400,         true;
     qs_futility_threshold        = 178, 80..=250,          true;
     qs_see_threshold             = -91, -200..=100,        true;
     qs_stand_pat_lerp_factor     = 52, 0..=100,            true;
@@ -274,6 +275,11 @@ tunable_params! {
     tm_score_base                = 1123, 800..=1800,       true;
     tm_score_scale               = 44, 10..=100,           true;
     tm_score_min                 = 913, 500..=1000,        true;
+    tm_ponder_credit             = 500, 0..=1000,          true;
+    tm_mtg_soft_scale            = 1000, 500..=2000,       true;
+    tm_mtg_buffer                = 2000, 0..=5000,         true;
+    tm_mtg_hard_scale            = 4000, 2000..=8000,      true;
+    tm_mtg_hard_max              = 750, 400..=950,         true;
     thread_weight_score_offset   = 12, 0..=20,             true;
 }
 
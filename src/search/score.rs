@@ -80,12 +80,18 @@ pub const fn to_search(score: i32, ply: usize) -> i32 {
     }
 }
 
+/// The distance to mate in full moves for a mate-or-mated score.
+#[inline]
+pub const fn moves_to_mate(score: i32) -> i32 {
+    let plies = MATE - score.abs();
+    (plies + 1) / 2
+}
+
 /// Format a score for UCI output, either as `cp <centipawns>` or `mate <n>`.
 pub fn format_score(score: i32) -> String {
     if is_mate(score) {
         // Distance in full moves to mate; positive = we give mate, negative = we are mated.
-        let plies = MATE - score.abs();
-        let moves = (plies + 1) / 2;
+        let moves = moves_to_mate(score);
         if score < 0 {
             format!("mate -{moves}")
         } else {
@@ -1,6 +1,6 @@
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crate::board::moves::Move;
 use crate::evaluation::NNUE;
@@ -8,9 +8,10 @@ use crate::search::correction::CorrectionHistories;
 use crate::search::history::Histories;
 use crate::search::node::NodeStack;
 use crate::search::time::{LimitType, SearchLimits};
-use crate::search::tt::TranspositionTable;
+use crate::search::tt::{TTFlag, TranspositionTable};
 use crate::search::{score, MAX_PLY};
 use crate::search::lmr::LmrTable;
+use crate::search::skill::Skill;
 use crate::search::parameters::score_stability_threshold;
 use crate::tools::utils::boxed_and_zeroed;
 
@@ -20,18 +21,61 @@ pub struct SharedContext {
     pub nodes: AtomicU64,
 }
 
+/// State for a ponder search, shared between the engine and the main search thread. While pondering,
+/// the search limits are suspended. On 'ponderhit' the time spent pondering is recorded, so that the
+/// time manager can start our clock from that point and credit the ponder time towards the search.
+#[derive(Default)]
+pub struct PonderState {
+    pondering: AtomicBool,
+    pondered_ms: AtomicU64,
+}
+
+impl PonderState {
+    /// Reset the state at the start of a new search, which may or may not be a ponder search.
+    pub fn start(&self, pondering: bool) {
+        self.pondered_ms.store(0, Relaxed);
+        self.pondering.store(pondering, Relaxed);
+    }
+
+    /// The opponent played the expected move: convert the ponder search into a normal search.
+    pub fn ponderhit(&self, elapsed: Duration) {
+        if self.is_pondering() {
+            self.pondered_ms.store(elapsed.as_millis() as u64, Relaxed);
+            self.pondering.store(false, Relaxed);
+        }
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Relaxed)
+    }
+
+    /// The time spent pondering before 'ponderhit' was received, or zero if this was not a ponder search.
+    pub fn time_pondered(&self) -> Duration {
+        Duration::from_millis(self.pondered_ms.load(Relaxed))
+    }
+}
+
 pub struct ThreadData {
     pub id: usize,
     pub main: bool,
     pub minimal_output: bool,
+    pub quiet: bool,
+    pub show_wdl: bool,
     pub use_soft_nodes: bool,
+    pub multi_pv: usize,
+    /// The reduced playing strength, or `None` at full strength.
+    pub skill: Option<Skill>,
+    pub pv_idx: usize,
+    pub root_lines: Vec<RootLine>,
     pub shared: Arc<SharedContext>,
     pub abort: Arc<AtomicBool>,
+    pub ponder: Arc<PonderState>,
     pub pv: PrincipalVariationTable,
     pub stack: NodeStack,
     pub nnue: NNUE,
     pub keys: Vec<u64>,
     pub root_ply: usize,
+    pub root_material: i32,
     pub history: Histories,
     pub correction_history: CorrectionHistories,
     pub lmr: LmrTable,
@@ -47,6 +91,7 @@ pub struct ThreadData {
     pub nmp_min_ply: i32,
     pub best_move: Move,
     pub best_score: i32,
+    pub iterations: Vec<Iteration>,
 }
 
 impl ThreadData {
@@ -55,19 +100,27 @@ impl ThreadData {
             id,
             main,
             minimal_output: false,
+            quiet: false,
+            show_wdl: false,
             use_soft_nodes: false,
+            multi_pv: 1,
+            skill: None,
+            pv_idx: 0,
+            root_lines: Vec::new(),
             shared,
             abort,
+            ponder: Arc::new(PonderState::default()),
             pv: PrincipalVariationTable::default(),
             stack: NodeStack::default(),
             nnue: NNUE::default(),
             keys: Vec::new(),
             root_ply: 0,
+            root_material: 0,
             history: Histories::default(),
             correction_history: CorrectionHistories::default(),
             lmr: LmrTable::default(),
             node_table: NodeTable::default(),
-            limits: SearchLimits::new(None, None, None, None, None, 0),
+            limits: SearchLimits::new(None, None, None, None, None, 0, 0),
             start_time: Instant::now(),
             best_move_stability: 0,
             score_stability: 0,
@@ -78,6 +131,7 @@ impl ThreadData {
             nmp_min_ply: 0,
             best_move: Move::NONE,
             best_score: score::MIN,
+            iterations: Vec::new(),
         }
     }
 }
@@ -152,6 +206,9 @@ impl ThreadData {
         self.best_score = 0;
         self.best_move_stability = 0;
         self.score_stability = 0;
+        self.pv_idx = 0;
+        self.root_lines.clear();
+        self.iterations.clear();
     }
 
     /// Clear the (shared) transposition table and this thread's per-thread search tables.
@@ -185,17 +242,35 @@ impl ThreadData {
         if self.abort.load(Relaxed) {
             return true;
         }
-        let stop = match limit_type {
-            LimitType::Soft => self.soft_limit_reached(),
-            LimitType::Hard => self.hard_limit_reached(),
-        };
+        // While pondering, the search limits are suspended until 'ponderhit' is received.
+        if self.ponder.is_pondering() {
+            return false;
+        }
+        let stop = self.mate_proven()
+            || match limit_type {
+                LimitType::Soft => self.soft_limit_reached(),
+                LimitType::Hard => self.hard_limit_reached(),
+            };
         if stop {
             self.abort.store(true, Relaxed);
         }
         stop
     }
 
+    /// The time elapsed on our clock since the search began. If the search began as a ponder search,
+    /// our clock only started running on 'ponderhit'.
+    fn clock_elapsed(&self) -> Duration {
+        self.start_time
+            .elapsed()
+            .saturating_sub(self.ponder.time_pondered())
+    }
+
     fn soft_limit_reached(&self) -> bool {
+        // In emergency mode, skip the stability scaling and stop as soon as depth 1 is complete.
+        if self.limits.emergency {
+            return true;
+        }
+
         let best_move_nodes = self.node_table.get(&self.best_move);
         let best_move_stability = self.best_move_stability as u64;
         let score_stability = self.score_stability as u64;
@@ -207,7 +282,8 @@ impl ThreadData {
             best_move_stability,
             score_stability,
         ) {
-            if self.start_time.elapsed() >= soft_time {
+            let credit = self.limits.ponder_credit(self.ponder.time_pondered());
+            if self.clock_elapsed() + credit >= soft_time {
                 return true;
             }
         }
@@ -227,6 +303,15 @@ impl ThreadData {
         false
     }
 
+    /// Whether a mate search is complete, because the first line has an exact score proving a mate
+    /// within the requested number of moves. This is checked with the hard limits too, so that the
+    /// search stops as soon as the line is recorded, without searching the rest of the iteration.
+    fn mate_proven(&self) -> bool {
+        self.root_lines
+            .first()
+            .is_some_and(|line| line.bound == TTFlag::Exact && self.limits.mate_found(line.score))
+    }
+
     fn hard_limit_reached(&self) -> bool {
         if let Some(hard_nodes) = self.limits.hard_nodes {
             if self.nodes() >= hard_nodes {
@@ -240,7 +325,7 @@ impl ThreadData {
         }
 
         if let Some(hard_time) = self.limits.hard_time {
-            if self.start_time.elapsed() >= hard_time {
+            if self.clock_elapsed() >= hard_time {
                 return true;
             }
         }
@@ -254,6 +339,33 @@ impl ThreadData {
         false
     }
 
+    /// Record the result of searching the current multi-PV line, copying the principal variation
+    /// out of the PV table so that it survives the search of the subsequent lines.
+    pub fn record_root_line(&mut self, score: i32, display_score: i32, bound: TTFlag) {
+        let line = RootLine {
+            depth: self.depth,
+            score,
+            display_score,
+            bound,
+            pv: self.pv.line().to_vec(),
+        };
+        match self.root_lines.get_mut(self.pv_idx) {
+            Some(existing) => *existing = line,
+            None => self.root_lines.push(line),
+        }
+    }
+
+    /// Whether the given root move is excluded from the current search, either because it is not
+    /// one of the 'searchmoves' the search is restricted to, or because it is the best move of one
+    /// of the multi-PV lines already searched in this iteration.
+    pub fn is_excluded_root_move(&self, mv: Move) -> bool {
+        let search_moves = &self.limits.search_moves;
+        (!search_moves.is_empty() && !search_moves.contains(&mv))
+            || self.root_lines[..self.pv_idx]
+                .iter()
+                .any(|line| line.best_move() == mv)
+    }
+
     pub fn update_tm_heuristics(&mut self, prev_mv: Move, prev_score: i32, score: i32) {
         if prev_mv == self.best_move {
             self.best_move_stability += 1;
@@ -295,6 +407,32 @@ impl NodeTable {
     }
 }
 
+/// The best move at the end of a completed iteration, with the time and nodes spent reaching it.
+#[derive(Clone, Copy)]
+pub struct Iteration {
+    pub depth: i32,
+    pub time_ms: u128,
+    pub nodes: u64,
+    pub best_move: Move,
+}
+
+/// The result of searching a single line in a multi-PV search: the depth at which the line was last
+/// searched, its score and bound, and the principal variation beginning with the root move.
+#[derive(Clone)]
+pub struct RootLine {
+    pub depth: i32,
+    pub score: i32,
+    pub display_score: i32,
+    pub bound: TTFlag,
+    pub pv: Vec<Move>,
+}
+
+impl RootLine {
+    pub fn best_move(&self) -> Move {
+        self.pv.first().copied().unwrap_or(Move::NONE)
+    }
+}
+
 pub struct PrincipalVariationTable {
     table: Box<[[Move; MAX_PLY + 1]; MAX_PLY + 1]>,
     len: [usize; MAX_PLY + 1],
@@ -1,8 +1,15 @@
 use std::time::Duration;
 
+use crate::board::moves::Move;
 use crate::search::parameters::*;
+use crate::search::score;
 
-pub const UCI_OVERHEAD_MS: u64 = 50;
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 50;
+pub const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+
+/// Below this much usable time on the clock the engine enters emergency mode, in which it plays the
+/// TT move instantly if it has one, and otherwise only completes a depth-1 search.
+pub const EMERGENCY_TIME_MS: u64 = 100;
 
 #[rustfmt::skip]
 #[derive(Clone)]
@@ -21,6 +28,11 @@ pub struct TimeParams {
     pub score_tm_base:       f32,
     pub score_tm_scale:      f32,
     pub score_tm_min:        f32,
+    pub ponder_credit:       f64,
+    pub mtg_soft_scale:      f64,
+    pub mtg_buffer:          f64,
+    pub mtg_hard_scale:      f64,
+    pub mtg_hard_max:        f64,
 }
 
 impl TimeParams {
@@ -41,6 +53,11 @@ impl TimeParams {
             score_tm_base:      tm_score_base() as f32 / 1000.0,
             score_tm_scale:     tm_score_scale() as f32 / 1000.0,
             score_tm_min:       tm_score_min() as f32 / 1000.0,
+            ponder_credit:      tm_ponder_credit() as f64 / 1000.0,
+            mtg_soft_scale:     tm_mtg_soft_scale() as f64 / 1000.0,
+            mtg_buffer:         tm_mtg_buffer() as f64 / 1000.0,
+            mtg_hard_scale:     tm_mtg_hard_scale() as f64 / 1000.0,
+            mtg_hard_max:       tm_mtg_hard_max() as f64 / 1000.0,
         }
     }
 }
@@ -50,7 +67,15 @@ pub enum LimitType {
     Hard,
 }
 
-pub type FischerTime = (u64, u64);
+/// The clock state for the side to move: remaining time and increment in milliseconds, plus the
+/// number of moves until the next time control, if the game is played with repeating controls
+/// (e.g. 40 moves in 120 minutes) rather than sudden death.
+#[derive(Clone, Copy)]
+pub struct FischerTime {
+    pub time: u64,
+    pub inc: u64,
+    pub moves_to_go: Option<u64>,
+}
 
 /// The amount of time the engine chooses to search is split into to two limits: hard and soft. The
 /// hard limit is checked regularly during search, and the search is aborted as soon as it is reached.
@@ -61,6 +86,16 @@ pub type FischerTime = (u64, u64);
 /// 'How stable the search is' can be captured a few ways: how many iterations the best move has
 /// remained the same, how stable the search score has been across iterations, or what portion of
 /// nodes have been spent searching the current best move.
+///
+/// The limits also carry the optional set of root moves the search is restricted to (the UCI
+/// 'go searchmoves' parameter), where an empty list means all legal moves are searched, and whether
+/// the search is a ponder search, in which case the limits only apply from 'ponderhit' onwards.
+///
+/// An infinite search never reports its best move until it is stopped. A mate search stops as soon
+/// as a mate in the given number of moves (or fewer) is found.
+///
+/// The move overhead is reserved from the remaining time to cover communication latency with the
+/// GUI. If the clock is close to running out, the search falls back to emergency mode.
 #[derive(Clone)]
 pub struct SearchLimits {
     pub hard_time: Option<Duration>,
@@ -68,6 +103,11 @@ pub struct SearchLimits {
     pub soft_nodes: Option<u64>,
     pub hard_nodes: Option<u64>,
     pub depth: Option<u64>,
+    pub search_moves: Vec<Move>,
+    pub ponder: bool,
+    pub infinite: bool,
+    pub mate: Option<u64>,
+    pub emergency: bool,
     pub time_params: TimeParams,
 }
 
@@ -79,11 +119,14 @@ impl SearchLimits {
         hard_nodes: Option<u64>,
         depth: Option<u64>,
         fm_clock: usize,
+        move_overhead: u64,
     ) -> SearchLimits {
         let time_params = TimeParams::init();
+        let emergency =
+            fischer.is_some_and(|f| f.time.saturating_sub(move_overhead) < EMERGENCY_TIME_MS);
         let (soft_time, hard_time) = match (fischer, movetime) {
             (Some(f), _) => {
-                let (soft, hard) = Self::calc_time_limits(&time_params, f, fm_clock);
+                let (soft, hard) = Self::calc_time_limits(&time_params, f, fm_clock, move_overhead);
                 (Some(soft), Some(hard))
             }
             (None, Some(mt)) => {
@@ -99,6 +142,11 @@ impl SearchLimits {
             soft_nodes,
             hard_nodes,
             depth,
+            search_moves: Vec::new(),
+            ponder: false,
+            infinite: false,
+            mate: None,
+            emergency,
             time_params,
         }
     }
@@ -127,6 +175,19 @@ impl SearchLimits {
         })
     }
 
+    /// Whether the score proves a mate within the number of moves requested by a mate search.
+    pub fn mate_found(&self, score: i32) -> bool {
+        self.mate.is_some_and(|moves| {
+            score::is_mating(score) && score::moves_to_mate(score) as u64 <= moves
+        })
+    }
+
+    /// 'Ponder credit': after a ponderhit, a fraction of the time already spent pondering on the
+    /// expected move is credited towards the soft limit, since that search effort carries over.
+    pub fn ponder_credit(&self, time_pondered: Duration) -> Duration {
+        time_pondered.mul_f64(self.time_params.ponder_credit)
+    }
+
     /// 'Node TM': scale the soft limit based on the fraction of nodes that have been spent searching
     /// the current best move.
     fn node_tm_scale(p: &TimeParams, depth: i32, nodes: u64, best_move_nodes: u64) -> f32 {
@@ -153,17 +214,112 @@ impl SearchLimits {
         p: &TimeParams,
         fischer: FischerTime,
         fm_clock: usize,
+        move_overhead: u64,
     ) -> (Duration, Duration) {
-        let (time, inc) = (fischer.0, fischer.1 as f64);
-        let max_time = time.saturating_sub(UCI_OVERHEAD_MS);
+        let max_time = fischer.time.saturating_sub(move_overhead);
+        let (soft_bound, hard_bound) = match fischer.moves_to_go {
+            Some(mtg) if mtg > 0 => Self::mtg_time_limits(p, max_time, fischer.inc, mtg),
+            _ => Self::sudden_death_time_limits(p, max_time, fischer.inc, fm_clock),
+        };
+        (
+            Duration::from_millis(soft_bound),
+            Duration::from_millis(hard_bound),
+        )
+    }
+
+    /// Sudden death (plus increment): spend a fraction of the remaining time that grows with the
+    /// number of moves played, on the assumption that the game will last a fair while longer.
+    fn sudden_death_time_limits(
+        p: &TimeParams,
+        max_time: u64,
+        inc: u64,
+        fm_clock: usize,
+    ) -> (u64, u64) {
+        let inc = inc as f64;
         let soft_scale = p.soft_tm_base
             + p.soft_tm_scale * (1.0 - (-p.soft_tm_fm_scale * fm_clock as f64).exp());
         let soft_bound = (soft_scale * max_time as f64 + p.soft_tm_inc_scale * inc) as u64;
         let hard_bound =
             ((p.hard_tm_scale * max_time as f64 + p.hard_tm_inc_scale * inc) as u64).min(max_time);
+        (soft_bound, hard_bound)
+    }
+
+    /// Repeating time controls: split the remaining time evenly across the moves left until the
+    /// next control, keeping a small buffer of moves in reserve. As the control boundary approaches
+    /// the hard limit is allowed to take a larger share of the clock, since the time is about to be
+    /// topped up anyway.
+    fn mtg_time_limits(p: &TimeParams, max_time: u64, inc: u64, mtg: u64) -> (u64, u64) {
+        let (max_time, inc) = (max_time as f64, inc as f64);
+        let base = max_time / (mtg as f64 + p.mtg_buffer);
+        let hard_cap = if mtg == 1 { max_time } else { p.mtg_hard_max * max_time };
+        let hard_bound = (p.mtg_hard_scale * base + p.hard_tm_inc_scale * inc).min(hard_cap);
+        let soft_bound = (p.mtg_soft_scale * base + p.soft_tm_inc_scale * inc).min(hard_bound);
+        (soft_bound as u64, hard_bound as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The soft and hard limits, in milliseconds, for the given clock and move overhead.
+    fn limits(time: u64, inc: u64, moves_to_go: Option<u64>, move_overhead: u64) -> (u64, u64) {
+        let fischer = FischerTime {
+            time,
+            inc,
+            moves_to_go,
+        };
+        let limits = SearchLimits::new(Some(fischer), None, None, None, None, 20, move_overhead);
         (
-            Duration::from_millis(soft_bound),
-            Duration::from_millis(hard_bound),
+            limits.soft_time.unwrap().as_millis() as u64,
+            limits.hard_time.unwrap().as_millis() as u64,
         )
     }
+
+    #[test]
+    fn test_moves_to_go_limits() {
+        // With one move to go, the whole usable clock may be spent, as it is about to be topped up.
+        let (soft, hard) = limits(10_000, 0, Some(1), 50);
+        assert_eq!(hard, 9950);
+        assert!(soft > 0 && soft < hard, "soft {} hard {}", soft, hard);
+
+        // With many moves to go, each move gets a small share of the clock.
+        let (soft, hard) = limits(10_000, 0, Some(100), 50);
+        assert!(soft > 0 && soft <= hard, "soft {} hard {}", soft, hard);
+        assert!(hard < 9950 / 20, "hard {}", hard);
+        let (fewer_soft, fewer_hard) = limits(10_000, 0, Some(10), 50);
+        assert!(fewer_soft > soft && fewer_hard > hard);
+
+        // The increment adds to both limits.
+        let (soft, hard) = limits(10_000, 0, Some(10), 50);
+        let (inc_soft, inc_hard) = limits(10_000, 1000, Some(10), 50);
+        assert!(inc_soft > soft && inc_hard > hard);
+
+        // A movestogo of 0 falls back to the sudden death limits.
+        assert_eq!(limits(10_000, 0, Some(0), 50), limits(10_000, 0, None, 50));
+    }
+
+    #[test]
+    fn test_limits_stay_within_clock() {
+        for time in [200, 1000, 10_000, 600_000] {
+            for inc in [0, 100, 5000] {
+                for moves_to_go in [1, 2, 40, 1000] {
+                    for move_overhead in [0, 50, 150] {
+                        let (soft, hard) = limits(time, inc, Some(moves_to_go), move_overhead);
+                        let usable = time - move_overhead;
+                        assert!(
+                            soft <= hard && hard <= usable,
+                            "time {} inc {} mtg {} overhead {}: soft {} hard {}",
+                            time,
+                            inc,
+                            moves_to_go,
+                            move_overhead,
+                            soft,
+                            hard
+                        );
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,111 @@
+use crate::board::piece::Piece::{Bishop, Knight, Pawn, Queen, Rook};
+use crate::board::Board;
+use crate::search::score;
+
+/// Win-Draw-Loss Model
+/// The probability of winning from a given score is modelled as a logistic function of the score,
+/// whose centre (a) and spread (b) are cubic polynomials in the amount of material on the board.
+///
+/// The coefficients are provisional. They were fitted by `wdl_fit <data> epochs 60000` to the
+/// positions of 300 self-play games from `datagen games 300 nodes 5000 seed 7`, but no trained
+/// network was at hand, so the games were played with a randomly initialised one and the fit says
+/// nothing about the embedded network. Redo it on games of the embedded network, with the same
+/// two commands, and set `WDL_FITTED` once the coefficients can be trusted.
+#[rustfmt::skip]
+pub(crate) const WDL_A: [f64; 4] = [290.1, -427.6, 168.8, 3.7];
+#[rustfmt::skip]
+pub(crate) const WDL_B: [f64; 4] = [1301.4, -1970.3, 948.6, -88.8];
+
+/// Whether the coefficients are fitted to the embedded network. Until they are, reported scores
+/// are not normalised, as the model would distort them.
+const WDL_FITTED: bool = false;
+
+/// The material count the model is anchored on (the material of the starting position is 78).
+const WDL_MATERIAL_ANCHOR: i32 = 58;
+const WDL_MATERIAL_MIN: i32 = 17;
+const WDL_MATERIAL_MAX: i32 = 78;
+
+/// Count the material on the board, using the classical piece values.
+pub fn material(board: &Board) -> i32 {
+    board.pieces(Pawn).count() as i32
+        + 3 * board.pieces(Knight).count() as i32
+        + 3 * board.pieces(Bishop).count() as i32
+        + 5 * board.pieces(Rook).count() as i32
+        + 9 * board.pieces(Queen).count() as i32
+}
+
+/// The win-draw-loss probabilities for a score, in per mille, from the side to move's perspective.
+pub fn wdl(score: i32, material: i32) -> (u32, u32, u32) {
+    if score::is_mating(score) {
+        return (1000, 0, 0);
+    }
+    if score::is_mated(score) {
+        return (0, 0, 1000);
+    }
+    let win = win_rate(score, material);
+    let loss = win_rate(-score, material).min(1000 - win);
+    (win, 1000 - win - loss, loss)
+}
+
+/// Normalise a score so that 100 cp corresponds to a 50% chance of winning. Mate scores are
+/// returned unchanged, and so are all scores while the model is not fitted.
+pub fn normalise(score: i32, material: i32) -> i32 {
+    if !WDL_FITTED || score::is_mate(score) || score == score::DRAW {
+        return score;
+    }
+    let (a, _) = wdl_params(material);
+    (score as f64 * 100.0 / a).round() as i32
+}
+
+fn win_rate(score: i32, material: i32) -> u32 {
+    let (a, b) = wdl_params(material);
+    (1000.0 / (1.0 + ((a - score as f64) / b).exp())).round() as u32
+}
+
+fn wdl_params(material: i32) -> (f64, f64) {
+    let m = material_ratio(material);
+    (polynomial(&WDL_A, m), polynomial(&WDL_B, m))
+}
+
+/// The material count as a fraction of the anchor, which the model's polynomials take as input.
+pub(crate) fn material_ratio(material: i32) -> f64 {
+    material.clamp(WDL_MATERIAL_MIN, WDL_MATERIAL_MAX) as f64 / WDL_MATERIAL_ANCHOR as f64
+}
+
+/// Evaluate a cubic polynomial, with its coefficients ordered from the highest power.
+pub(crate) fn polynomial(coefficients: &[f64; 4], x: f64) -> f64 {
+    coefficients.iter().fold(0.0, |acc, &c| acc * x + c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wdl_sums_to_1000() {
+        for material in [0, WDL_MATERIAL_MIN, 30, WDL_MATERIAL_ANCHOR, 90] {
+            for score in (-3000..=3000).step_by(7) {
+                let (win, draw, loss) = wdl(score, material);
+                assert_eq!(
+                    win + draw + loss,
+                    1000,
+                    "score {} material {}",
+                    score,
+                    material
+                );
+            }
+        }
+        assert_eq!(wdl(score::mate_in(5), 40), (1000, 0, 0));
+        assert_eq!(wdl(score::mated_in(5), 40), (0, 0, 1000));
+    }
+
+    #[test]
+    fn test_normalise_anchor() {
+        let (a, _) = wdl_params(WDL_MATERIAL_ANCHOR);
+        let expected = if WDL_FITTED { 100 } else { a.round() as i32 };
+        assert_eq!(normalise(a.round() as i32, WDL_MATERIAL_ANCHOR), expected);
+        assert_eq!(normalise(0, WDL_MATERIAL_ANCHOR), 0);
+        let (win, _, _) = wdl(a.round() as i32, WDL_MATERIAL_ANCHOR);
+        assert!((495..=505).contains(&win), "win rate {} at the anchor", win);
+    }
+}
@@ -1,11 +1,14 @@
+use crate::board::moves::Move;
 use crate::board::Board;
 use crate::print_measurements;
+use crate::search::engine::Engine;
 use crate::search::search;
-use crate::search::thread::ThreadData;
 use crate::search::time::SearchLimits;
+use crate::tools::json::{build_info, Json};
+use std::fs;
 use std::time::Instant;
 
-const BENCH_DEPTH: u64 = 12;
+pub const BENCH_DEPTH: u64 = 12;
 
 const FENS: [&str; 50] = [
     "r3k2r/2pb1ppp/2pp1q2/p7/1nP1B3/1P2P3/P2N1PPP/R2QK2R w KQkq a6 0 14",
@@ -60,38 +63,214 @@ const FENS: [&str; 50] = [
     "2r2b2/5p2/5k2/p1r1pP2/P2pB3/1P3P2/K1P3R1/7R w - - 23 93",
 ];
 
-pub fn bench(td: &mut ThreadData) {
-    let mut nodes: u64 = 0;
-    let mut time: u64 = 0;
-    td.clear();
-    td.limits = SearchLimits::new(None, None, None, None, Some(BENCH_DEPTH), 0);
-    let minimal_enabled = td.minimal_output;
-    td.minimal_output = true;
+/// The bench settings. By default, the built-in positions are searched to `BENCH_DEPTH` on a
+/// single thread with the engine's current hash size, which gives the node count used as the
+/// signature of a build.
+pub struct BenchConfig {
+    pub depth: u64,
+    pub hash_mb: Option<usize>,
+    pub threads: usize,
+    pub fen_file: Option<String>,
+    /// Print a single JSON document in place of the human-readable output.
+    pub json: bool,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            depth: BENCH_DEPTH,
+            hash_mb: None,
+            threads: 1,
+            fen_file: None,
+            json: false,
+        }
+    }
+}
+
+/// The outcome of searching a single bench position.
+pub struct PositionResult {
+    pub fen: String,
+    pub nodes: u64,
+    pub time_ms: u64,
+    pub depth: i32,
+    pub score: i32,
+    pub best_move: Move,
+}
+
+pub struct BenchResult {
+    pub positions: Vec<PositionResult>,
+    pub nodes: u64,
+    pub time_ms: u64,
+    pub nps: u64,
+}
+
+/// Search each bench position from a clean slate and report the nodes searched. A single thread
+/// searches on the main thread directly, while multiple threads go through `Engine::go`, so that
+/// bench measures the same thread pool as a real search. The engine's hash size and thread count
+/// are restored afterwards.
+pub fn bench(engine: &mut Engine, config: &BenchConfig) -> Result<BenchResult, String> {
+    let fens = match &config.fen_file {
+        Some(path) => read_fens(path)?,
+        None => FENS.iter().map(|fen| fen.to_string()).collect(),
+    };
+    let boards = fens
+        .iter()
+        .map(|fen| Board::from_fen(fen).map_err(|e| format!("invalid fen '{}': {}", fen, e)))
+        .collect::<Result<Vec<Board>, String>>()?;
+
+    let previous_hash_mb = engine.hash_mb();
+    let previous_threads = engine.num_threads();
+    let minimal_enabled = engine.td().minimal_output;
+    if let Some(hash_mb) = config.hash_mb {
+        engine.set_hash(hash_mb);
+    }
+    engine.set_threads(config.threads);
+    engine.set_minimal_output(true);
+    engine.set_quiet(config.json);
+    engine.new_game();
+
+    let limits = SearchLimits::new(None, None, None, None, Some(config.depth), 0, 0);
+    let mut positions = Vec::with_capacity(boards.len());
     let start = Instant::now();
 
-    for fen in FENS {
-        println!("fen: {}", fen);
-        let board = Board::from_fen(fen).unwrap();
-        td.reset();
-        td.start_time = Instant::now();
-        search(&board, td);
-        println!("bestmove {}\n", td.best_move.to_uci());
-        nodes += td.nodes();
-        time += td.start_time.elapsed().as_millis() as u64;
+    for (fen, board) in fens.into_iter().zip(boards) {
+        if !config.json {
+            println!("fen: {}", fen);
+        }
+        let search_start = Instant::now();
+        if config.threads > 1 {
+            engine.go(board, limits.clone());
+            engine.join();
+            if !config.json {
+                println!();
+            }
+        } else {
+            let td = engine.td_mut();
+            td.limits = limits.clone();
+            td.reset();
+            td.start_time = search_start;
+            search(&board, td);
+            if !config.json {
+                println!("bestmove {}\n", td.best_move.to_uci());
+            }
+        }
+        let td = engine.td();
+        positions.push(PositionResult {
+            fen,
+            nodes: td.nodes(),
+            time_ms: search_start.elapsed().as_millis() as u64,
+            depth: td.completed_depth,
+            score: td.best_score,
+            best_move: td.best_move,
+        });
     }
 
     let end = Instant::now();
 
     print_measurements!();
-    td.minimal_output = minimal_enabled;
-    td.clear();
+    engine.set_minimal_output(minimal_enabled);
+    engine.set_quiet(false);
+    engine.set_threads(previous_threads);
+    if config.hash_mb.is_some() {
+        engine.set_hash(previous_hash_mb);
+    }
+    engine.new_game();
+
+    let nodes = positions.iter().map(|pos| pos.nodes).sum::<u64>();
+    let time = positions.iter().map(|pos| pos.time_ms).sum::<u64>();
+    let nps = (nodes / time.max(1)) * 1000;
+    let hash_mb = config.hash_mb.unwrap_or(previous_hash_mb);
+
+    let result = BenchResult {
+        positions,
+        nodes,
+        time_ms: time,
+        nps,
+    };
+    if config.json {
+        println!("{}", result.to_json(config, hash_mb));
+        return Ok(result);
+    }
+    let positions = &result.positions;
+
+    println!(
+        "{:>3} {:>12} {:>9} {:>10} {:>5} {:>7}  {:<8} fen",
+        "#", "nodes", "time", "nps", "depth", "score", "bestmove"
+    );
+    for (i, pos) in positions.iter().enumerate() {
+        println!(
+            "{:>3} {:>12} {:>9} {:>10} {:>5} {:>7}  {:<8} {}",
+            i + 1,
+            pos.nodes,
+            pos.time_ms,
+            pos.nodes * 1000 / pos.time_ms.max(1),
+            pos.depth,
+            pos.score,
+            pos.best_move.to_uci(),
+            pos.fen
+        );
+    }
+    println!(
+        "\n{} positions, depth {}, {} threads, {} MB hash",
+        positions.len(),
+        config.depth,
+        config.threads,
+        hash_mb
+    );
 
     let seconds = end.duration_since(start).as_secs_f64();
     println!("{:.4} seconds\n", seconds);
 
-    let nps = (nodes / time) * 1000;
     println!("{} nodes {} nps", nodes, nps);
 
     #[cfg(feature = "track_l0_activations")]
     crate::evaluation::sparse::dump_activation_counts();
+
+    Ok(result)
+}
+
+impl BenchResult {
+    fn to_json(&self, config: &BenchConfig, hash_mb: usize) -> Json {
+        let positions = self
+            .positions
+            .iter()
+            .map(|pos| {
+                Json::object([
+                    ("fen", pos.fen.as_str().into()),
+                    ("nodes", pos.nodes.into()),
+                    ("time_ms", pos.time_ms.into()),
+                    ("nps", (pos.nodes * 1000 / pos.time_ms.max(1)).into()),
+                    ("depth", pos.depth.into()),
+                    ("score", pos.score.into()),
+                    ("bestmove", pos.best_move.to_uci().into()),
+                ])
+            })
+            .collect::<Vec<Json>>();
+        Json::object([
+            ("build", build_info()),
+            ("depth", config.depth.into()),
+            ("threads", config.threads.into()),
+            ("hash_mb", hash_mb.into()),
+            ("nodes", self.nodes.into()),
+            ("time_ms", self.time_ms.into()),
+            ("nps", self.nps.into()),
+            ("positions", positions.into()),
+        ])
+    }
+}
+
+/// Read the positions from a file with one FEN per line, ignoring blank lines.
+fn read_fens(path: &str) -> Result<Vec<String>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let fens: Vec<String> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+    if fens.is_empty() {
+        return Err(format!("{} contains no positions", path));
+    }
+    Ok(fens)
 }
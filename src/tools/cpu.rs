@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicU8, Ordering::Relaxed};
+
+/// The SIMD instruction set used by the NNUE inference and setwise move generation kernels.
+///
+/// Builds targeting a CPU with AVX2 or NEON select the backend at compile time. Portable x86-64
+/// builds (compiled without AVX2) include both the AVX-512 and AVX2 kernels, and select the best
+/// backend the CPU supports at startup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SimdBackend {
+    Scalar = 0,
+    Avx2 = 1,
+    Avx512 = 2,
+    Neon = 3,
+}
+
+static BACKEND: AtomicU8 = AtomicU8::new(compile_time_backend() as u8);
+
+/// Detect the SIMD backend to use. Must be called at startup, before any evaluation.
+pub fn init() {
+    BACKEND.store(detect() as u8, Relaxed);
+}
+
+#[inline(always)]
+pub fn simd_backend() -> SimdBackend {
+    match BACKEND.load(Relaxed) {
+        1 => SimdBackend::Avx2,
+        2 => SimdBackend::Avx512,
+        3 => SimdBackend::Neon,
+        _ => SimdBackend::Scalar,
+    }
+}
+
+impl SimdBackend {
+    pub fn name(self) -> &'static str {
+        match self {
+            SimdBackend::Scalar => "scalar",
+            SimdBackend::Avx2 => "avx2",
+            SimdBackend::Avx512 => "avx512",
+            SimdBackend::Neon => "neon",
+        }
+    }
+}
+
+const fn compile_time_backend() -> SimdBackend {
+    if cfg!(target_feature = "avx512f") {
+        SimdBackend::Avx512
+    } else if cfg!(target_feature = "avx2") {
+        SimdBackend::Avx2
+    } else if cfg!(target_feature = "neon") {
+        SimdBackend::Neon
+    } else {
+        SimdBackend::Scalar
+    }
+}
+
+#[cfg(simd_dispatch)]
+fn detect() -> SimdBackend {
+    if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw") {
+        SimdBackend::Avx512
+    } else if is_x86_feature_detected!("avx2") {
+        SimdBackend::Avx2
+    } else {
+        SimdBackend::Scalar
+    }
+}
+
+#[cfg(not(simd_dispatch))]
+fn detect() -> SimdBackend {
+    compile_time_backend()
+}
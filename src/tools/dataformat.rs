@@ -0,0 +1,780 @@
+//! Encoding and decoding of training data. Three formats are supported:
+//!
+//! - Text: one position per line, as `<fen> | <score> | <result>`, with the score in centipawns
+//!   and the result (`1.0`, `0.5` or `0.0`) both from white's perspective.
+//! - Bulletformat: bullet's 32-byte `ChessBoard`, one per position. The board is stored relative
+//!   to the side to move (flipped if black is to move), along with the score and result from the
+//!   side to move's perspective. Castling rights, en passant and the move counters are not stored.
+//! - Viriformat: one record per game, consisting of a 32-byte marlinformat `PackedBoard` holding
+//!   the starting position and result, followed by a `(move, score)` pair for every move played,
+//!   with scores from white's perspective, and terminated by four zero bytes.
+//!
+//! All integers are little-endian.
+
+use crate::board::bitboard::Bitboard;
+use crate::board::castling::{self, Rights};
+use crate::board::movegen::MoveFilter;
+use crate::board::moves::{Move, MoveFlag, MoveList};
+use crate::board::observer::NullBoardObserver;
+use crate::board::piece::{Piece, PIECES};
+use crate::board::rank::Rank;
+use crate::board::side::Side;
+use crate::board::side::Side::{Black, White};
+use crate::board::square::Square;
+use crate::board::zobrist::Hashes;
+use crate::board::Board;
+use crate::search::score::is_mate;
+use std::fs::File as FsFile;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataFormat {
+    Text,
+    Bullet,
+    Viri,
+}
+
+impl DataFormat {
+    pub fn parse(name: &str) -> Option<DataFormat> {
+        match name {
+            "text" | "txt" => Some(DataFormat::Text),
+            "bullet" | "bulletformat" => Some(DataFormat::Bullet),
+            "viri" | "viriformat" => Some(DataFormat::Viri),
+            _ => None,
+        }
+    }
+}
+
+/// The result of a game, from white's perspective.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    WhiteWin,
+    Draw,
+    BlackWin,
+}
+
+impl GameResult {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GameResult::WhiteWin => "1.0",
+            GameResult::Draw => "0.5",
+            GameResult::BlackWin => "0.0",
+        }
+    }
+
+    pub fn parse(result: &str) -> Option<GameResult> {
+        match result.trim().trim_start_matches('[').trim_end_matches(']') {
+            "1.0" | "1" | "1-0" => Some(GameResult::WhiteWin),
+            "0.5" | "1/2-1/2" => Some(GameResult::Draw),
+            "0.0" | "0" | "0-1" => Some(GameResult::BlackWin),
+            _ => None,
+        }
+    }
+
+    /// The result as a score between 0 and 1, from the given side's perspective.
+    pub fn score(self, side: Side) -> f64 {
+        self.wdl(side) as f64 / 2.0
+    }
+
+    /// The result encoded as 0 (loss), 1 (draw) or 2 (win), from the given side's perspective.
+    fn wdl(self, side: Side) -> u8 {
+        let wdl = match self {
+            GameResult::WhiteWin => 2,
+            GameResult::Draw => 1,
+            GameResult::BlackWin => 0,
+        };
+        if side == White {
+            wdl
+        } else {
+            2 - wdl
+        }
+    }
+
+    fn from_wdl(wdl: u8, side: Side) -> Option<GameResult> {
+        let wdl = if side == White {
+            wdl
+        } else {
+            2u8.checked_sub(wdl)?
+        };
+        match wdl {
+            2 => Some(GameResult::WhiteWin),
+            1 => Some(GameResult::Draw),
+            0 => Some(GameResult::BlackWin),
+            _ => None,
+        }
+    }
+}
+
+/// A single training position, with the score and result from white's perspective.
+#[derive(Clone, Copy)]
+pub struct TrainingPosition {
+    pub board: Board,
+    pub score: i16,
+    pub result: GameResult,
+}
+
+impl TrainingPosition {
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} | {} | {}",
+            self.board.to_fen(),
+            self.score,
+            self.result.as_str()
+        )
+    }
+
+    pub fn from_text(line: &str) -> Result<TrainingPosition, String> {
+        let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+        if parts.len() != 3 {
+            return Err(format!(
+                "expected '<fen> | <score> | <result>', found '{}'",
+                line
+            ));
+        }
+        let board = Board::from_fen(parts[0])?;
+        let score = parts[1]
+            .parse::<i32>()
+            .map_err(|_| format!("invalid score '{}'", parts[1]))?;
+        let result = GameResult::parse(parts[2]).ok_or(format!("invalid result '{}'", parts[2]))?;
+        Ok(TrainingPosition {
+            board,
+            score: clamp_score(score),
+            result,
+        })
+    }
+}
+
+/// Whether a position is worth training on: positions in check, positions where the best move is
+/// noisy, and positions with a mate score are all poor training targets for a static evaluation.
+pub fn is_quiet_position(board: &Board, best_move: &Move, score: i32) -> bool {
+    board.checkers.is_empty() && !board.is_noisy(best_move) && !is_mate(score)
+}
+
+/// Saturate a search score into the 16 bits available in the binary formats.
+pub fn clamp_score(score: i32) -> i16 {
+    score.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+/// Bullet's `ChessBoard` layout. The pieces are stored as 4-bit codes in the order of the occupied
+/// squares, where the low three bits are the piece type and the high bit is set for the opponent's
+/// pieces.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BulletBoard {
+    pub occ: u64,
+    pub pcs: [u8; 16],
+    pub score: i16,
+    pub result: u8,
+    pub ksq: u8,
+    pub opp_ksq: u8,
+    pub extra: [u8; 3],
+}
+
+impl BulletBoard {
+    pub const SIZE: usize = 32;
+
+    pub fn from_position(pos: &TrainingPosition) -> BulletBoard {
+        let board = &pos.board;
+        let stm = board.stm;
+        // Flip the board vertically if black is to move, so the side to move is always 'white'.
+        let relative = |sq: Square| if stm == White { sq } else { sq.flip_rank() };
+
+        let mut occ = 0u64;
+        for sq in board.occ() {
+            occ |= 1 << relative(sq).0;
+        }
+
+        let mut pcs = [0u8; 16];
+        for (i, sq) in Bitboard(occ).enumerate() {
+            let abs_sq = relative(sq);
+            let piece = board.piece_at(abs_sq).unwrap() as u8;
+            let colour = u8::from(board.side_at(abs_sq) != Some(stm));
+            pcs[i / 2] |= ((colour << 3) | piece) << (4 * (i & 1));
+        }
+
+        let score = if stm == White {
+            pos.score
+        } else {
+            pos.score.saturating_neg()
+        };
+        BulletBoard {
+            occ,
+            pcs,
+            score,
+            result: pos.result.wdl(stm),
+            ksq: relative(board.king_sq(stm)).0,
+            opp_ksq: relative(board.king_sq(!stm)).0 ^ 56,
+            extra: [0; 3],
+        }
+    }
+
+    /// Decode the position. The side to move is not stored, so the board is returned from the side
+    /// to move's perspective, with white to move, and the score and result relative to that.
+    pub fn to_position(&self) -> Result<TrainingPosition, String> {
+        let mut board = Board::empty();
+        for (i, sq) in Bitboard(self.occ).enumerate() {
+            let code = (self.pcs[i / 2] >> (4 * (i & 1))) & 0xF;
+            let piece = piece_from_code(code & 0b111)?;
+            let side = if code & 0b1000 == 0 { White } else { Black };
+            board.toggle_sq(sq, piece, side);
+        }
+        board.stm = White;
+        finish_board(&mut board)?;
+        let result = GameResult::from_wdl(self.result, White)
+            .ok_or(format!("invalid result {}", self.result))?;
+        Ok(TrainingPosition {
+            board,
+            score: self.score,
+            result,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.occ.to_le_bytes());
+        bytes[8..24].copy_from_slice(&self.pcs);
+        bytes[24..26].copy_from_slice(&self.score.to_le_bytes());
+        bytes[26] = self.result;
+        bytes[27] = self.ksq;
+        bytes[28] = self.opp_ksq;
+        bytes[29..32].copy_from_slice(&self.extra);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> BulletBoard {
+        BulletBoard {
+            occ: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            pcs: bytes[8..24].try_into().unwrap(),
+            score: i16::from_le_bytes(bytes[24..26].try_into().unwrap()),
+            result: bytes[26],
+            ksq: bytes[27],
+            opp_ksq: bytes[28],
+            extra: bytes[29..32].try_into().unwrap(),
+        }
+    }
+}
+
+/// Marlinformat's `PackedBoard`, used as the header of each viriformat game. The pieces are stored
+/// as 4-bit codes in the order of the occupied squares, where the low three bits are the piece type
+/// (with 6 meaning a rook that can still castle) and the high bit is set for black pieces.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PackedBoard {
+    pub occ: u64,
+    pub pieces: u128,
+    pub stm_ep: u8,
+    pub halfmove_clock: u8,
+    pub fullmove_number: u16,
+    pub eval: i16,
+    pub wdl: u8,
+    pub extra: u8,
+}
+
+const UNMOVED_ROOK: u8 = 6;
+const NO_EP_SQUARE: u8 = 64;
+
+impl PackedBoard {
+    pub const SIZE: usize = 32;
+
+    /// Pack a board, with the score and result from white's perspective.
+    pub fn from_board(board: &Board, eval: i16, result: GameResult) -> PackedBoard {
+        let castle_rooks = castle_rook_squares(board);
+        let mut pieces = 0u128;
+        for (i, sq) in board.occ().enumerate() {
+            let piece = board.piece_at(sq).unwrap();
+            let side = board.side_at(sq).unwrap();
+            let code = if piece == Piece::Rook && castle_rooks.contains(sq) {
+                UNMOVED_ROOK
+            } else {
+                piece as u8
+            };
+            let colour = u8::from(side == Black);
+            pieces |= (((colour << 3) | code) as u128) << (4 * i);
+        }
+
+        let ep = board.ep_sq.map_or(NO_EP_SQUARE, |sq| sq.0);
+        PackedBoard {
+            occ: board.occ().0,
+            pieces,
+            stm_ep: (u8::from(board.stm == Black) << 7) | ep,
+            halfmove_clock: board.hm,
+            fullmove_number: board.fm as u16,
+            eval,
+            wdl: result.wdl(White),
+            extra: 0,
+        }
+    }
+
+    pub fn to_board(&self) -> Result<Board, String> {
+        let mut board = Board::empty();
+        let mut castle_rooks = Bitboard::empty();
+        for (i, sq) in Bitboard(self.occ).enumerate() {
+            let code = ((self.pieces >> (4 * i)) & 0xF) as u8;
+            let side = if code & 0b1000 == 0 { White } else { Black };
+            let piece = if code & 0b111 == UNMOVED_ROOK {
+                castle_rooks |= sq;
+                Piece::Rook
+            } else {
+                piece_from_code(code & 0b111)?
+            };
+            board.toggle_sq(sq, piece, side);
+        }
+
+        board.stm = if self.stm_ep >> 7 == 0 { White } else { Black };
+        let ep = self.stm_ep & 0x7F;
+        board.ep_sq = (ep < NO_EP_SQUARE).then_some(Square(ep));
+        board.hm = self.halfmove_clock;
+        board.fm = self.fullmove_number.min(u8::MAX as u16) as u8;
+        board.rights = castle_rights(&board, castle_rooks)?;
+        board.frc = castling::requires_frc(&board);
+        finish_board(&mut board)?;
+        Ok(board)
+    }
+
+    pub fn result(&self) -> Result<GameResult, String> {
+        GameResult::from_wdl(self.wdl, White).ok_or(format!("invalid result {}", self.wdl))
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.occ.to_le_bytes());
+        bytes[8..24].copy_from_slice(&self.pieces.to_le_bytes());
+        bytes[24] = self.stm_ep;
+        bytes[25] = self.halfmove_clock;
+        bytes[26..28].copy_from_slice(&self.fullmove_number.to_le_bytes());
+        bytes[28..30].copy_from_slice(&self.eval.to_le_bytes());
+        bytes[30] = self.wdl;
+        bytes[31] = self.extra;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> PackedBoard {
+        PackedBoard {
+            occ: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            pieces: u128::from_le_bytes(bytes[8..24].try_into().unwrap()),
+            stm_ep: bytes[24],
+            halfmove_clock: bytes[25],
+            fullmove_number: u16::from_le_bytes(bytes[26..28].try_into().unwrap()),
+            eval: i16::from_le_bytes(bytes[28..30].try_into().unwrap()),
+            wdl: bytes[30],
+            extra: bytes[31],
+        }
+    }
+}
+
+/// A game in viriformat: the starting position and result, and every move played with its score.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ViriGame {
+    pub initial: PackedBoard,
+    pub moves: Vec<(u16, i16)>,
+}
+
+impl ViriGame {
+    pub fn new(board: &Board, result: GameResult) -> ViriGame {
+        ViriGame {
+            initial: PackedBoard::from_board(board, 0, result),
+            moves: Vec::new(),
+        }
+    }
+
+    /// Record a move played from the given position, with its score from white's perspective.
+    pub fn push(&mut self, board: &Board, mv: &Move, score: i16) {
+        self.moves.push((encode_viri_move(board, mv), score));
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.initial.to_bytes())?;
+        for (mv, score) in &self.moves {
+            writer.write_all(&mv.to_le_bytes())?;
+            writer.write_all(&score.to_le_bytes())?;
+        }
+        writer.write_all(&[0; 4])
+    }
+
+    /// Read the next game, or `None` at the end of the input.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<ViriGame>> {
+        let mut header = [0u8; PackedBoard::SIZE];
+        if !read_exact_or_eof(reader, &mut header)? {
+            return Ok(None);
+        }
+        let initial = PackedBoard::from_bytes(&header);
+        let mut moves = Vec::new();
+        loop {
+            let mut entry = [0u8; 4];
+            reader.read_exact(&mut entry)?;
+            if entry == [0; 4] {
+                break;
+            }
+            let mv = u16::from_le_bytes([entry[0], entry[1]]);
+            let score = i16::from_le_bytes([entry[2], entry[3]]);
+            moves.push((mv, score));
+        }
+        Ok(Some(ViriGame { initial, moves }))
+    }
+
+    /// Replay the game, returning each position along with the move played from it and its score.
+    pub fn positions(&self) -> Result<Vec<(TrainingPosition, Move)>, String> {
+        let result = self.initial.result()?;
+        let mut board = self.initial.to_board()?;
+        let mut positions = Vec::with_capacity(self.moves.len());
+        for &(viri_mv, score) in &self.moves {
+            let mv = decode_viri_move(&board, viri_mv)?;
+            positions.push((
+                TrainingPosition {
+                    board,
+                    score,
+                    result,
+                },
+                mv,
+            ));
+            board.make(&mv, &mut NullBoardObserver);
+        }
+        Ok(positions)
+    }
+}
+
+pub struct ConvertStats {
+    pub read: u64,
+    pub written: u64,
+}
+
+/// Convert a training data file from one format to another. Viriformat games are expanded into
+/// their quiet positions (see `is_quiet_position`) when converted to a per-position format, while
+/// viriformat output can only be produced from viriformat input, as the other formats carry no
+/// move stream. Returns the number of records read and written.
+pub fn convert(
+    input: &str,
+    from: DataFormat,
+    output: &str,
+    to: DataFormat,
+) -> Result<ConvertStats, String> {
+    if to == DataFormat::Viri && from != DataFormat::Viri {
+        return Err(
+            "viriformat can only be converted from viriformat, as the other formats \
+                    have no move stream"
+                .to_string(),
+        );
+    }
+    let output_file =
+        FsFile::create(output).map_err(|e| format!("could not create {}: {}", output, e))?;
+    let mut writer = BufWriter::new(output_file);
+    let write_err = |e: io::Error| format!("could not write to {}: {}", output, e);
+
+    let mut written = 0;
+    let read = if to == DataFormat::Viri {
+        let input_file =
+            FsFile::open(input).map_err(|e| format!("could not open {}: {}", input, e))?;
+        let mut reader = BufReader::new(input_file);
+        let mut read = 0;
+        while let Some(game) = ViriGame::read_from(&mut reader)
+            .map_err(|e| format!("could not read {}: {}", input, e))?
+        {
+            read += 1;
+            game.write_to(&mut writer).map_err(write_err)?;
+            written += 1;
+        }
+        read
+    } else {
+        for_each_position(input, from, |pos, mv| {
+            if let Some(mv) = mv {
+                let stm_score = if pos.board.stm == White {
+                    pos.score
+                } else {
+                    -pos.score
+                };
+                if !is_quiet_position(&pos.board, &mv, stm_score as i32) {
+                    return Ok(());
+                }
+            }
+            write_position(&mut writer, pos, to).map_err(write_err)?;
+            written += 1;
+            Ok(())
+        })?
+    };
+
+    writer.flush().map_err(write_err)?;
+    Ok(ConvertStats { read, written })
+}
+
+/// Stream every position in a training data file, along with the move played from it if the
+/// format records one, which only viriformat does. Returns the number of records read, which for
+/// viriformat is the number of games.
+pub fn for_each_position<F>(input: &str, from: DataFormat, mut f: F) -> Result<u64, String>
+where
+    F: FnMut(&TrainingPosition, Option<Move>) -> Result<(), String>,
+{
+    let input_file = FsFile::open(input).map_err(|e| format!("could not open {}: {}", input, e))?;
+    let mut reader = BufReader::new(input_file);
+    let read_err = |e: io::Error| format!("could not read {}: {}", input, e);
+    let mut read = 0;
+
+    match from {
+        DataFormat::Text => {
+            for (i, line) in reader.lines().enumerate() {
+                let line = line.map_err(read_err)?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let pos = TrainingPosition::from_text(&line)
+                    .map_err(|e| format!("{} line {}: {}", input, i + 1, e))?;
+                read += 1;
+                f(&pos, None)?;
+            }
+        }
+        DataFormat::Bullet => {
+            let mut bytes = [0u8; BulletBoard::SIZE];
+            while read_exact_or_eof(&mut reader, &mut bytes).map_err(read_err)? {
+                let pos = BulletBoard::from_bytes(&bytes)
+                    .to_position()
+                    .map_err(|e| format!("{} record {}: {}", input, read + 1, e))?;
+                read += 1;
+                f(&pos, None)?;
+            }
+        }
+        DataFormat::Viri => {
+            while let Some(game) = ViriGame::read_from(&mut reader).map_err(read_err)? {
+                read += 1;
+                let positions = game
+                    .positions()
+                    .map_err(|e| format!("{} game {}: {}", input, read, e))?;
+                for (pos, mv) in positions {
+                    f(&pos, Some(mv))?;
+                }
+            }
+        }
+    }
+    Ok(read)
+}
+
+/// Write a single position in one of the per-position formats.
+pub fn write_position<W: Write>(
+    writer: &mut W,
+    pos: &TrainingPosition,
+    to: DataFormat,
+) -> io::Result<()> {
+    match to {
+        DataFormat::Bullet => writer.write_all(&BulletBoard::from_position(pos).to_bytes()),
+        _ => writeln!(writer, "{}", pos.to_text()),
+    }
+}
+
+// Viriformat move encoding: the from and to squares in the low 12 bits, the promotion piece
+// (knight, bishop, rook, queen) in the next two bits, and the move type in the top two bits.
+// Castling moves are encoded as the king capturing its own rook.
+const VIRI_EP: u16 = 1 << 14;
+const VIRI_CASTLE: u16 = 2 << 14;
+const VIRI_PROMO: u16 = 3 << 14;
+
+pub fn encode_viri_move(board: &Board, mv: &Move) -> u16 {
+    let from = mv.from();
+    let to = if mv.is_castle() {
+        let file = match mv.flag() {
+            MoveFlag::CastleK => board.rights.kingside(board.stm),
+            _ => board.rights.queenside(board.stm),
+        };
+        file.map_or(mv.to(), |file| Square::from(file, from.rank()))
+    } else {
+        mv.to()
+    };
+    let base = from.0 as u16 | ((to.0 as u16) << 6);
+    match mv.promo_piece() {
+        Some(piece) => base | ((piece as u16 - Piece::Knight as u16) << 12) | VIRI_PROMO,
+        None if mv.is_ep() => base | VIRI_EP,
+        None if mv.is_castle() => base | VIRI_CASTLE,
+        None => base,
+    }
+}
+
+pub fn decode_viri_move(board: &Board, viri_mv: u16) -> Result<Move, String> {
+    let mut legal_moves = MoveList::new();
+    board.gen_moves(MoveFilter::All, &mut legal_moves);
+    let mv = legal_moves
+        .iter()
+        .map(|entry| entry.mv)
+        .find(|mv| encode_viri_move(board, mv) == viri_mv);
+    mv.ok_or(format!(
+        "illegal viriformat move {:#06x} in {}",
+        viri_mv,
+        board.to_fen()
+    ))
+}
+
+/// Read exactly enough bytes to fill the buffer, returning false if the input ended cleanly before
+/// the first byte.
+pub fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn piece_from_code(code: u8) -> Result<Piece, String> {
+    PIECES
+        .get(code as usize)
+        .copied()
+        .ok_or(format!("invalid piece code {}", code))
+}
+
+/// The squares of the rooks that can still castle.
+fn castle_rook_squares(board: &Board) -> Bitboard {
+    let mut rooks = Bitboard::empty();
+    for (side, rank) in [(White, Rank::One), (Black, Rank::Eight)] {
+        for file in [board.rights.kingside(side), board.rights.queenside(side)]
+            .into_iter()
+            .flatten()
+        {
+            rooks |= Square::from(file, rank);
+        }
+    }
+    rooks
+}
+
+/// Derive the castling rights from the rooks that can still castle, which must be on their back
+/// rank on either side of their king.
+fn castle_rights(board: &Board, castle_rooks: Bitboard) -> Result<Rights, String> {
+    let mut rights = Rights::default();
+    for sq in castle_rooks {
+        let side = board.side_at(sq).unwrap();
+        let king_sq = board.king_sq(side);
+        let back_rank = if side == White {
+            Rank::One
+        } else {
+            Rank::Eight
+        };
+        if sq.rank() != back_rank || king_sq.rank() != back_rank {
+            return Err("castling rook is not on the back rank with its king".to_string());
+        }
+        if sq.file() > king_sq.file() {
+            rights.set_kingside(side, sq.file());
+        } else {
+            rights.set_queenside(side, sq.file());
+        }
+    }
+    Ok(rights)
+}
+
+/// Compute the derived board state once all the pieces have been placed.
+fn finish_board(board: &mut Board) -> Result<(), String> {
+    if board.king(White).count() != 1 || board.king(Black).count() != 1 {
+        return Err("each side must have exactly one king".to_string());
+    }
+    board.hashes = Hashes::new(board);
+    board.threats = board.calc_threats(board.stm);
+    board.checkers = board.calc_checkers(board.stm);
+    board.pinned = board.calc_both_pinned();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use crate::tools::fen;
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    const FENS: [&str; 5] = [
+        fen::STARTPOS,
+        "r3k2r/2pb1ppp/2pp1q2/p7/1nP1B3/1P2P3/P2N1PPP/R2QK2R w KQkq a6 0 14",
+        "4rrk1/2p1b1p1/p1p3q1/4p3/2P2n1p/1P1NR2P/PB3PP1/3R1QK1 b - - 2 24",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+    ];
+
+    #[test]
+    fn test_text_round_trip() {
+        init();
+        let line = format!("{} | -123 | 0.5", FENS[1]);
+        let pos = TrainingPosition::from_text(&line).unwrap();
+        assert_eq!(pos.score, -123);
+        assert_eq!(pos.result, GameResult::Draw);
+        assert_eq!(pos.to_text(), line);
+    }
+
+    #[test]
+    fn test_packed_board_round_trip() {
+        init();
+        for fen in FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            board.set_frc(fen.contains("FBfb"));
+            let packed = PackedBoard::from_board(&board, 57, GameResult::WhiteWin);
+            let bytes = packed.to_bytes();
+            let decoded = PackedBoard::from_bytes(&bytes);
+            assert_eq!(packed, decoded);
+            let decoded_board = decoded.to_board().unwrap();
+            assert_eq!(decoded_board.to_fen(), board.to_fen());
+            assert_eq!(decoded_board.hash(), board.hash());
+            assert_eq!(decoded.result().unwrap(), GameResult::WhiteWin);
+        }
+    }
+
+    #[test]
+    fn test_bullet_board_is_relative_to_stm() {
+        init();
+        let board = Board::from_fen(FENS[2]).unwrap();
+        let pos = TrainingPosition {
+            board,
+            score: 75,
+            result: GameResult::WhiteWin,
+        };
+        let bullet = BulletBoard::from_bytes(&BulletBoard::from_position(&pos).to_bytes());
+        assert_eq!(bullet.score, -75);
+        assert_eq!(bullet.result, 0);
+
+        let decoded = bullet.to_position().unwrap();
+        let flipped = "3r1qk1/pb3pp1/1p1nr2p/2p2N1P/4P3/P1P3Q1/2P1B1P1/4RRK1 w - - 0 0";
+        assert_eq!(decoded.board.to_fen(), flipped);
+        assert_eq!(decoded.score, -75);
+        assert_eq!(decoded.result, GameResult::BlackWin);
+    }
+
+    #[test]
+    fn test_viri_game_round_trip() {
+        init();
+        let mut board = Board::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let mut game = ViriGame::new(&board, GameResult::BlackWin);
+        // Capture en passant, castle on both sides and promote, to cover every move type.
+        let moves = ["e5d6", "e8g8", "b7a8q", "f8a8", "e1c1"];
+        for (i, uci) in moves.iter().enumerate() {
+            let mut legal_moves = MoveList::new();
+            board.gen_moves(MoveFilter::All, &mut legal_moves);
+            let target = Move::parse_uci(uci);
+            let mv = legal_moves
+                .iter()
+                .map(|e| e.mv)
+                .find(|m| m.matches(&target))
+                .unwrap();
+            game.push(&board, &mv, i as i16 * 10);
+            board.make(&mv, &mut NullBoardObserver);
+        }
+
+        let mut bytes = Vec::new();
+        game.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), PackedBoard::SIZE + 4 * moves.len() + 4);
+
+        let decoded = ViriGame::read_from(&mut bytes.as_slice()).unwrap().unwrap();
+        assert_eq!(decoded, game);
+        let positions = decoded.positions().unwrap();
+        assert_eq!(positions.len(), moves.len());
+        for (i, (pos, mv)) in positions.iter().enumerate() {
+            assert_eq!(mv.to_uci(), moves[i]);
+            assert_eq!(pos.score, i as i16 * 10);
+            assert_eq!(pos.result, GameResult::BlackWin);
+        }
+        assert!(ViriGame::read_from(&mut &bytes[bytes.len()..])
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,11 +1,413 @@
 use crate::board::movegen::MoveFilter;
-use crate::board::moves::MoveList;
+use crate::board::moves::{Move, MoveList};
 use crate::board::observer::NullBoardObserver;
+use crate::board::side::Side::White;
 use crate::board::Board;
-use crate::search::thread::ThreadData;
+use crate::search::search;
+use crate::search::thread::{SharedContext, ThreadData};
+use crate::search::time::SearchLimits;
+use crate::tools::dataformat::{
+    clamp_score, is_quiet_position, write_position, DataFormat, GameResult, TrainingPosition,
+    ViriGame,
+};
 use crate::tools::fen;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The game is adjudicated as a win once the score has been at least this large, in favour of the
+/// same side, for this many consecutive plies.
+const WIN_ADJ_SCORE: i32 = 2500;
+const WIN_ADJ_PLIES: u32 = 4;
+
+/// The game is adjudicated as a draw once the score has been within this window for this many
+/// consecutive plies, provided the game has lasted at least the minimum number of plies.
+const DRAW_ADJ_SCORE: i32 = 10;
+const DRAW_ADJ_PLIES: u32 = 10;
+const DRAW_ADJ_MIN_PLY: usize = 80;
+
+/// The hard node limit for each search, as a multiple of the soft node limit.
+pub const HARD_NODES_SCALE: u64 = 10;
+
+/// How often progress is reported, by `datagen` and by `rescore`.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct DatagenConfig {
+    pub games: u64,
+    pub threads: usize,
+    pub soft_nodes: u64,
+    pub seed: u64,
+    pub dfrc_percent: u64,
+    pub random_moves: usize,
+    pub hash_mb: usize,
+    pub format: DataFormat,
+    pub output: String,
+}
+
+/// A move played during a game, with the position it was played from and its search score from
+/// white's perspective.
+struct PlayedMove {
+    board: Board,
+    mv: Move,
+    score: i32,
+}
+
+/// The output files shared between the datagen threads. The data file is written in any of the
+/// formats in `dataformat`. After each game is written, its index and the new length of the data
+/// file are appended to the progress file, so that an interrupted run can be resumed.
+struct Output {
+    data: File,
+    progress: File,
+    data_len: u64,
+    games: u64,
+    positions: u64,
+    last_report: Instant,
+}
+
+/// Generate training data by self-play. Each game starts from a random opening (standard or DFRC),
+/// and each move is chosen by a soft-node-limited search. Viriformat output records every move of
+/// the game with its score, while the per-position formats only record quiet positions, in both
+/// cases along with the final result of the game. Every game is derived from the seed and its
+/// index, so the same seed always produces the same games, regardless of the number of threads.
+/// If a progress file for the output exists, the games already completed are skipped.
+pub fn run_datagen(config: &DatagenConfig) -> Result<(), String> {
+    if config.dfrc_percent > 100 {
+        return Err(format!(
+            "dfrc must be a percentage, got {}",
+            config.dfrc_percent
+        ));
+    }
+
+    let progress_path = format!("{}.progress", config.output);
+    let Progress {
+        completed,
+        data_len,
+        progress_len,
+    } = read_progress(&config.output, &progress_path)?;
+
+    let data = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.output)
+        .map_err(|e| format!("could not open {}: {}", config.output, e))?;
+    // Discard anything written after the last completed game, e.g. if the previous run was killed.
+    data.set_len(data_len)
+        .map_err(|e| format!("could not truncate {}: {}", config.output, e))?;
+    let progress = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&progress_path)
+        .map_err(|e| format!("could not open {}: {}", progress_path, e))?;
+    progress
+        .set_len(progress_len)
+        .map_err(|e| format!("could not truncate {}: {}", progress_path, e))?;
+
+    if !completed.is_empty() {
+        println!(
+            "info string datagen resuming {} with {} of {} games completed",
+            config.output,
+            completed.len(),
+            config.games
+        );
+    }
+
+    let output = Mutex::new(Output {
+        data,
+        progress,
+        data_len,
+        games: completed.len() as u64,
+        positions: 0,
+        last_report: Instant::now(),
+    });
+    let next_game = AtomicU64::new(0);
+    let start = Instant::now();
+
+    // Each thread has its own transposition table, so that its searches are independent.
+    let threads = config.threads.max(1);
+    let mut workers: Vec<Box<ThreadData>> = (0..threads)
+        .map(|id| {
+            let shared = Arc::new(SharedContext::new(config.hash_mb));
+            let mut td = Box::new(ThreadData::new(
+                id,
+                true,
+                shared,
+                Arc::new(AtomicBool::new(false)),
+            ));
+            td.quiet = true;
+            td
+        })
+        .collect();
+
+    std::thread::scope(|s| -> Result<(), String> {
+        let handles: Vec<_> = workers
+            .iter_mut()
+            .map(|td| s.spawn(|| run_worker(td, config, &completed, &next_game, &output, start)))
+            .collect();
+        handles.into_iter().try_for_each(|h| {
+            h.join()
+                .map_err(|_| "datagen thread panicked".to_string())?
+        })
+    })?;
+
+    let output = output.lock().unwrap();
+    println!(
+        "info string datagen finished {}/{} games, {} new positions written to {}",
+        output.games, config.games, output.positions, config.output
+    );
+    Ok(())
+}
+
+fn run_worker(
+    td: &mut ThreadData,
+    config: &DatagenConfig,
+    completed: &HashSet<u64>,
+    next_game: &AtomicU64,
+    output: &Mutex<Output>,
+    start: Instant,
+) -> Result<(), String> {
+    loop {
+        let game = next_game.fetch_add(1, Relaxed);
+        if game >= config.games {
+            return Ok(());
+        }
+        if completed.contains(&game) {
+            continue;
+        }
+
+        let (start_board, moves, result) = play_game(td, config, game);
+        let (block, positions) = encode_game(config.format, &start_board, &moves, result);
+
+        let mut out = output.lock().unwrap();
+        out.data
+            .write_all(&block)
+            .and_then(|_| out.data.flush())
+            .map_err(|e| format!("could not write to {}: {}", config.output, e))?;
+        out.data_len += block.len() as u64;
+        let progress_line = format!("{} {}\n", game, out.data_len);
+        out.progress
+            .write_all(progress_line.as_bytes())
+            .and_then(|_| out.progress.flush())
+            .map_err(|e| format!("could not write progress for {}: {}", config.output, e))?;
+
+        out.games += 1;
+        out.positions += positions;
+        if out.last_report.elapsed() >= REPORT_INTERVAL || out.games == config.games {
+            let seconds = start.elapsed().as_secs_f64().max(0.001);
+            println!(
+                "info string datagen games {}/{} positions {} pos/s {:.0}",
+                out.games,
+                config.games,
+                out.positions,
+                out.positions as f64 / seconds
+            );
+            out.last_report = Instant::now();
+        }
+    }
+}
+
+/// Play a single game of self-play, returning the starting position, moves played and result.
+fn play_game(
+    td: &mut ThreadData,
+    config: &DatagenConfig,
+    game: u64,
+) -> (Board, Vec<PlayedMove>, GameResult) {
+    let mut rng = StdRng::seed_from_u64(game_seed(config.seed, game));
+    let dfrc = rng.random_range(0..100) < config.dfrc_percent;
+    let opening = generate_random_opening(td, &mut rng, config.random_moves, dfrc);
+    let mut board = Board::from_fen(&opening).unwrap();
+    board.set_frc(dfrc);
+
+    // Start every game from a clean slate, so that it does not depend on the games played before.
+    td.clear();
+    td.keys.push(board.hash());
+
+    let start_board = board;
+    let mut moves = Vec::new();
+    let mut win_plies = 0;
+    let mut loss_plies = 0;
+    let mut draw_plies = 0;
+    let mut ply = 0;
+
+    let result = loop {
+        if let Some(result) = game_over(&board, &td.keys) {
+            break result;
+        }
+
+        td.reset();
+        td.root_ply = td.keys.len() - 1;
+        td.tt().birthday();
+        let soft_nodes = config.soft_nodes;
+        let hard_nodes = soft_nodes * HARD_NODES_SCALE;
+        td.limits = SearchLimits::new(None, None, Some(soft_nodes), Some(hard_nodes), None, 0, 0);
+        td.start_time = Instant::now();
+        let (mv, score) = search(&board, td);
+
+        let white_score = if board.stm == White { score } else { -score };
+
+        moves.push(PlayedMove {
+            board,
+            mv,
+            score: white_score,
+        });
+
+        // Win adjudication
+        win_plies = if white_score >= WIN_ADJ_SCORE {
+            win_plies + 1
+        } else {
+            0
+        };
+        loss_plies = if white_score <= -WIN_ADJ_SCORE {
+            loss_plies + 1
+        } else {
+            0
+        };
+        if win_plies >= WIN_ADJ_PLIES {
+            break GameResult::WhiteWin;
+        }
+        if loss_plies >= WIN_ADJ_PLIES {
+            break GameResult::BlackWin;
+        }
+
+        // Draw adjudication
+        draw_plies = if ply >= DRAW_ADJ_MIN_PLY && white_score.abs() <= DRAW_ADJ_SCORE {
+            draw_plies + 1
+        } else {
+            0
+        };
+        if draw_plies >= DRAW_ADJ_PLIES {
+            break GameResult::Draw;
+        }
+
+        board.make(&mv, &mut NullBoardObserver);
+        td.keys.push(board.hash());
+        ply += 1;
+    };
+
+    (start_board, moves, result)
+}
+
+/// Encode a finished game in the output format, returning the bytes and the number of positions.
+fn encode_game(
+    format: DataFormat,
+    start_board: &Board,
+    moves: &[PlayedMove],
+    result: GameResult,
+) -> (Vec<u8>, u64) {
+    let mut bytes = Vec::new();
+    if format == DataFormat::Viri {
+        let mut game = ViriGame::new(start_board, result);
+        for played in moves {
+            game.push(&played.board, &played.mv, clamp_score(played.score));
+        }
+        game.write_to(&mut bytes).unwrap();
+        return (bytes, moves.len() as u64);
+    }
+
+    // Only record quiet positions with a non-mate score, which are best for training.
+    let mut positions = 0;
+    for played in moves {
+        let stm_score = if played.board.stm == White {
+            played.score
+        } else {
+            -played.score
+        };
+        if !is_quiet_position(&played.board, &played.mv, stm_score) {
+            continue;
+        }
+        let pos = TrainingPosition {
+            board: played.board,
+            score: clamp_score(played.score),
+            result,
+        };
+        write_position(&mut bytes, &pos, format).unwrap();
+        positions += 1;
+    }
+    (bytes, positions)
+}
+
+/// Check whether the game has ended by checkmate, stalemate, or one of the draw rules.
+fn game_over(board: &Board, keys: &[u64]) -> Option<GameResult> {
+    let mut legal_moves = MoveList::new();
+    board.gen_moves(MoveFilter::All, &mut legal_moves);
+    if legal_moves.is_empty() {
+        return if board.checkers.is_empty() {
+            Some(GameResult::Draw)
+        } else if board.stm == White {
+            Some(GameResult::BlackWin)
+        } else {
+            Some(GameResult::WhiteWin)
+        };
+    }
+
+    if board.is_fifty_move_rule() || board.is_insufficient_material() {
+        return Some(GameResult::Draw);
+    }
+
+    // Threefold repetition, only considering positions since the last irreversible move.
+    let hash = board.hash();
+    let window = keys.len().saturating_sub(board.hm as usize + 1);
+    if keys[window..].iter().filter(|&&key| key == hash).count() >= 3 {
+        return Some(GameResult::Draw);
+    }
+
+    None
+}
+
+/// The state of a previous run, read from its progress file.
+#[derive(Default)]
+struct Progress {
+    completed: HashSet<u64>,
+    data_len: u64,
+    progress_len: u64,
+}
+
+/// Read the progress file of a previous run, returning the completed games, the length of the data
+/// file after the last completed game, and the length of the valid part of the progress file.
+fn read_progress(data_path: &str, progress_path: &str) -> Result<Progress, String> {
+    let mut progress = Progress::default();
+
+    if !Path::new(progress_path).exists() {
+        let existing = std::fs::metadata(data_path).map(|m| m.len()).unwrap_or(0);
+        if existing > 0 {
+            return Err(format!(
+                "{} already exists but has no progress file, so it cannot be resumed",
+                data_path
+            ));
+        }
+        return Ok(progress);
+    }
+
+    let contents = std::fs::read_to_string(progress_path)
+        .map_err(|e| format!("could not read {}: {}", progress_path, e))?;
+    for line in contents.split_inclusive('\n') {
+        let mut parts = line.split_whitespace();
+        match (
+            parts.next().and_then(|p| p.parse::<u64>().ok()),
+            parts.next().and_then(|p| p.parse::<u64>().ok()),
+        ) {
+            // A partially written final line is discarded, and the game will be replayed.
+            (Some(game), Some(len)) if line.ends_with('\n') => {
+                progress.completed.insert(game);
+                progress.data_len = len;
+                progress.progress_len += line.len() as u64;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(progress)
+}
+
+/// The seed for an individual game, derived from the run's seed and the game's index.
+fn game_seed(seed: u64, game: u64) -> u64 {
+    seed ^ game.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
 
 pub fn generate_random_openings(
     td: &mut ThreadData,
@@ -74,3 +476,75 @@ fn startpos(rng: &mut StdRng, dfrc: bool) -> Board {
         Board::from_dfrc_idx(dfrc_seed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    fn config(games: u64, output: &Path) -> DatagenConfig {
+        DatagenConfig {
+            games,
+            threads: 1,
+            soft_nodes: 50,
+            seed: 3,
+            dfrc_percent: 0,
+            random_moves: 8,
+            hash_mb: 1,
+            format: DataFormat::Viri,
+            output: output.to_str().unwrap().to_string(),
+        }
+    }
+
+    fn append(path: &Path, bytes: &[u8]) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(bytes).unwrap();
+    }
+
+    /// The game indices recorded in a progress file, in order.
+    fn progress_games(path: &str) -> Vec<u64> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_datagen_resume() {
+        init();
+        let dir = std::env::temp_dir().join(format!("hobbes_datagen_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (full, resumed) = (dir.join("full.vf"), dir.join("resumed.vf"));
+
+        run_datagen(&config(4, &full)).unwrap();
+
+        // Generate the first two games, then leave a partial game and a partial progress line
+        // behind, as if the run had been killed while writing the third.
+        run_datagen(&config(2, &resumed)).unwrap();
+        let progress_path = format!("{}.progress", resumed.display());
+        append(&resumed, &[7; 100]);
+        append(Path::new(&progress_path), b"2 12");
+        run_datagen(&config(4, &resumed)).unwrap();
+
+        // Resuming again has nothing left to do.
+        run_datagen(&config(4, &resumed)).unwrap();
+
+        let data = std::fs::read(&resumed).unwrap();
+        assert_eq!(data, std::fs::read(&full).unwrap());
+        assert_eq!(progress_games(&progress_path), vec![0, 1, 2, 3]);
+        let progress = read_progress(resumed.to_str().unwrap(), &progress_path).unwrap();
+        assert_eq!(progress.data_len, data.len() as u64);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
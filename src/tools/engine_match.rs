@@ -0,0 +1,809 @@
+use crate::board::castling;
+use crate::board::movegen::MoveFilter;
+use crate::board::moves::{Move, MoveList};
+use crate::board::observer::NullBoardObserver;
+use crate::board::side::Side;
+use crate::board::side::Side::{Black, White};
+use crate::board::Board;
+use crate::search::engine::Engine;
+use crate::search::score;
+use crate::tools::dataformat::GameResult;
+use crate::tools::datagen::generate_random_openings;
+use crate::tools::epd::parse_epd;
+use crate::tools::pgn::{PgnGame, PgnMove};
+use crate::tools::sprt::{Pentanomial, Sprt, SprtVerdict};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long an engine may take to answer 'uci' or 'isready'.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an engine may overrun its clock before it loses on time, and how long a search under a
+/// node or movetime limit may take before the engine is considered to have stalled.
+const TIME_MARGIN_MS: u64 = 100;
+const STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The number of random plies in each generated opening, when no opening file is given.
+const OPENING_PLIES: usize = 8;
+
+pub struct MatchConfig {
+    pub engines: [EngineConfig; 2],
+    /// An EPD or FEN file of openings, each played twice with colours reversed. Without one, the
+    /// openings are generated as by `genfens`.
+    pub openings: Option<String>,
+    /// The index of the first opening to play, so that repeated matches can work through a file.
+    pub first_opening: usize,
+    pub games: u64,
+    pub time_control: TimeControl,
+    pub concurrency: usize,
+    pub pgn: Option<String>,
+    pub sprt: Option<Sprt>,
+    pub seed: u64,
+    pub adjudication: Adjudication,
+    /// Whether to leave out the running statistics after each pair and the summary at the end.
+    pub quiet: bool,
+}
+
+pub struct EngineConfig {
+    pub command: String,
+    /// UCI options set on the engine before the first game, as (name, value) pairs.
+    pub options: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy)]
+pub enum TimeControl {
+    Fischer { base_ms: u64, inc_ms: u64 },
+    MoveTime(u64),
+    Nodes(u64),
+}
+
+impl TimeControl {
+    /// Parse a time control in seconds, in the form `<base>+<increment>`.
+    pub fn parse_fischer(text: &str) -> Option<TimeControl> {
+        let (base, inc) = text.split_once('+').unwrap_or((text, "0"));
+        let base = base.parse::<f64>().ok().filter(|base| *base > 0.0)?;
+        let inc = inc.parse::<f64>().ok().filter(|inc| *inc >= 0.0)?;
+        Some(TimeControl::Fischer {
+            base_ms: (base * 1000.0) as u64,
+            inc_ms: (inc * 1000.0) as u64,
+        })
+    }
+
+    fn pgn_tag(self) -> String {
+        match self {
+            TimeControl::Fischer { base_ms, inc_ms } => {
+                format!("{}+{}", base_ms as f64 / 1000.0, inc_ms as f64 / 1000.0)
+            }
+            TimeControl::MoveTime(ms) => format!("{}/move", ms as f64 / 1000.0),
+            TimeControl::Nodes(_) => "-".to_string(),
+        }
+    }
+}
+
+/// Games are adjudicated once both engines agree on the outcome. A game is resigned when the last
+/// `resign_plies` scores all favour the same side by at least `resign_score`, and drawn from move
+/// `draw_move` onwards when the last `draw_plies` scores are all within `draw_score` of zero.
+#[derive(Clone, Copy)]
+pub struct Adjudication {
+    pub enabled: bool,
+    pub resign_score: i32,
+    pub resign_plies: usize,
+    pub draw_move: u32,
+    pub draw_score: i32,
+    pub draw_plies: usize,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication {
+            enabled: true,
+            resign_score: 1000,
+            resign_plies: 6,
+            draw_move: 40,
+            draw_score: 10,
+            draw_plies: 8,
+        }
+    }
+}
+
+/// The running totals of a match, from the first engine's perspective.
+#[derive(Default)]
+pub struct MatchResult {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+    pub pentanomial: Pentanomial,
+    pub verdict: Option<SprtVerdict>,
+}
+
+struct MatchState {
+    result: MatchResult,
+    pgn: Option<BufWriter<File>>,
+    games_played: u64,
+}
+
+/// Play a match between two UCI engines, running as child processes. The openings are played in
+/// pairs with colours reversed, by `concurrency` workers with two engine processes each. After
+/// every pair the Elo, LOS and, if configured, the SPRT log-likelihood ratio are reported, and the
+/// match stops early once the SPRT reaches a verdict.
+pub fn run_match(engine: &mut Engine, config: &MatchConfig) -> Result<MatchResult, String> {
+    let mut openings = load_openings(engine, config)?;
+    // Engines are told to play Chess960 if any opening needs it, and then every move is sent in
+    // the king-takes-rook form.
+    let frc = openings.iter().any(|board| board.is_frc());
+    for board in openings.iter_mut() {
+        board.set_frc(frc);
+    }
+
+    let pairs = config.games.div_ceil(2) as usize;
+    let pgn = match &config.pgn {
+        Some(path) => Some(BufWriter::new(
+            File::create(path).map_err(|e| format!("could not create {}: {}", path, e))?,
+        )),
+        None => None,
+    };
+    let state = Mutex::new(MatchState {
+        result: MatchResult::default(),
+        pgn,
+        games_played: 0,
+    });
+    let next_pair = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let start = Instant::now();
+
+    let errors: Vec<String> = std::thread::scope(|s| {
+        let workers: Vec<_> = (0..config.concurrency.clamp(1, pairs.max(1)))
+            .map(|_| {
+                s.spawn(|| -> Result<(), String> {
+                    let mut processes = [
+                        UciProcess::start(&config.engines[0], frc)?,
+                        UciProcess::start(&config.engines[1], frc)?,
+                    ];
+                    while !stop.load(Relaxed) {
+                        let pair = next_pair.fetch_add(1, Relaxed);
+                        if pair >= pairs {
+                            break;
+                        }
+                        let opening = &openings[(config.first_opening + pair) % openings.len()];
+                        let mut games = Vec::with_capacity(2);
+                        for first_is_white in [true, false] {
+                            let round = 2 * pair + !first_is_white as usize + 1;
+                            let game =
+                                play_game(&mut processes, opening, first_is_white, round, config)?;
+                            games.push(game);
+                        }
+                        let mut state = state.lock().unwrap();
+                        if record_pair(&mut state, &games, config)? {
+                            stop.store(true, Relaxed);
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .filter_map(|worker| worker.join().unwrap().err())
+            .collect()
+    });
+    if let Some(error) = errors.first() {
+        return Err(error.clone());
+    }
+
+    let state = state.into_inner().unwrap();
+    let result = state.result;
+    if config.quiet {
+        return Ok(result);
+    }
+    println!(
+        "info string match finished {} games in {:.1}s",
+        state.games_played,
+        start.elapsed().as_secs_f64()
+    );
+    if let Some(verdict) = result.verdict {
+        let verdict = match verdict {
+            SprtVerdict::H1 => "H1 accepted",
+            SprtVerdict::H0 => "H0 accepted",
+            SprtVerdict::Continue => "inconclusive",
+        };
+        println!("info string match sprt {}", verdict);
+    }
+    Ok(result)
+}
+
+fn load_openings(engine: &mut Engine, config: &MatchConfig) -> Result<Vec<Board>, String> {
+    let Some(path) = &config.openings else {
+        let count = config.games.div_ceil(2) as usize;
+        let fens = generate_random_openings(
+            engine.td_mut(),
+            count.max(1),
+            config.seed,
+            OPENING_PLIES,
+            false,
+        );
+        return fens
+            .iter()
+            .map(|fen| Board::from_fen(fen).map_err(|e| format!("invalid fen '{}': {}", fen, e)))
+            .collect();
+    };
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let openings = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            parse_epd(line, false)
+                .map(|position| position.board)
+                .map_err(|e| format!("{} line {}: {}", path, i + 1, e))
+        })
+        .collect::<Result<Vec<Board>, String>>()?;
+    if openings.is_empty() {
+        return Err(format!("{} has no openings", path));
+    }
+    Ok(openings)
+}
+
+/// Add a finished pair to the totals and the PGN file, and report the running statistics.
+/// Returns whether the SPRT has reached a verdict.
+fn record_pair(
+    state: &mut MatchState,
+    games: &[PgnGame],
+    config: &MatchConfig,
+) -> Result<bool, String> {
+    let mut pair_score = 0.0;
+    for (i, game) in games.iter().enumerate() {
+        let first_is_white = i == 0;
+        let first_won = match game.result {
+            Some(GameResult::WhiteWin) => Some(first_is_white),
+            Some(GameResult::BlackWin) => Some(!first_is_white),
+            _ => None,
+        };
+        let score = match first_won {
+            Some(true) => {
+                state.result.wins += 1;
+                1.0
+            }
+            Some(false) => {
+                state.result.losses += 1;
+                0.0
+            }
+            None => {
+                state.result.draws += 1;
+                0.5
+            }
+        };
+        pair_score += score;
+        if let Some(pgn) = &mut state.pgn {
+            write!(pgn, "{}", game).map_err(|e| format!("could not write pgn: {}", e))?;
+        }
+    }
+    if let Some(pgn) = &mut state.pgn {
+        pgn.flush()
+            .map_err(|e| format!("could not write pgn: {}", e))?;
+    }
+    state.games_played += games.len() as u64;
+
+    let result = &mut state.result;
+    result.pentanomial.add_pair(pair_score);
+    let ptnml = result.pentanomial;
+    let mut line = format!(
+        "info string match games {} wdl {}-{}-{} ptnml {} elo {:.1} +/- {:.1} los {:.1}%",
+        state.games_played,
+        result.wins,
+        result.draws,
+        result.losses,
+        ptnml.0.map(|n| n.to_string()).join(" "),
+        ptnml.elo(),
+        ptnml.elo_error(),
+        100.0 * ptnml.los()
+    );
+    let mut concluded = false;
+    if let Some(sprt) = config.sprt {
+        let (lower, upper) = sprt.bounds();
+        let verdict = sprt.verdict(ptnml);
+        line += &format!(
+            " llr {:.2} ({:.2}, {:.2}) [{:.1}, {:.1}]",
+            sprt.llr(ptnml),
+            lower,
+            upper,
+            sprt.elo0,
+            sprt.elo1
+        );
+        result.verdict = Some(verdict);
+        concluded = verdict != SprtVerdict::Continue;
+    }
+    if !config.quiet {
+        println!("{}", line);
+    }
+    Ok(concluded)
+}
+
+/// Play one game from the opening, with the first engine playing white if `first_is_white`. An
+/// engine which crashes or stops responding loses the game and is restarted.
+fn play_game(
+    processes: &mut [UciProcess; 2],
+    opening: &Board,
+    first_is_white: bool,
+    round: usize,
+    config: &MatchConfig,
+) -> Result<PgnGame, String> {
+    let engine_for = |side: Side| (side == White) != first_is_white;
+    let loss = |side: Side| {
+        if side == White {
+            GameResult::BlackWin
+        } else {
+            GameResult::WhiteWin
+        }
+    };
+    let mut game = PgnGame::new(*opening);
+    game.set_tag("Event", "hobbes match");
+    game.set_tag("Round", &round.to_string());
+    game.set_tag("White", &processes[engine_for(White) as usize].name.clone());
+    game.set_tag("Black", &processes[engine_for(Black) as usize].name.clone());
+    game.set_tag("TimeControl", &config.time_control.pgn_tag());
+
+    for process in processes.iter_mut() {
+        if process.new_game().is_err() {
+            *process = process.restart()?;
+        }
+    }
+
+    let start_fen = opening.to_fen();
+    let mut board = *opening;
+    let mut hashes = vec![board.hash()];
+    let mut uci_moves: Vec<String> = Vec::new();
+    let mut white_scores: Vec<Option<i32>> = Vec::new();
+    let mut clocks = match config.time_control {
+        TimeControl::Fischer { base_ms, .. } => [base_ms as i64; 2],
+        _ => [0; 2],
+    };
+
+    let (result, termination) = loop {
+        let mut moves = MoveList::new();
+        board.gen_moves(MoveFilter::All, &mut moves);
+        let legal: Vec<Move> = moves.iter().map(|entry| entry.mv).collect();
+
+        if legal.is_empty() {
+            let result = if board.checkers.is_empty() {
+                GameResult::Draw
+            } else {
+                loss(board.stm)
+            };
+            break (result, "normal");
+        }
+        let repetitions = hashes.iter().filter(|&&hash| hash == board.hash()).count();
+        if board.hm >= 100 || repetitions >= 3 || board.is_insufficient_material() {
+            break (GameResult::Draw, "normal");
+        }
+        if let Some(result) = adjudicate(&config.adjudication, &white_scores, board.fm as u32) {
+            break (result, "adjudication");
+        }
+
+        let side = board.stm;
+        let process = &mut processes[engine_for(side) as usize];
+        let search = match process.go(&start_fen, &uci_moves, config.time_control, clocks, side) {
+            Ok(search) => search,
+            Err(e) => {
+                println!(
+                    "info string match {} in round {}: {}",
+                    process.name, round, e
+                );
+                *process = process.restart()?;
+                break (loss(side), "abandoned");
+            }
+        };
+
+        if let TimeControl::Fischer { inc_ms, .. } = config.time_control {
+            let clock = &mut clocks[side as usize];
+            *clock -= search.elapsed_ms as i64;
+            if *clock < -(TIME_MARGIN_MS as i64) {
+                break (loss(side), "time forfeit");
+            }
+            *clock = (*clock).max(0) + inc_ms as i64;
+        }
+
+        let Some(mv) = find_move(&board, &legal, &search.best_move) else {
+            println!(
+                "info string match {} played illegal move {} in round {}",
+                process.name, search.best_move, round
+            );
+            break (loss(side), "rules infraction");
+        };
+
+        game.moves.push(PgnMove {
+            score: search.score,
+            depth: search.depth,
+            ..PgnMove::new(mv)
+        });
+        white_scores.push(
+            search
+                .score
+                .map(|score| if side == White { score } else { -score }),
+        );
+        uci_moves.push(mv.to_uci());
+        board.make(&mv, &mut NullBoardObserver);
+        hashes.push(board.hash());
+    };
+
+    game.set_result(Some(result));
+    game.set_tag("Termination", termination);
+    Ok(game)
+}
+
+/// Resolve a move sent by an engine against the legal moves. Castling is accepted both as the king
+/// moving two squares and as the king capturing its own rook.
+fn find_move(board: &Board, legal: &[Move], text: &str) -> Option<Move> {
+    legal.iter().copied().find(|mv| {
+        if mv.to_uci() == text {
+            return true;
+        }
+        if !mv.is_castle() {
+            return false;
+        }
+        let kingside = castling::is_kingside(mv.from(), mv.to());
+        let king_to = castling::king_to(board.stm, kingside);
+        let rook_from = if board.is_frc() {
+            mv.to()
+        } else {
+            castling::rook_from(board.stm, kingside)
+        };
+        let from = Move::uci_sq(mv.from());
+        [king_to, rook_from]
+            .iter()
+            .any(|&to| format!("{}{}", from, Move::uci_sq(to)) == text)
+    })
+}
+
+/// Adjudicate the game from the scores reported so far, from white's perspective.
+fn adjudicate(
+    rules: &Adjudication,
+    scores: &[Option<i32>],
+    move_number: u32,
+) -> Option<GameResult> {
+    if !rules.enabled {
+        return None;
+    }
+    let last = |plies: usize| {
+        scores
+            .len()
+            .checked_sub(plies)
+            .map(|start| &scores[start..])
+    };
+    if let Some(recent) = last(rules.resign_plies.max(1)) {
+        if recent
+            .iter()
+            .all(|score| score.is_some_and(|s| s >= rules.resign_score))
+        {
+            return Some(GameResult::WhiteWin);
+        }
+        if recent
+            .iter()
+            .all(|score| score.is_some_and(|s| s <= -rules.resign_score))
+        {
+            return Some(GameResult::BlackWin);
+        }
+    }
+    if move_number >= rules.draw_move {
+        if let Some(recent) = last(rules.draw_plies.max(1)) {
+            if recent
+                .iter()
+                .all(|score| score.is_some_and(|s| s.abs() <= rules.draw_score))
+            {
+                return Some(GameResult::Draw);
+            }
+        }
+    }
+    None
+}
+
+struct SearchOutcome {
+    best_move: String,
+    score: Option<i32>,
+    depth: Option<i32>,
+    elapsed_ms: u64,
+}
+
+/// A UCI engine running as a child process. Its output is read on a separate thread, so that a
+/// search which overruns its time can be detected.
+struct UciProcess {
+    name: String,
+    command: String,
+    options: Vec<(String, String)>,
+    frc: bool,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciProcess {
+    fn start(config: &EngineConfig, frc: bool) -> Result<UciProcess, String> {
+        let mut parts = config.command.split_whitespace();
+        let program = parts.next().ok_or("missing engine command")?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("could not start {}: {}", config.command, e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut process = UciProcess {
+            name: config.command.clone(),
+            command: config.command.clone(),
+            options: config.options.clone(),
+            frc,
+            child,
+            stdin,
+            lines,
+        };
+        process.send("uci")?;
+        for line in process.read_until("uciok", HANDSHAKE_TIMEOUT)? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                process.name = name.trim().to_string();
+            }
+        }
+        if frc {
+            process.send("setoption name UCI_Chess960 value true")?;
+        }
+        for (name, value) in config.options.clone() {
+            process.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        process.send("isready")?;
+        process.read_until("readyok", HANDSHAKE_TIMEOUT)?;
+        Ok(process)
+    }
+
+    fn restart(&self) -> Result<UciProcess, String> {
+        let config = EngineConfig {
+            command: self.command.clone(),
+            options: self.options.clone(),
+        };
+        UciProcess::start(&config, self.frc)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("could not write to {}: {}", self.name, e))
+    }
+
+    /// Collect the engine's output up to and including the first line starting with `prefix`.
+    fn read_until(&mut self, prefix: &str, timeout: Duration) -> Result<Vec<String>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) => {
+                    let done = line.starts_with(prefix);
+                    lines.push(line);
+                    if done {
+                        return Ok(lines);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!("timed out waiting for '{}'", prefix))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err("the engine process exited".to_string())
+                }
+            }
+        }
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.read_until("readyok", HANDSHAKE_TIMEOUT).map(|_| ())
+    }
+
+    fn go(
+        &mut self,
+        fen: &str,
+        moves: &[String],
+        time_control: TimeControl,
+        clocks: [i64; 2],
+        side: Side,
+    ) -> Result<SearchOutcome, String> {
+        let mut position = format!("position fen {}", fen);
+        if !moves.is_empty() {
+            position += &format!(" moves {}", moves.join(" "));
+        }
+        self.send(&position)?;
+
+        let (go, timeout) = match time_control {
+            TimeControl::Fischer { inc_ms, .. } => {
+                let [white, black] = clocks.map(|clock| clock.max(0) as u64);
+                let go = format!(
+                    "go wtime {} btime {} winc {} binc {}",
+                    white, black, inc_ms, inc_ms
+                );
+                let remaining = if side == White { white } else { black };
+                let timeout = Duration::from_millis(remaining + TIME_MARGIN_MS);
+                (go, timeout)
+            }
+            TimeControl::MoveTime(ms) => (
+                format!("go movetime {}", ms),
+                Duration::from_millis(ms + TIME_MARGIN_MS) + STALL_TIMEOUT,
+            ),
+            TimeControl::Nodes(nodes) => (format!("go nodes {}", nodes), STALL_TIMEOUT),
+        };
+
+        let start = Instant::now();
+        self.send(&go)?;
+        let lines = self.read_until("bestmove", timeout)?;
+        let elapsed_ms = start.elapsed().as_millis() as u64;
+
+        let best_move = lines
+            .last()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or_default()
+            .to_string();
+        let (score, depth) = lines
+            .iter()
+            .rev()
+            .find_map(|line| parse_info(line))
+            .map_or((None, None), |(score, depth)| (Some(score), depth));
+        Ok(SearchOutcome {
+            best_move,
+            score,
+            depth,
+            elapsed_ms,
+        })
+    }
+}
+
+impl Drop for UciProcess {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        std::thread::sleep(Duration::from_millis(10));
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Read the score and depth from an 'info' line, skipping lines without an exact score and
+/// 'info string' lines, whose text is free-form.
+fn parse_info(line: &str) -> Option<(i32, Option<i32>)> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let bound = tokens.contains(&"lowerbound") || tokens.contains(&"upperbound");
+    if tokens.first() != Some(&"info") || tokens.get(1) == Some(&"string") || bound {
+        return None;
+    }
+    let value = |name: &str| {
+        let index = tokens.iter().position(|&token| token == name)?;
+        tokens.get(index + 1)?.parse::<i32>().ok()
+    };
+    let score = match tokens.iter().position(|&token| token == "score")? {
+        i if tokens.get(i + 1) == Some(&"cp") => tokens.get(i + 2)?.parse::<i32>().ok()?,
+        i if tokens.get(i + 1) == Some(&"mate") => {
+            let moves = tokens.get(i + 2)?.parse::<i32>().ok()?;
+            if moves > 0 {
+                score::mate_in((2 * moves - 1) as usize)
+            } else {
+                score::mated_in((-2 * moves) as usize)
+            }
+        }
+        _ => return None,
+    };
+    Some((score, value("depth")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    fn legal_moves(board: &Board) -> Vec<Move> {
+        let mut moves = MoveList::new();
+        board.gen_moves(MoveFilter::All, &mut moves);
+        moves.iter().map(|entry| entry.mv).collect()
+    }
+
+    #[test]
+    fn test_find_move_castling() {
+        init();
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let legal = legal_moves(&board);
+        for (text, other) in [("e1g1", "e1h1"), ("e1c1", "e1a1")] {
+            let mv = find_move(&board, &legal, text).unwrap();
+            assert!(mv.is_castle());
+            assert_eq!(find_move(&board, &legal, other), Some(mv));
+        }
+        assert!(find_move(&board, &legal, "e1f1").is_some_and(|mv| !mv.is_castle()));
+        assert_eq!(find_move(&board, &legal, "e1e3"), None);
+
+        let mut board =
+            Board::from_fen("1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1").unwrap();
+        board.set_frc(true);
+        let legal = legal_moves(&board);
+        for (text, other) in [("e1g1", "e1g1"), ("e1b1", "e1c1")] {
+            let mv = find_move(&board, &legal, text).unwrap();
+            assert!(mv.is_castle());
+            assert_eq!(find_move(&board, &legal, other), Some(mv));
+        }
+    }
+
+    #[test]
+    fn test_parse_info() {
+        assert_eq!(
+            parse_info("info depth 12 seldepth 18 score cp 35 nodes 1000 pv e2e4"),
+            Some((35, Some(12)))
+        );
+        assert_eq!(
+            parse_info("info score cp -120 nodes 1000"),
+            Some((-120, None))
+        );
+        assert_eq!(
+            parse_info("info depth 20 score mate 3 pv h5f7"),
+            Some((score::mate_in(5), Some(20)))
+        );
+        assert_eq!(
+            parse_info("info depth 20 score mate -2 pv g8f6"),
+            Some((score::mated_in(4), Some(20)))
+        );
+        assert_eq!(parse_info("info depth 9 score cp 50 lowerbound"), None);
+        assert_eq!(parse_info("info depth 9 score cp 50 upperbound"), None);
+        assert_eq!(parse_info("info string score cp 50"), None);
+        assert_eq!(parse_info("bestmove e2e4"), None);
+    }
+
+    #[test]
+    fn test_adjudicate() {
+        let rules = Adjudication::default();
+        let repeat = |score: Option<i32>, plies: usize| vec![score; plies];
+
+        let winning = repeat(Some(1200), rules.resign_plies);
+        assert_eq!(adjudicate(&rules, &winning, 10), Some(GameResult::WhiteWin));
+        let losing = repeat(Some(-1000), rules.resign_plies);
+        assert_eq!(adjudicate(&rules, &losing, 10), Some(GameResult::BlackWin));
+        assert_eq!(adjudicate(&rules, &winning[1..], 10), None);
+
+        // A single score short of the threshold, or missing, interrupts the run.
+        let mut interrupted = winning.clone();
+        interrupted[2] = Some(999);
+        assert_eq!(adjudicate(&rules, &interrupted, 10), None);
+        interrupted[2] = None;
+        assert_eq!(adjudicate(&rules, &interrupted, 10), None);
+
+        let level = repeat(Some(5), rules.draw_plies);
+        assert_eq!(adjudicate(&rules, &level, rules.draw_move - 1), None);
+        assert_eq!(
+            adjudicate(&rules, &level, rules.draw_move),
+            Some(GameResult::Draw)
+        );
+        assert_eq!(adjudicate(&rules, &level[1..], rules.draw_move), None);
+
+        let disabled = Adjudication {
+            enabled: false,
+            ..rules
+        };
+        assert_eq!(adjudicate(&disabled, &winning, 10), None);
+        assert_eq!(adjudicate(&disabled, &level, rules.draw_move), None);
+    }
+}
@@ -0,0 +1,397 @@
+use crate::board::castling;
+use crate::board::moves::Move;
+use crate::board::san::{parse_san, to_san};
+use crate::board::Board;
+use crate::search::engine::Engine;
+use crate::search::thread::Iteration;
+use crate::search::time::SearchLimits;
+use std::fs;
+use std::time::Instant;
+
+/// The default time per position when neither a movetime nor a node limit is given.
+pub const DEFAULT_MOVETIME_MS: u64 = 1000;
+
+/// A test position from an EPD file, with the operations used to score the engine's answer.
+pub struct EpdPosition {
+    pub board: Board,
+    /// The `id` operation, naming the position within its suite.
+    pub id: Option<String>,
+    /// The `bm` operation: the position is solved by playing any of these moves.
+    pub best_moves: Vec<Move>,
+    /// The `am` operation: the position is solved by playing none of these moves.
+    pub avoid_moves: Vec<Move>,
+    /// The `c0` operation, a free-form comment.
+    pub comment: Option<String>,
+    /// The moves of the STS-style `c7` operation, paired with the points from `c8`.
+    pub points: Vec<(Move, u32)>,
+}
+
+impl EpdPosition {
+    /// Whether the move solves the position, or `None` if it has no `bm` or `am` operation.
+    pub fn solved_by(&self, mv: Move) -> Option<bool> {
+        if self.best_moves.is_empty() && self.avoid_moves.is_empty() {
+            return None;
+        }
+        let best = self.best_moves.is_empty() || self.best_moves.contains(&mv);
+        let avoided = !self.avoid_moves.contains(&mv);
+        Some(best && avoided)
+    }
+
+    /// The points awarded for the move by the `c7`/`c8` operations.
+    pub fn points_for(&self, mv: Move) -> u32 {
+        self.points
+            .iter()
+            .find(|(m, _)| *m == mv)
+            .map_or(0, |(_, points)| *points)
+    }
+
+    pub fn max_points(&self) -> u32 {
+        self.points
+            .iter()
+            .map(|(_, points)| *points)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Parse a line of an EPD file: the first four fields of a FEN, optionally followed by the move
+/// counters, then a list of `opcode operand...;` operations. Moves are given in SAN and resolved
+/// against the position, so an illegal or ambiguous move is an error.
+pub fn parse_epd(line: &str, frc: bool) -> Result<EpdPosition, String> {
+    let mut rest = line.trim();
+    let mut fields = Vec::new();
+    for _ in 0..4 {
+        let (field, tail) = next_token(rest);
+        if field.is_empty() {
+            return Err(format!("invalid epd '{}'", line));
+        }
+        fields.push(field);
+        rest = tail;
+    }
+
+    // Some suites include the halfmove and fullmove counters, which are not EPD operations.
+    let (halfmove, tail) = next_token(rest);
+    let (fullmove, counters_tail) = next_token(tail);
+    if halfmove.parse::<u32>().is_ok() && fullmove.parse::<u32>().is_ok() {
+        fields.extend([halfmove, fullmove]);
+        rest = counters_tail;
+    } else {
+        fields.extend(["0", "1"]);
+    }
+
+    let fen = fields.join(" ");
+    let mut board = Board::from_fen(&fen).map_err(|e| format!("invalid fen '{}': {}", fen, e))?;
+    board.set_frc(frc || castling::requires_frc(&board));
+
+    let mut position = EpdPosition {
+        board,
+        id: None,
+        best_moves: Vec::new(),
+        avoid_moves: Vec::new(),
+        comment: None,
+        points: Vec::new(),
+    };
+    let mut c7 = Vec::new();
+    let mut c8 = Vec::new();
+
+    for operation in split_operations(rest) {
+        let (opcode, operand) = next_token(&operation);
+        let operand = operand.trim();
+        let unquoted = operand.trim_matches('"').to_string();
+        let parse_moves = |text: &str| -> Result<Vec<Move>, String> {
+            text.split_whitespace()
+                .map(|san| parse_san(&board, san))
+                .collect()
+        };
+        match opcode {
+            "bm" => position.best_moves = parse_moves(operand)?,
+            "am" => position.avoid_moves = parse_moves(operand)?,
+            "id" => position.id = Some(unquoted),
+            "c0" => position.comment = Some(unquoted),
+            "c7" => c7 = parse_moves(&unquoted)?,
+            "c8" => {
+                c8 = unquoted
+                    .split_whitespace()
+                    .map(|points| points.parse::<u32>())
+                    .collect::<Result<Vec<u32>, _>>()
+                    .map_err(|_| format!("invalid c8 points '{}'", unquoted))?
+            }
+            _ => {}
+        }
+    }
+
+    if c7.len() != c8.len() {
+        return Err(format!(
+            "c7 has {} moves but c8 has {} points",
+            c7.len(),
+            c8.len()
+        ));
+    }
+    position.points = c7.into_iter().zip(c8).collect();
+    Ok(position)
+}
+
+/// Split off the first whitespace-separated token, returning it and the rest of the string.
+fn next_token(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], &text[index..]),
+        None => (text, ""),
+    }
+}
+
+/// Split the operations section of an EPD line on the semicolons outside quoted strings.
+fn split_operations(text: &str) -> Vec<String> {
+    let mut operations = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ';' if !quoted => operations.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    operations.push(current);
+    operations.retain(|operation| !operation.trim().is_empty());
+    operations
+}
+
+pub struct EpdConfig {
+    pub path: String,
+    pub movetime: Option<u64>,
+    pub nodes: Option<u64>,
+    pub frc: bool,
+}
+
+#[derive(Default)]
+pub struct EpdSummary {
+    pub positions: usize,
+    /// The number of positions with a `bm` or `am` operation, and how many of those were solved.
+    pub scored: usize,
+    pub solved: usize,
+    pub points: u64,
+    pub max_points: u64,
+    /// The total time to solution across the solved positions.
+    pub solution_time_ms: u128,
+}
+
+/// Search every position of an EPD test suite with a fresh hash table, and score the engine's
+/// answer against the `bm`/`am` and `c7`/`c8` operations. A position counts as solved from the
+/// first completed iteration after which the best move was a solution for the rest of the search.
+pub fn run_epd_test(engine: &mut Engine, config: &EpdConfig) -> Result<EpdSummary, String> {
+    let contents = fs::read_to_string(&config.path)
+        .map_err(|e| format!("could not read {}: {}", config.path, e))?;
+    let positions = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| parse_epd(line, config.frc).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect::<Result<Vec<EpdPosition>, String>>()?;
+
+    let movetime = match (config.movetime, config.nodes) {
+        (None, None) => Some(DEFAULT_MOVETIME_MS),
+        (movetime, _) => movetime,
+    };
+    let limits = SearchLimits::new(None, movetime, None, config.nodes, None, 0, 0);
+
+    println!(
+        "{:>4} {:<16} {:<8} {:<8} {:<16} {:>7} {:>9} {:>6}",
+        "#", "id", "result", "move", "expected", "score", "time", "points"
+    );
+
+    let mut summary = EpdSummary {
+        positions: positions.len(),
+        ..EpdSummary::default()
+    };
+    let start = Instant::now();
+    engine.set_quiet(true);
+
+    for (i, position) in positions.iter().enumerate() {
+        engine.new_game();
+        let td = engine.td_mut();
+        td.keys.clear();
+        td.keys.push(position.board.hash());
+        td.root_ply = 0;
+        engine.go(position.board, limits.clone());
+        engine.join();
+
+        let td = engine.td();
+        let best_move = td.best_move;
+        let solved = position.solved_by(best_move);
+        let solution_time = solved
+            .filter(|&solved| solved)
+            .map(|_| time_to_solution(&td.iterations, |mv| position.solved_by(mv) == Some(true)));
+
+        summary.scored += solved.is_some() as usize;
+        summary.solved += (solved == Some(true)) as usize;
+        summary.solution_time_ms += solution_time.unwrap_or(0);
+        let points = position.points_for(best_move);
+        summary.points += points as u64;
+        summary.max_points += position.max_points() as u64;
+
+        let result = match solved {
+            Some(true) => "solved",
+            Some(false) => "failed",
+            None => "-",
+        };
+        let expected = if !position.best_moves.is_empty() {
+            format_moves(&position.board, "", &position.best_moves)
+        } else {
+            format_moves(&position.board, "not ", &position.avoid_moves)
+        };
+        println!(
+            "{:>4} {:<16} {:<8} {:<8} {:<16} {:>7} {:>9} {:>6}",
+            i + 1,
+            position.id.as_deref().unwrap_or("-"),
+            result,
+            to_san(&position.board, best_move),
+            expected,
+            td.best_score,
+            solution_time.map_or("-".to_string(), |ms| format!("{}ms", ms)),
+            if position.points.is_empty() {
+                "-".to_string()
+            } else {
+                format!("{}/{}", points, position.max_points())
+            }
+        );
+    }
+
+    engine.set_quiet(false);
+    engine.new_game();
+
+    println!();
+    if summary.scored > 0 {
+        println!(
+            "solved {}/{} ({:.1}%), average time to solution {}ms",
+            summary.solved,
+            summary.scored,
+            100.0 * summary.solved as f64 / summary.scored as f64,
+            summary.solution_time_ms / summary.solved.max(1) as u128
+        );
+    }
+    if summary.max_points > 0 {
+        println!(
+            "points {}/{} ({:.1}%)",
+            summary.points,
+            summary.max_points,
+            100.0 * summary.points as f64 / summary.max_points as f64
+        );
+    }
+    println!(
+        "{} positions in {:.2}s",
+        summary.positions,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(summary)
+}
+
+/// The time of the first iteration from which every later iteration's best move was a solution.
+/// A search with no completed iterations, such as one with a single legal move, solves instantly.
+fn time_to_solution(iterations: &[Iteration], is_solution: impl Fn(Move) -> bool) -> u128 {
+    let unsolved = iterations.iter().rposition(|it| !is_solution(it.best_move));
+    let first_solved = unsolved.map_or(0, |index| index + 1);
+    iterations.get(first_solved).map_or(0, |it| it.time_ms)
+}
+
+fn format_moves(board: &Board, prefix: &str, moves: &[Move]) -> String {
+    let moves: Vec<String> = moves.iter().map(|mv| to_san(board, *mv)).collect();
+    format!("{}{}", prefix, moves.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    #[test]
+    fn test_parse_epd_sts() {
+        init();
+        let line = "1kr5/3n4/q3p2p/p2n2p1/PppB1P2/5BP1/1P2Q2P/3R2K1 w - - bm f5; \
+                    id \"Undermine.001\"; c0 \"f5=10, Be5+=2, Bf2=3, Bg4=2\"; \
+                    c7 \"f5 Bf2 Be5+ Bg4\"; c8 \"10 3 2 2\";";
+        let position = parse_epd(line, false).unwrap();
+        let san = |mv: Move| to_san(&position.board, mv);
+
+        assert_eq!(position.id.as_deref(), Some("Undermine.001"));
+        assert_eq!(
+            position.comment.as_deref(),
+            Some("f5=10, Be5+=2, Bf2=3, Bg4=2")
+        );
+        assert_eq!(
+            position.board.to_fen(),
+            "1kr5/3n4/q3p2p/p2n2p1/PppB1P2/5BP1/1P2Q2P/3R2K1 w - - 0 1"
+        );
+        assert_eq!(
+            position
+                .best_moves
+                .iter()
+                .map(|&mv| san(mv))
+                .collect::<Vec<_>>(),
+            ["f5"]
+        );
+        assert!(position.avoid_moves.is_empty());
+
+        let points: Vec<(String, u32)> = position
+            .points
+            .iter()
+            .map(|&(mv, points)| (san(mv), points))
+            .collect();
+        assert_eq!(
+            points,
+            [
+                ("f5".into(), 10),
+                ("Bf2".into(), 3),
+                ("Be5+".into(), 2),
+                ("Bg4".into(), 2)
+            ]
+        );
+        assert_eq!(position.max_points(), 10);
+        assert_eq!(position.points_for(position.points[1].0), 3);
+        assert_eq!(position.solved_by(position.best_moves[0]), Some(true));
+        assert_eq!(position.solved_by(position.points[1].0), Some(false));
+    }
+
+    #[test]
+    fn test_parse_epd_avoid_move_and_counters() {
+        init();
+        let line =
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3 am Nxe5; id \"am\";";
+        let position = parse_epd(line, false).unwrap();
+        assert_eq!(position.board.to_fen(), line[..line.find(" am").unwrap()]);
+        assert_eq!(position.id.as_deref(), Some("am"));
+        assert!(position.best_moves.is_empty());
+        assert_eq!(position.avoid_moves.len(), 1);
+        assert_eq!(position.solved_by(position.avoid_moves[0]), Some(false));
+        assert_eq!(position.solved_by(Move::NONE), Some(true));
+        assert_eq!(position.max_points(), 0);
+
+        let bare = parse_epd("4k3/8/8/8/8/8/8/4K2R w K -", false).unwrap();
+        assert_eq!(bare.solved_by(Move::NONE), None);
+    }
+
+    #[test]
+    fn test_parse_epd_errors() {
+        init();
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K -";
+        assert!(parse_epd("4k3/8/8 w", false).is_err());
+        assert!(parse_epd(&format!("{} bm Qd8;", fen), false).is_err());
+        assert!(
+            parse_epd(&format!("{} c7 \"Rh8+ Kf2\"; c8 \"10\";", fen), false)
+                .is_err_and(|e| e.contains("c7 has 2 moves but c8 has 1 points"))
+        );
+        assert!(parse_epd(&format!("{} c7 \"Rh8+\"; c8 \"ten\";", fen), false).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::tools::cpu::simd_backend;
+use crate::VERSION;
+use std::fmt;
+
+/// The name of the network embedded at build time, as recorded in network.txt.
+pub const NETWORK_NAME: &str = env!("NETWORK_NAME");
+
+/// A JSON value, for the machine-readable output of the tools. Objects keep their fields in
+/// insertion order.
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    pub fn object<const N: usize>(fields: [(&'static str, Json); N]) -> Json {
+        Json::Object(fields.into_iter().collect())
+    }
+
+    /// Append a field to an object. Has no effect on any other value.
+    pub fn push(&mut self, key: &'static str, value: Json) {
+        if let Json::Object(fields) = self {
+            fields.push((key, value));
+        }
+    }
+}
+
+/// The build metadata included in every document: the engine version, the SIMD backend in use,
+/// and the name of the embedded network.
+pub fn build_info() -> Json {
+    Json::object([
+        ("version", VERSION.into()),
+        ("simd", simd_backend().name().into()),
+        ("network", NETWORK_NAME.into()),
+    ])
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(value: i32) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Float(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::Str(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::Str(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Self {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Int(value) => write!(f, "{}", value),
+            // JSON has no representation of infinity or NaN.
+            Json::Float(value) if !value.is_finite() => write!(f, "null"),
+            Json::Float(value) => write!(f, "{}", value),
+            Json::Str(value) => write_str(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_display() {
+        let mut doc = Json::object([
+            ("name", "a \"quoted\"\nline".into()),
+            ("nodes", 42u64.into()),
+            ("loss", Some(0.5).into()),
+            ("missing", None::<i32>.into()),
+            ("nan", f64::NAN.into()),
+            ("list", vec![1, -2].into()),
+        ]);
+        doc.push("ok", true.into());
+        assert_eq!(
+            doc.to_string(),
+            concat!(
+                r#"{"name":"a \"quoted\"\nline","nodes":42,"loss":0.5,"#,
+                r#""missing":null,"nan":null,"list":[1,-2],"ok":true}"#
+            )
+        );
+    }
+}
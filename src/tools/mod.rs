@@ -1,9 +1,21 @@
 pub mod bench;
+pub mod cpu;
+pub mod dataformat;
 pub mod datagen;
 pub mod debug;
+pub mod engine_match;
+pub mod epd;
 pub mod fen;
+pub mod json;
+pub mod param_file;
 pub mod perft;
+pub mod pgn;
 pub mod pretty;
+pub mod rescore;
 pub mod scharnagl;
+#[cfg(feature = "tuning")]
+pub mod spsa;
+pub mod sprt;
 pub mod uci;
 pub mod utils;
+pub mod wdl_fit;
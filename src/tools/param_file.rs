@@ -0,0 +1,153 @@
+use crate::search::parameters::{array_params, params, set_array_param, set_param};
+use crate::tools::utils::TunableParam;
+use std::fs;
+
+/// Load a parameter file of `name=value` lines, where array entries are named by their index as in
+/// `lmr_factor_1_3`. Anything after a '#' is a comment, and fractional values, as written by the
+/// SPSA checkpoint, are rounded. Every value is checked against its declared range before any is
+/// applied, so an invalid file leaves the parameters unchanged. Returns the number of parameters
+/// set.
+pub fn load_params(path: &str) -> Result<usize, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let declared: Vec<TunableParam> = params().into_iter().chain(array_params()).collect();
+
+    let mut values = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: String| format!("{} line {}: {}", path, i + 1, message);
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("'{}' is not name=value", line)))?;
+        let (name, value) = (name.trim(), value.trim());
+        let param = declared
+            .iter()
+            .find(|param| param.name == name)
+            .ok_or_else(|| error(format!("unknown parameter '{}'", name)))?;
+        let value = value
+            .parse::<f64>()
+            .map(|value| value.round())
+            .map_err(|_| error(format!("invalid value '{}' for {}", value, name)))?;
+        if value < param.min as f64 || value > param.max as f64 {
+            return Err(error(format!(
+                "{}={} is outside {}..={}",
+                name, value, param.min, param.max
+            )));
+        }
+        values.push((name, value as i32));
+    }
+
+    for &(name, value) in &values {
+        if !set_array_param(name, value) {
+            set_param(name, value);
+        }
+    }
+    Ok(values.len())
+}
+
+/// The current value of every parameter as a parameter file, with the declared default and range
+/// of each parameter in a trailing comment.
+pub fn format_params() -> String {
+    params()
+        .into_iter()
+        .chain(array_params())
+        .map(|param| {
+            format!(
+                "{}={} # default {}, {}..={}\n",
+                param.name, param.value, param.default, param.min, param.max
+            )
+        })
+        .collect()
+}
+
+pub fn save_params(path: &str) -> Result<(), String> {
+    fs::write(path, format_params()).map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+/// Restore every parameter to its declared default.
+pub fn reset_params() {
+    for param in params().into_iter().chain(array_params()) {
+        if !set_array_param(&param.name, param.default) {
+            set_param(&param.name, param.default);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::parameters::{asp_delta, lmr_factor_1};
+    use std::sync::Mutex;
+
+    /// The parameters are global, so the tests which load them must not overlap.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// Load a parameter file with the given contents, from a file unique to the test.
+    fn load(name: &str, contents: &str) -> Result<usize, String> {
+        let path = std::env::temp_dir().join(format!("hobbes_{}_{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        let result = load_params(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_load_params_comments_and_rounding() {
+        let _lock = LOCK.lock().unwrap();
+        let contents = "# tuned values\n\nasp_delta = 11.6 # was 10\nlmr_factor_1_0=  0.4\n";
+        assert_eq!(load("comments", contents), Ok(2));
+        assert_eq!(asp_delta(), 12);
+        assert_eq!(lmr_factor_1(0), 0);
+
+        let contents = "asp_delta=-0.4";
+        assert!(load("rounding", contents).is_err_and(|e| e.contains("outside")));
+        reset_params();
+    }
+
+    #[test]
+    fn test_load_params_rejects_invalid_files() {
+        let _lock = LOCK.lock().unwrap();
+        let default = asp_delta();
+        for (contents, message) in [
+            (
+                "asp_delta=12\nasp_delta=37\n",
+                "line 2: asp_delta=37 is outside 4..=36",
+            ),
+            ("asp_delta=12\nasp_delta=3", "asp_delta=3 is outside 4..=36"),
+            (
+                "asp_delta=12\nno_such_param=3",
+                "line 2: unknown parameter 'no_such_param'",
+            ),
+            (
+                "asp_delta=12\nlmr_factor_1_99=3",
+                "unknown parameter 'lmr_factor_1_99'",
+            ),
+            ("asp_delta=12\nasp_delta", "'asp_delta' is not name=value"),
+            ("asp_delta=twelve", "invalid value 'twelve' for asp_delta"),
+        ] {
+            let error = load("invalid", contents).unwrap_err();
+            assert!(error.contains(message), "{}", error);
+            assert_eq!(asp_delta(), default, "{}", contents);
+        }
+    }
+
+    #[test]
+    fn test_format_params_round_trip() {
+        let _lock = LOCK.lock().unwrap();
+        let declared = params().len() + array_params().len();
+        assert_eq!(load("round_trip", "asp_delta=20\nlmr_factor_1_2=7"), Ok(2));
+        let formatted = format_params();
+        assert!(formatted.contains("asp_delta=20 # default 10, 4..=36\n"));
+
+        reset_params();
+        assert_eq!(asp_delta(), 10);
+        assert_eq!(load("round_trip", &formatted), Ok(declared));
+        assert_eq!(asp_delta(), 20);
+        assert_eq!(lmr_factor_1(2), 7);
+        assert_eq!(format_params(), formatted);
+        reset_params();
+    }
+}
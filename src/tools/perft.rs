@@ -1,38 +1,60 @@
 use crate::board::movegen::MoveFilter;
-use crate::board::moves::MoveList;
+use crate::board::moves::{Move, MoveList};
 use crate::board::observer::NullBoardObserver;
 use crate::board::Board;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
 
 pub fn perft<const BULK: bool>(board: &Board, depth: u8) -> u64 {
-    let mut moves = MoveList::new();
-    board.gen_moves(MoveFilter::All, &mut moves);
-    let mut total = 0;
+    let entries = divide::<BULK>(board, depth, 1, None);
+    for (mv, count) in &entries {
+        println!("{} - {}", mv, count);
+    }
+    entries.iter().map(|(_, count)| count).sum()
+}
+
+/// Count the leaf nodes below each legal move from the given position, sorted by move. The root
+/// moves are shared out between the given number of threads, which may also share a hash table.
+pub fn divide<const BULK: bool>(
+    board: &Board,
+    depth: u8,
+    threads: usize,
+    table: Option<&PerftTable>,
+) -> Vec<(String, u64)> {
+    let mut move_list = MoveList::new();
+    board.gen_moves(MoveFilter::All, &mut move_list);
+    let moves: Vec<Move> = move_list.iter().map(|entry| entry.mv).collect();
+    let counts: Vec<AtomicU64> = moves.iter().map(|_| AtomicU64::new(0)).collect();
+    let next = AtomicUsize::new(0);
+
+    std::thread::scope(|s| {
+        for _ in 0..threads.clamp(1, moves.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Relaxed);
+                let Some(mv) = moves.get(i) else {
+                    break;
+                };
+                let mut child = *board;
+                child.make(mv, &mut NullBoardObserver);
+                let nodes = if depth <= 1 {
+                    1
+                } else {
+                    perft_inner::<BULK>(&child, depth - 1, table)
+                };
+                counts[i].store(nodes, Relaxed);
+            });
+        }
+    });
 
     let mut entries: Vec<(String, u64)> = moves
         .iter()
-        .map(|entry| {
-            let mv = entry.mv;
-            let mut child = *board;
-            child.make(&mv, &mut NullBoardObserver);
-            let nodes = if depth <= 1 {
-                1
-            } else {
-                perft_inner::<BULK>(&child, depth - 1)
-            };
-            total += nodes;
-            (mv.to_uci(), nodes)
-        })
+        .zip(&counts)
+        .map(|(mv, count)| (mv.to_uci(), count.load(Relaxed)))
         .collect();
-
     entries.sort_by(|(a, _), (b, _)| a.cmp(b));
-    for (mv, count) in &entries {
-        println!("{} - {}", mv, count);
-    }
-
-    total
+    entries
 }
 
-fn perft_inner<const BULK: bool>(board: &Board, depth: u8) -> u64 {
+fn perft_inner<const BULK: bool>(board: &Board, depth: u8, table: Option<&PerftTable>) -> u64 {
     let mut moves = MoveList::new();
     board.gen_moves(MoveFilter::All, &mut moves);
 
@@ -40,6 +62,12 @@ fn perft_inner<const BULK: bool>(board: &Board, depth: u8) -> u64 {
         return moves.len() as u64;
     }
 
+    // Leaf nodes are cheaper to count than to look up.
+    let table = table.filter(|_| depth > 1);
+    if let Some(nodes) = table.and_then(|table| table.probe(board.hash(), depth)) {
+        return nodes;
+    }
+
     let mut nodes = 0;
     for entry in moves.iter() {
         let mv = entry.mv;
@@ -48,8 +76,145 @@ fn perft_inner<const BULK: bool>(board: &Board, depth: u8) -> u64 {
         nodes += if depth == 1 {
             1
         } else {
-            perft_inner::<BULK>(&child, depth - 1)
+            perft_inner::<BULK>(&child, depth - 1, table)
         };
     }
+
+    if let Some(table) = table {
+        table.store(board.hash(), depth, nodes);
+    }
     nodes
 }
+
+/// A hash table of subtree node counts, keyed on the position hash and the remaining depth, which
+/// may be shared between threads. Entries are written without locking, so each one stores its key
+/// xor-ed with its node count, and an entry torn by a concurrent write is ignored on lookup.
+pub struct PerftTable {
+    entries: Box<[PerftEntry]>,
+}
+
+#[derive(Default)]
+struct PerftEntry {
+    key: AtomicU64,
+    nodes: AtomicU64,
+}
+
+impl PerftTable {
+    pub fn new(mb: usize) -> PerftTable {
+        let len = (mb * 1024 * 1024 / size_of::<PerftEntry>()).max(1);
+        PerftTable {
+            entries: (0..len).map(|_| PerftEntry::default()).collect(),
+        }
+    }
+
+    pub fn clear(&self) {
+        for entry in self.entries.iter() {
+            entry.key.store(0, Relaxed);
+            entry.nodes.store(0, Relaxed);
+        }
+    }
+
+    fn key(hash: u64, depth: u8) -> u64 {
+        hash ^ (depth as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn entry(&self, key: u64) -> &PerftEntry {
+        let index = ((key as u128 * self.entries.len() as u128) >> 64) as usize;
+        &self.entries[index]
+    }
+
+    fn probe(&self, hash: u64, depth: u8) -> Option<u64> {
+        let key = Self::key(hash, depth);
+        let entry = self.entry(key);
+        let nodes = entry.nodes.load(Relaxed);
+        (nodes != 0 && entry.key.load(Relaxed) ^ nodes == key).then_some(nodes)
+    }
+
+    fn store(&self, hash: u64, depth: u8, nodes: u64) {
+        let key = Self::key(hash, depth);
+        let entry = self.entry(key);
+        entry.key.store(key ^ nodes, Relaxed);
+        entry.nodes.store(nodes, Relaxed);
+    }
+}
+
+/// The expected node counts for one EPD line, as `(depth, nodes)` pairs from its `;Dn` entries.
+pub struct PerftEpdEntry {
+    pub fen: String,
+    pub expected: Vec<(u8, u64)>,
+}
+
+/// Parse a line of a perft EPD file, in the form `<fen> ;D1 <nodes> ;D2 <nodes> ...`.
+pub fn parse_perft_epd(line: &str) -> Result<PerftEpdEntry, String> {
+    let mut parts = line.split(';');
+    let fen = parts.next().unwrap_or_default().trim().to_string();
+    let mut expected = Vec::new();
+    for part in parts {
+        let part = part.trim();
+        let Some((depth, nodes)) = part.strip_prefix('D').and_then(|p| p.split_once(' ')) else {
+            return Err(format!("invalid perft entry '{}'", part));
+        };
+        match (depth.parse::<u8>(), nodes.trim().parse::<u64>()) {
+            (Ok(depth), Ok(nodes)) => expected.push((depth, nodes)),
+            _ => return Err(format!("invalid perft entry '{}'", part)),
+        }
+    }
+    Ok(PerftEpdEntry { fen, expected })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use crate::tools::fen;
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    #[test]
+    fn test_divide_threads_and_table() {
+        init();
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let frc = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let table = PerftTable::new(1);
+        for (fen, frc, depth, expected) in [
+            (fen::STARTPOS, false, 4, 197_281),
+            (kiwipete, false, 3, 97_862),
+            (frc, true, 4, 326_672),
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            board.set_frc(frc);
+            let single = divide::<false>(&board, depth, 1, None);
+            let total: u64 = single.iter().map(|(_, nodes)| nodes).sum();
+            assert_eq!(total, expected, "{}", fen);
+
+            // Threads and the table must not change the breakdown, even once the table is warm.
+            table.clear();
+            for _ in 0..2 {
+                let parallel = divide::<true>(&board, depth, 4, Some(&table));
+                assert_eq!(parallel, single, "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_perft_epd() {
+        let entry = parse_perft_epd("8/8/8/8/8/8/8/K6k w - - 0 1 ;D1 3 ;D2 9").unwrap();
+        assert_eq!(entry.fen, "8/8/8/8/8/8/8/K6k w - - 0 1");
+        assert_eq!(entry.expected, vec![(1, 3), (2, 9)]);
+
+        for line in [
+            "8/8/8/8/8/8/8/K6k w - - 0 1 ;D1 x",
+            "8/8/8/8/8/8/8/K6k w - - 0 1 ;E1 3",
+            "8/8/8/8/8/8/8/K6k w - - 0 1 ;D1",
+        ] {
+            assert!(parse_perft_epd(line).is_err_and(|e| e.contains("invalid perft entry")));
+        }
+    }
+}
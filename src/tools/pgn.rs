@@ -0,0 +1,490 @@
+use crate::board::castling;
+use crate::board::moves::Move;
+use crate::board::observer::NullBoardObserver;
+use crate::board::san::{parse_san, to_san};
+use crate::board::side::Side::White;
+use crate::board::Board;
+use crate::search::score;
+use crate::tools::dataformat::GameResult;
+use crate::tools::fen;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+
+/// The maximum width of a line of movetext when writing a game.
+const LINE_WIDTH: usize = 80;
+
+/// A game of chess: its tag pairs, starting position and moves, with the annotations that the
+/// tools read and write. The same representation is used for games from PGN files, self-play and
+/// analysis.
+#[derive(Clone)]
+pub struct PgnGame {
+    /// The tag pairs, in the order they are written.
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub moves: Vec<PgnMove>,
+    /// The result of the game, or `None` if it is unfinished (`*`).
+    pub result: Option<GameResult>,
+}
+
+#[derive(Clone, Default)]
+pub struct PgnMove {
+    pub mv: Move,
+    /// The engine's score after searching the move, from the perspective of the side that played
+    /// it, and the depth it searched to. Written as a `{score/depth}` comment.
+    pub score: Option<i32>,
+    pub depth: Option<i32>,
+    /// Any other comment following the move.
+    pub comment: Option<String>,
+    /// Numeric annotation glyphs, with the `!` and `?` suffixes converted to their `$n` form.
+    pub nags: Vec<u8>,
+}
+
+impl PgnMove {
+    pub fn new(mv: Move) -> PgnMove {
+        PgnMove {
+            mv,
+            ..PgnMove::default()
+        }
+    }
+
+    /// Record a comment on the move, reading a leading `score/depth` annotation if there is one.
+    fn annotate(&mut self, comment: &str) {
+        let (head, rest) = comment.split_once(' ').unwrap_or((comment, ""));
+        let parsed = head
+            .split_once('/')
+            .and_then(|(score, depth)| Some((parse_score(score)?, depth.parse::<i32>().ok()?)));
+        match parsed {
+            Some((score, depth)) => {
+                self.score = Some(score);
+                self.depth = Some(depth);
+                let rest = rest.trim();
+                if !rest.is_empty() {
+                    self.comment = Some(rest.to_string());
+                }
+            }
+            None if !comment.is_empty() => self.comment = Some(comment.to_string()),
+            None => {}
+        }
+    }
+}
+
+impl PgnGame {
+    /// A game from the given position with the seven required tags, plus the `SetUp`, `FEN` and
+    /// `Variant` tags when the game does not start from the standard starting position.
+    pub fn new(start: Board) -> PgnGame {
+        let mut game = PgnGame {
+            tags: Vec::new(),
+            start,
+            moves: Vec::new(),
+            result: None,
+        };
+        for name in ["Event", "Site", "Date", "Round", "White", "Black"] {
+            game.set_tag(name, if name == "Date" { "????.??.??" } else { "?" });
+        }
+        game.set_tag("Result", "*");
+        if start.is_frc() {
+            game.set_tag("Variant", "Chess960");
+        }
+        let fen = start.to_fen();
+        if fen != fen::STARTPOS {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set the value of a tag, replacing its existing value or adding it after the others.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn set_result(&mut self, result: Option<GameResult>) {
+        self.result = result;
+        self.set_tag("Result", result_str(result));
+    }
+
+    /// The position before each move, followed by the final position.
+    pub fn boards(&self) -> Vec<Board> {
+        let mut boards = Vec::with_capacity(self.moves.len() + 1);
+        let mut board = self.start;
+        boards.push(board);
+        for mv in &self.moves {
+            board.make(&mv.mv, &mut NullBoardObserver);
+            boards.push(board);
+        }
+        boards
+    }
+
+    /// Parse a single game: its tag pairs followed by its movetext. Variations are skipped, and
+    /// moves are resolved against the position, so an illegal or ambiguous move is an error.
+    pub fn parse(text: &str) -> Result<PgnGame, String> {
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if movetext.is_empty() && line.starts_with('[') {
+                tags.push(parse_tag(line)?);
+            } else if !line.starts_with('%') {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        let tag = |name: &str| tags.iter().find(|(key, _)| key == name).map(|(_, v)| v);
+        let frc = tag("Variant").is_some_and(|variant: &String| {
+            let variant = variant.to_lowercase();
+            variant.contains("960") || variant.contains("fischer")
+        });
+        let fen = match (tag("SetUp").map(String::as_str), tag("FEN")) {
+            (Some("0"), _) | (_, None) => fen::STARTPOS.to_string(),
+            (_, Some(fen)) => fen.clone(),
+        };
+        let mut start =
+            Board::from_fen(&fen).map_err(|e| format!("invalid fen '{}': {}", fen, e))?;
+        start.set_frc(frc || castling::requires_frc(&start));
+
+        let mut game = PgnGame {
+            tags,
+            start,
+            moves: Vec::new(),
+            result: None,
+        };
+        game.parse_movetext(&movetext)?;
+        Ok(game)
+    }
+
+    fn parse_movetext(&mut self, text: &str) -> Result<(), String> {
+        let mut board = self.start;
+        let mut chars = text.chars().peekable();
+        let mut variation_depth = 0;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    if let Some(last) = self.moves.last_mut().filter(|_| variation_depth == 0) {
+                        last.annotate(comment.trim());
+                    }
+                }
+                ';' => {
+                    chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+                }
+                '(' => variation_depth += 1,
+                ')' => variation_depth = (variation_depth - 1).max(0),
+                c if c.is_whitespace() => {}
+                _ => {
+                    let mut token = c.to_string();
+                    while let Some(&next) = chars.peek() {
+                        if next.is_whitespace() || "{}();".contains(next) {
+                            break;
+                        }
+                        token.push(next);
+                        chars.next();
+                    }
+                    if variation_depth == 0 {
+                        self.parse_token(&mut board, &token)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_token(&mut self, board: &mut Board, token: &str) -> Result<(), String> {
+        if let Some(nag) = token.strip_prefix('$') {
+            let nag = nag
+                .parse()
+                .map_err(|_| format!("invalid NAG '{}'", token))?;
+            if let Some(last) = self.moves.last_mut() {
+                last.nags.push(nag);
+            }
+            return Ok(());
+        }
+        if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+            self.result = GameResult::parse(token);
+            return Ok(());
+        }
+
+        // Move numbers may be written apart from or attached to the move, as in "12." or "12...e5".
+        // Digits are only a move number when followed by a dot, so that "0-0" is left intact.
+        let san = match token.trim_start_matches(|c: char| c.is_ascii_digit()) {
+            rest if rest.len() < token.len() && rest.starts_with('.') => {
+                rest.trim_start_matches('.')
+            }
+            _ => token.trim_start_matches('.'),
+        };
+        if san.is_empty() {
+            return Ok(());
+        }
+        let (san, suffix) = san.split_at(san.trim_end_matches(['!', '?']).len());
+        let mv = parse_san(board, san)
+            .map_err(|e| format!("move {}: {}", self.moves.len() / 2 + 1, e))?;
+        board.make(&mv, &mut NullBoardObserver);
+
+        let mut pgn_move = PgnMove::new(mv);
+        pgn_move.nags.extend(suffix_nag(suffix));
+        self.moves.push(pgn_move);
+        Ok(())
+    }
+}
+
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        let mut board = self.start;
+        let mut needs_number = true;
+        for pgn_move in &self.moves {
+            if board.stm == White {
+                tokens.push(format!("{}.", board.fm));
+            } else if needs_number {
+                tokens.push(format!("{}...", board.fm));
+            }
+            tokens.push(to_san(&board, pgn_move.mv));
+            tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+
+            let mut comment = Vec::new();
+            if let (Some(score), Some(depth)) = (pgn_move.score, pgn_move.depth) {
+                comment.push(format!("{}/{}", format_score(score), depth));
+            }
+            comment.extend(pgn_move.comment.clone());
+            needs_number = !comment.is_empty();
+            if needs_number {
+                tokens.push(format!("{{{}}}", comment.join(" ")));
+            }
+            board.make(&pgn_move.mv, &mut NullBoardObserver);
+        }
+        tokens.push(result_str(self.result).to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{}", line)?;
+        writeln!(f)
+    }
+}
+
+/// Reads the games of a PGN file one at a time, so that large files can be streamed.
+pub struct PgnReader<R: BufRead> {
+    lines: Lines<R>,
+    pending: Option<String>,
+}
+
+impl PgnReader<BufReader<File>> {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+        Ok(PgnReader::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        PgnReader {
+            lines: reader.lines(),
+            pending: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, String>;
+
+    /// A game ends where the tag pairs of the next game begin, or at the end of the file.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut text = self.pending.take().unwrap_or_default();
+        let mut in_movetext = false;
+        loop {
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(format!("could not read pgn: {}", e))),
+                None => break,
+            };
+            let trimmed = line.trim_start_matches('\u{feff}').trim();
+            if trimmed.starts_with('[') && in_movetext {
+                self.pending = Some(format!("{}\n", trimmed));
+                break;
+            }
+            if !trimmed.is_empty() && !trimmed.starts_with('[') {
+                in_movetext = true;
+            }
+            text.push_str(trimmed);
+            text.push('\n');
+        }
+        if text.trim().is_empty() {
+            return None;
+        }
+        Some(PgnGame::parse(&text))
+    }
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), String> {
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .ok_or_else(|| format!("invalid tag '{}'", line))?;
+    let (name, value) = inner
+        .split_once(' ')
+        .ok_or_else(|| format!("invalid tag '{}'", line))?;
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+    let value = value.replace("\\\"", "\"").replace("\\\\", "\\");
+    Ok((name.to_string(), value))
+}
+
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+fn result_str(result: Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::WhiteWin) => "1-0",
+        Some(GameResult::Draw) => "1/2-1/2",
+        Some(GameResult::BlackWin) => "0-1",
+        None => "*",
+    }
+}
+
+/// Format a score in pawns, or as `+M<n>`/`-M<n>` for a mate in `n` moves.
+fn format_score(score: i32) -> String {
+    if score::is_mate(score) {
+        let sign = if score > 0 { '+' } else { '-' };
+        format!("{}M{}", sign, score::moves_to_mate(score))
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
+    }
+}
+
+fn parse_score(text: &str) -> Option<i32> {
+    let (sign, rest) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text.trim_start_matches('+')),
+    };
+    if let Some(moves) = rest.strip_prefix('M') {
+        let moves = moves.parse::<usize>().ok().filter(|&moves| moves > 0)?;
+        return Some(if sign > 0 {
+            score::mate_in(2 * moves - 1)
+        } else {
+            score::mated_in(2 * moves)
+        });
+    }
+    let pawns = rest.parse::<f64>().ok()?;
+    Some(sign * (pawns * 100.0).round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray};
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    const GAME: &str = r#"[Event "Test \"quoted\""]
+[Site "?"]
+[Result "1-0"]
+
+1. e4 {+0.30/12 book} e5 2. Nf3 $1 (2. f4 exf4 {gambit}) Nc6?! ; rest of line
+3. Bb5 {+M3/20} a6 4. Bxc6 dxc6 1-0
+"#;
+
+    #[test]
+    fn test_pgn_round_trip() {
+        init();
+        let game = PgnGame::parse(GAME).unwrap();
+        assert_eq!(game.tag("Event"), Some("Test \"quoted\""));
+        assert_eq!(game.result, Some(GameResult::WhiteWin));
+        assert_eq!(game.moves.len(), 8);
+        assert_eq!(
+            (game.moves[0].score, game.moves[0].depth),
+            (Some(30), Some(12))
+        );
+        assert_eq!(game.moves[0].comment.as_deref(), Some("book"));
+        assert_eq!(game.moves[2].nags, vec![1]);
+        assert_eq!(game.moves[3].nags, vec![6]);
+        assert_eq!(game.moves[4].score, Some(score::mate_in(5)));
+
+        let written = game.to_string();
+        assert!(written.contains("1. e4 {+0.30/12 book} 1... e5 2. Nf3 $1 Nc6 $6 3. Bb5 {+M3/20}"));
+        let reparsed = PgnGame::parse(&written).unwrap();
+        let moves = |game: &PgnGame| game.moves.iter().map(|m| m.mv).collect::<Vec<Move>>();
+        assert_eq!(moves(&reparsed), moves(&game));
+        assert_eq!(reparsed.tags, game.tags);
+
+        let file = format!("{}\n{}", GAME, written);
+        let games: Vec<PgnGame> = PgnReader::new(file.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[1].moves.len(), 8);
+    }
+
+    #[test]
+    fn test_pgn_chess960_setup() {
+        init();
+        let fen = "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1";
+        let text = format!(
+            "[Variant \"Chess960\"]\n[SetUp \"1\"]\n[FEN \"{}\"]\n\n1. O-O-O O-O *\n",
+            fen
+        );
+        let game = PgnGame::parse(&text).unwrap();
+        assert!(game.start.is_frc());
+        assert_eq!(game.result, None);
+        assert!(game.moves[0].mv.is_castle());
+        assert_eq!(game.boards().len(), 3);
+        assert!(PgnGame::new(game.start)
+            .to_string()
+            .contains("[Variant \"Chess960\"]"));
+    }
+
+    #[test]
+    fn test_pgn_castling_with_zeros() {
+        init();
+        let text = "1. e4 e5 2. Nf3 Nc6 3. Bc4 d6 4. 0-0 Bg4 5. d3 Qd7 6. Nc3 0-0-0 7.Re1 7...Kb8";
+        let game = PgnGame::parse(text).unwrap();
+        assert_eq!(game.moves.len(), 14);
+        assert!(game.moves[6].mv.is_castle());
+        assert!(game.moves[11].mv.is_castle());
+        assert!(game.to_string().contains("4. O-O Bg4"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::tools::cpu::simd_backend;
 use crate::{AUTHOR, CONTRIBUTORS, VERSION};
 
 const CYAN: &str = "\x1b[36m";
@@ -7,6 +8,7 @@ pub fn print_uci_info() {
     let version_formatted = format!("{CYAN}{VERSION}{RESET}");
     let author_formatted = format!("{CYAN}{}{}", AUTHOR, RESET);
     let contributors_formatted = format!("{CYAN}{}{}", CONTRIBUTORS, RESET);
+    let simd_formatted = format!("{CYAN}{}{RESET}", simd_backend().name());
     let calvinbench_formatted =
         format!("CalvinBench {CYAN}https://kelseyde.pythonanywhere.com/index/{RESET}");
     let mattbench_formatted = format!("MattBench {CYAN}https://chess.n9x.co/index/{RESET}");
@@ -15,6 +17,7 @@ pub fn print_uci_info() {
     println!("│ Version: {:<66}│", version_formatted);
     println!("│ Author: {:<67}│", author_formatted);
     println!("│ Contributors: {:<61}│", contributors_formatted);
+    println!("│ SIMD: {:<69}│", simd_formatted);
     println!("│ OpenBench: {:<64}│", calvinbench_formatted);
     println!("│            {:<64}│", mattbench_formatted);
     println!("└───────────────────────────────────────────────────────────────────┘");
@@ -0,0 +1,307 @@
+use crate::board::moves::Move;
+use crate::board::side::Side::White;
+use crate::search::score::is_mate;
+use crate::search::search;
+use crate::search::thread::{SharedContext, ThreadData};
+use crate::search::time::SearchLimits;
+use crate::tools::dataformat::{
+    clamp_score, for_each_position, write_position, DataFormat, TrainingPosition,
+};
+use crate::tools::datagen::{HARD_NODES_SCALE, REPORT_INTERVAL};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Instant;
+
+pub struct RescoreConfig {
+    pub input: String,
+    pub from: DataFormat,
+    pub output: String,
+    pub to: DataFormat,
+    /// The soft node limit for re-searching each position, or `None` to use the static evaluation.
+    pub nodes: Option<u64>,
+    pub hash_mb: usize,
+    pub filters: Filters,
+}
+
+/// The filters applied to each position after it has been rescored. Each position is counted
+/// against the first filter that rejects it, in the order of the fields below.
+pub struct Filters {
+    pub in_check: bool,
+    /// Reject positions where the best move is a capture or promotion. The best move comes from the
+    /// search when rescoring with a search, or from the game record when the input is viriformat.
+    pub noisy: bool,
+    pub mate: bool,
+    /// Reject positions whose new score, in absolute value, exceeds this bound.
+    pub max_eval: Option<i32>,
+    /// Reject positions outside this range of plies, derived from the fullmove counter.
+    pub min_ply: Option<u32>,
+    pub max_ply: Option<u32>,
+    /// Reject positions whose hash has already been seen earlier in the file.
+    pub duplicates: bool,
+}
+
+#[derive(Default)]
+pub struct RescoreStats {
+    pub read: u64,
+    pub written: u64,
+    pub in_check: u64,
+    pub noisy: u64,
+    pub mate: u64,
+    pub extreme: u64,
+    pub ply: u64,
+    pub duplicate: u64,
+}
+
+/// Stream a training data file, relabel every position with a new score from either the static
+/// evaluation or a fixed-node search, and write the positions which pass the filters. Viriformat
+/// input is expanded into its positions, so the output must be in a per-position format.
+pub fn run_rescore(config: &RescoreConfig) -> Result<RescoreStats, String> {
+    if config.to == DataFormat::Viri {
+        return Err("rescored data must be written as text or bullet".to_string());
+    }
+    if config.filters.noisy && config.nodes.is_none() && config.from != DataFormat::Viri {
+        println!(
+            "info string rescore skipping the noisy filter, as there is no best move without \
+             viriformat input or a search"
+        );
+    }
+    let has_ply_filter = config.filters.min_ply.is_some() || config.filters.max_ply.is_some();
+    if has_ply_filter && config.from == DataFormat::Bullet {
+        println!(
+            "info string rescore skipping the ply filter, as bulletformat has no move counters"
+        );
+    }
+
+    let output = File::create(&config.output)
+        .map_err(|e| format!("could not create {}: {}", config.output, e))?;
+    let mut writer = BufWriter::new(output);
+
+    let shared = Arc::new(SharedContext::new(config.hash_mb));
+    let mut td = Box::new(ThreadData::new(
+        0,
+        true,
+        shared,
+        Arc::new(AtomicBool::new(false)),
+    ));
+    td.quiet = true;
+
+    let mut stats = RescoreStats::default();
+    let mut seen = HashSet::new();
+    let start = Instant::now();
+    let mut last_report = Instant::now();
+    let mut positions = 0u64;
+
+    stats.read = for_each_position(&config.input, config.from, |pos, game_move| {
+        positions += 1;
+        let (score, best_move) = rescore(&mut td, pos, config.nodes);
+        let best_move = best_move.or(game_move);
+        if let Some(reject) = filter(
+            &config.filters,
+            config.from,
+            pos,
+            best_move,
+            score,
+            &mut seen,
+        ) {
+            *reject.count(&mut stats) += 1;
+        } else {
+            let white_score = if pos.board.stm == White {
+                score
+            } else {
+                -score
+            };
+            let rescored = TrainingPosition {
+                score: clamp_score(white_score),
+                ..*pos
+            };
+            write_position(&mut writer, &rescored, config.to)
+                .map_err(|e| format!("could not write to {}: {}", config.output, e))?;
+            stats.written += 1;
+        }
+
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            let seconds = start.elapsed().as_secs_f64().max(0.001);
+            println!(
+                "info string rescore positions {} written {} pos/s {:.0}",
+                positions,
+                stats.written,
+                positions as f64 / seconds
+            );
+            last_report = Instant::now();
+        }
+        Ok(())
+    })?;
+
+    writer
+        .flush()
+        .map_err(|e| format!("could not write to {}: {}", config.output, e))?;
+    Ok(stats)
+}
+
+/// Score a position from the side to move's perspective, returning the best move if a search was
+/// used.
+fn rescore(td: &mut ThreadData, pos: &TrainingPosition, nodes: Option<u64>) -> (i32, Option<Move>) {
+    let board = &pos.board;
+    let Some(soft_nodes) = nodes else {
+        td.nnue.activate(board);
+        return (td.nnue.evaluate(board), None);
+    };
+    td.keys.clear();
+    td.keys.push(board.hash());
+    td.root_ply = 0;
+    td.reset();
+    td.tt().birthday();
+    let hard_nodes = soft_nodes * HARD_NODES_SCALE;
+    td.limits = SearchLimits::new(None, None, Some(soft_nodes), Some(hard_nodes), None, 0, 0);
+    td.start_time = Instant::now();
+    let (mv, score) = search(board, td);
+    (score, Some(mv))
+}
+
+#[derive(Clone, Copy)]
+enum Reject {
+    InCheck,
+    Noisy,
+    Mate,
+    Extreme,
+    Ply,
+    Duplicate,
+}
+
+impl Reject {
+    fn count(self, stats: &mut RescoreStats) -> &mut u64 {
+        match self {
+            Reject::InCheck => &mut stats.in_check,
+            Reject::Noisy => &mut stats.noisy,
+            Reject::Mate => &mut stats.mate,
+            Reject::Extreme => &mut stats.extreme,
+            Reject::Ply => &mut stats.ply,
+            Reject::Duplicate => &mut stats.duplicate,
+        }
+    }
+}
+
+/// Find the first filter which rejects the position, if any. The score is from the side to move's
+/// perspective.
+fn filter(
+    filters: &Filters,
+    from: DataFormat,
+    pos: &TrainingPosition,
+    best_move: Option<Move>,
+    score: i32,
+    seen: &mut HashSet<u64>,
+) -> Option<Reject> {
+    let board = &pos.board;
+    if filters.in_check && !board.checkers.is_empty() {
+        return Some(Reject::InCheck);
+    }
+    if filters.noisy && best_move.is_some_and(|mv| board.is_noisy(&mv)) {
+        return Some(Reject::Noisy);
+    }
+    if filters.mate && is_mate(score) {
+        return Some(Reject::Mate);
+    }
+    if filters.max_eval.is_some_and(|max| score.abs() > max) {
+        return Some(Reject::Extreme);
+    }
+    if from != DataFormat::Bullet {
+        let ply = 2 * (board.fm as u32).saturating_sub(1) + (board.stm != White) as u32;
+        let too_early = filters.min_ply.is_some_and(|min| ply < min);
+        let too_late = filters.max_ply.is_some_and(|max| ply > max);
+        if too_early || too_late {
+            return Some(Reject::Ply);
+        }
+    }
+    if filters.duplicates && !seen.insert(board.hash()) {
+        return Some(Reject::Duplicate);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{magics, ray, Board};
+    use crate::tools::dataformat::GameResult;
+    use std::sync::Once;
+
+    fn init() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            magics::init();
+            ray::init();
+        });
+    }
+
+    #[test]
+    fn test_rescore_round_trip() {
+        init();
+        let kept = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 1234 | 1.0",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 3 3 | -77 | 0.0",
+        ];
+        let rejected = [
+            // A duplicate of the first position.
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 | 5 | 0.5",
+            // White is in check.
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3 | 0 | 0.0",
+            // Ply 119, beyond the maximum.
+            "8/5k2/8/8/8/8/2K5/8 b - - 10 60 | 0 | 0.5",
+        ];
+        let input = [kept[0], rejected[0], rejected[1], kept[1], rejected[2]];
+
+        let dir = std::env::temp_dir().join(format!("hobbes_rescore_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input_path, output_path) = (dir.join("input.txt"), dir.join("output.txt"));
+        std::fs::write(&input_path, input.join("\n")).unwrap();
+
+        let config = RescoreConfig {
+            input: input_path.to_str().unwrap().to_string(),
+            from: DataFormat::Text,
+            output: output_path.to_str().unwrap().to_string(),
+            to: DataFormat::Text,
+            nodes: None,
+            hash_mb: 1,
+            filters: Filters {
+                in_check: true,
+                noisy: true,
+                mate: true,
+                max_eval: None,
+                min_ply: None,
+                max_ply: Some(100),
+                duplicates: true,
+            },
+        };
+        let stats = run_rescore(&config).unwrap();
+        assert_eq!((stats.read, stats.written), (5, 2));
+        assert_eq!((stats.in_check, stats.ply, stats.duplicate), (1, 1, 1));
+
+        // The kept positions are written in order, with their results and new scores from white's
+        // perspective.
+        let mut td = Box::new(ThreadData::default());
+        let mut written = Vec::new();
+        for_each_position(&config.output, DataFormat::Text, |pos, _| {
+            written.push(*pos);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(written.len(), kept.len());
+        for (pos, line) in written.iter().zip(kept) {
+            let original = TrainingPosition::from_text(line).unwrap();
+            assert_eq!(pos.board.to_fen(), original.board.to_fen());
+            assert_eq!(pos.result, original.result);
+
+            let board = Board::from_fen(&original.board.to_fen()).unwrap();
+            td.nnue.activate(&board);
+            let eval = td.nnue.evaluate(&board);
+            let white_eval = if board.stm == White { eval } else { -eval };
+            assert_eq!(pos.score, clamp_score(white_eval));
+        }
+        assert_eq!(written[1].result, GameResult::BlackWin);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+/// The results of game pairs, where each pair plays the same opening with colours reversed. Pair
+/// results are counted by their total score, from 0 (two losses) to 2 (two wins) in steps of half
+/// a point, which captures the correlation between the two games of a pair that a plain
+/// win-draw-loss count ignores.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Pentanomial(pub [u64; 5]);
+
+/// The outcome of a sequential probability ratio test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SprtVerdict {
+    /// The test rejected the null hypothesis: the patch gains at least `elo1`.
+    H1,
+    /// The test accepted the null hypothesis: the patch gains at most `elo0`.
+    H0,
+    Continue,
+}
+
+/// The hypotheses and error rates of a sequential probability ratio test.
+#[derive(Clone, Copy, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Pentanomial {
+    /// Record a pair, given the first engine's total score over its two games.
+    pub fn add_pair(&mut self, score: f64) {
+        let index = (score * 2.0).round().clamp(0.0, 4.0) as usize;
+        self.0[index] += 1;
+    }
+
+    pub fn pairs(self) -> u64 {
+        self.0.iter().sum()
+    }
+
+    /// The mean score per game and its variance per pair, both on a scale of 0 to 1. Empty cells
+    /// are given a small weight, so that the variance is not zero after a run of identical pairs.
+    fn mean_and_variance(self) -> (f64, f64) {
+        let regularise = self.0.contains(&0);
+        let counts = self
+            .0
+            .map(|n| n as f64 + if regularise { 1e-3 } else { 0.0 });
+        let total: f64 = counts.iter().sum();
+        let score = |i: usize| i as f64 / 4.0;
+        let mean = (0..5).map(|i| counts[i] * score(i)).sum::<f64>() / total;
+        let variance = (0..5)
+            .map(|i| counts[i] * (score(i) - mean).powi(2))
+            .sum::<f64>()
+            / total;
+        (mean, variance)
+    }
+
+    /// The mean score per game on a scale of 0 to 1.
+    pub fn score(self) -> f64 {
+        self.mean_and_variance().0
+    }
+
+    /// The logistic Elo difference implied by the score.
+    pub fn elo(self) -> f64 {
+        score_to_elo(self.score())
+    }
+
+    /// The half-width of the 95% confidence interval of the Elo difference.
+    pub fn elo_error(self) -> f64 {
+        let pairs = self.pairs();
+        if pairs == 0 {
+            return f64::INFINITY;
+        }
+        let (mean, variance) = self.mean_and_variance();
+        let error = 1.959964 * (variance / pairs as f64).sqrt();
+        (score_to_elo(mean + error) - score_to_elo(mean - error)) / 2.0
+    }
+
+    /// The likelihood that the first engine is stronger than the second.
+    pub fn los(self) -> f64 {
+        let pairs = self.pairs();
+        if pairs == 0 {
+            return 0.5;
+        }
+        let (mean, variance) = self.mean_and_variance();
+        let deviation = (variance / pairs as f64).sqrt();
+        0.5 * (1.0 + erf((mean - 0.5) / (deviation * std::f64::consts::SQRT_2)))
+    }
+}
+
+impl Sprt {
+    /// The log-likelihood ratio of the two hypotheses, using the normal approximation of the
+    /// generalised SPRT on the pentanomial pair scores, with both hypotheses in logistic Elo.
+    pub fn llr(self, results: Pentanomial) -> f64 {
+        let pairs = results.pairs();
+        if pairs == 0 {
+            return 0.0;
+        }
+        let (mean, variance) = results.mean_and_variance();
+        let s0 = elo_to_score(self.elo0);
+        let s1 = elo_to_score(self.elo1);
+        pairs as f64 * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    /// The lower and upper bounds of the log-likelihood ratio, at which the test stops.
+    pub fn bounds(self) -> (f64, f64) {
+        let lower = (self.beta / (1.0 - self.alpha)).ln();
+        let upper = ((1.0 - self.beta) / self.alpha).ln();
+        (lower, upper)
+    }
+
+    pub fn verdict(self, results: Pentanomial) -> SprtVerdict {
+        let llr = self.llr(results);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtVerdict::H1
+        } else if llr <= lower {
+            SprtVerdict::H0
+        } else {
+            SprtVerdict::Continue
+        }
+    }
+}
+
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// The error function, to within 1.5e-7 (Abramowitz and Stegun, formula 7.1.26).
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - poly * (-x * x).exp();
+    if x >= 0.0 {
+        y
+    } else {
+        -y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sprt_statistics() {
+        let even = Pentanomial([10, 40, 100, 40, 10]);
+        assert!(even.elo().abs() < 1e-9);
+        assert!((even.los() - 0.5).abs() < 1e-9);
+
+        let results = Pentanomial([50, 500, 1000, 550, 60]);
+        assert!((results.elo() - 5.63).abs() < 0.01, "{}", results.elo());
+        assert!(
+            (results.elo_error() - 6.08).abs() < 0.01,
+            "{}",
+            results.elo_error()
+        );
+        assert!((results.los() - 0.965).abs() < 0.001, "{}", results.los());
+
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 1e-3 && (upper - 2.944).abs() < 1e-3);
+        assert!(
+            (sprt.llr(results) - 1.63).abs() < 0.01,
+            "{}",
+            sprt.llr(results)
+        );
+        assert_eq!(sprt.verdict(results), SprtVerdict::Continue);
+        assert_eq!(
+            sprt.verdict(Pentanomial([0, 200, 1000, 600, 0])),
+            SprtVerdict::H1
+        );
+        assert_eq!(
+            sprt.verdict(Pentanomial([0, 600, 1000, 200, 0])),
+            SprtVerdict::H0
+        );
+    }
+}
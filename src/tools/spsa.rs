@@ -0,0 +1,267 @@
+use crate::search::engine::Engine;
+use crate::search::parameters::{array_params, params, set_array_param, set_param};
+use crate::tools::engine_match::{run_match, Adjudication, EngineConfig, MatchConfig, TimeControl};
+use crate::tools::utils::{TunableParam, SPSA_R_END};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+/// The decay exponents of the learning rate and the perturbation size, and the stability constant
+/// as a fraction of the iterations, with the values recommended by Spall and used by OpenBench.
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+const STABILITY: f64 = 0.1;
+
+pub struct SpsaConfig {
+    pub iterations: u64,
+    /// The number of game pairs between the two perturbed parameter sets in each iteration.
+    pub pairs: u64,
+    pub time_control: TimeControl,
+    pub concurrency: usize,
+    pub openings: Option<String>,
+    /// UCI options set on both engines, such as the EvalFile.
+    pub options: Vec<(String, String)>,
+    /// The file the parameters are written to after every iteration. If it already exists, the
+    /// run resumes from the iteration and parameters it holds.
+    pub checkpoint: String,
+    pub seed: u64,
+}
+
+/// A parameter under tuning, which takes fractional values between iterations. `c_end` and
+/// `r_end` are the final perturbation size and learning rate, as in OpenBench's SPSA input.
+struct SpsaParam {
+    name: String,
+    value: f64,
+    min: f64,
+    max: f64,
+    c_end: f64,
+    r_end: f64,
+}
+
+impl SpsaParam {
+    fn new(param: TunableParam) -> SpsaParam {
+        SpsaParam {
+            name: param.name,
+            value: param.value as f64,
+            min: param.min as f64,
+            max: param.max as f64,
+            c_end: param.step.max(1) as f64,
+            r_end: SPSA_R_END,
+        }
+    }
+
+    /// The perturbation size and the learning rate at iteration `k` of `n`, decayed so that they
+    /// reach `c_end` and `r_end` at the last iteration.
+    fn schedule(&self, k: u64, n: u64) -> (f64, f64) {
+        let stability = STABILITY * n as f64;
+        let c = self.c_end * (n as f64).powf(GAMMA);
+        let a = self.r_end * self.c_end.powi(2) * (stability + n as f64).powf(ALPHA);
+        let c_k = c / ((k + 1) as f64).powf(GAMMA);
+        let a_k = a / (stability + (k + 1) as f64).powf(ALPHA);
+        (c_k, a_k / c_k.powi(2))
+    }
+
+    fn clamp(&self, value: f64) -> f64 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+/// Tune the SPSA parameters by self-play. Every iteration perturbs each parameter by plus or minus
+/// its perturbation size, with the signs chosen at random, and plays a short match between the two
+/// resulting parameter sets. Each parameter then moves towards whichever set won, in proportion to
+/// the score difference. The parameters are checkpointed after every iteration, and the final
+/// values are applied to this engine.
+pub fn run_spsa(engine: &mut Engine, config: &SpsaConfig) -> Result<(), String> {
+    let command = std::env::current_exe()
+        .map_err(|e| format!("could not find the engine binary: {}", e))?
+        .display()
+        .to_string();
+    let mut params: Vec<SpsaParam> = params()
+        .into_iter()
+        .chain(array_params())
+        .filter(|param| param.spsa)
+        .map(SpsaParam::new)
+        .collect();
+    let first_iteration = if Path::new(&config.checkpoint).exists() {
+        let iteration = load_checkpoint(&config.checkpoint, &mut params)?;
+        println!(
+            "info string spsa resuming from iteration {} of {}",
+            iteration, config.checkpoint
+        );
+        iteration
+    } else {
+        0
+    };
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    // Skip the signs of the iterations already played, so that a resumed run matches an
+    // uninterrupted one.
+    for _ in 0..first_iteration * params.len() as u64 {
+        rng.random::<bool>();
+    }
+    let start = Instant::now();
+
+    for k in first_iteration..config.iterations {
+        let mut plus = Vec::with_capacity(params.len());
+        let mut minus = Vec::with_capacity(params.len());
+        let mut deltas = Vec::with_capacity(params.len());
+        for param in &params {
+            let delta = if rng.random::<bool>() { 1.0 } else { -1.0 };
+            let (c_k, _) = param.schedule(k, config.iterations);
+            let value = |sign: f64| {
+                let value = param.clamp(param.value + sign * c_k * delta);
+                (param.name.clone(), (value.round() as i32).to_string())
+            };
+            plus.push(value(1.0));
+            minus.push(value(-1.0));
+            deltas.push(delta);
+        }
+
+        let engine_config = |params: Vec<(String, String)>| EngineConfig {
+            command: command.clone(),
+            options: config.options.iter().cloned().chain(params).collect(),
+        };
+        let match_config = MatchConfig {
+            engines: [engine_config(plus), engine_config(minus)],
+            openings: config.openings.clone(),
+            first_opening: (k * config.pairs) as usize,
+            games: 2 * config.pairs,
+            time_control: config.time_control,
+            concurrency: config.concurrency,
+            pgn: None,
+            sprt: None,
+            seed: config.seed.wrapping_add(k),
+            adjudication: Adjudication::default(),
+            quiet: true,
+        };
+        let result = run_match(engine, &match_config)?;
+        let score = result.wins as f64 - result.losses as f64;
+
+        for (param, delta) in params.iter_mut().zip(deltas) {
+            let (c_k, r_k) = param.schedule(k, config.iterations);
+            param.value = param.clamp(param.value + r_k * c_k * score * delta);
+        }
+        save_checkpoint(&config.checkpoint, &params, k + 1)?;
+        println!(
+            "info string spsa iteration {}/{} wdl {}-{}-{} time {:.1}s",
+            k + 1,
+            config.iterations,
+            result.wins,
+            result.draws,
+            result.losses,
+            start.elapsed().as_secs_f64()
+        );
+    }
+
+    for param in &params {
+        let value = param.value.round() as i32;
+        if !set_array_param(&param.name, value) {
+            set_param(&param.name, value);
+        }
+    }
+    println!(
+        "info string spsa finished, parameters saved to {}",
+        config.checkpoint
+    );
+    Ok(())
+}
+
+/// Write the parameters as `name=value` lines, after a comment with the number of iterations
+/// played.
+fn save_checkpoint(path: &str, params: &[SpsaParam], iteration: u64) -> Result<(), String> {
+    let mut contents = format!("# spsa iteration {}\n", iteration);
+    for param in params {
+        contents += &format!("{}={:.4}\n", param.name, param.value);
+    }
+    fs::write(path, contents).map_err(|e| format!("could not write {}: {}", path, e))
+}
+
+/// Read the parameters of a checkpoint, returning the number of iterations played. Parameters
+/// missing from the file keep their current values.
+fn load_checkpoint(path: &str, params: &mut [SpsaParam]) -> Result<u64, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let mut iteration = 0;
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(n) = comment.trim().strip_prefix("spsa iteration ") {
+                iteration = n
+                    .parse()
+                    .map_err(|_| format!("{} line {}: invalid iteration", path, i + 1))?;
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || format!("{} line {}: invalid parameter '{}'", path, i + 1, line);
+        let (name, value) = line.split_once('=').ok_or_else(invalid)?;
+        let value: f64 = value.trim().parse().map_err(|_| invalid())?;
+        let param = params
+            .iter_mut()
+            .find(|param| param.name == name.trim())
+            .ok_or_else(|| format!("{} line {}: unknown parameter '{}'", path, i + 1, name))?;
+        param.value = param.clamp(value);
+    }
+    Ok(iteration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, value: i32, min: i32, max: i32) -> SpsaParam {
+        SpsaParam::new(TunableParam {
+            name: name.to_string(),
+            value,
+            default: value,
+            min,
+            max,
+            step: (max - min) / 20,
+            spsa: true,
+        })
+    }
+
+    #[test]
+    fn test_schedule_ends_at_c_end_and_r_end() {
+        let param = param("nmp_margin", 37, 0, 80);
+        for n in [1, 10, 250, 10000] {
+            let (c, r) = param.schedule(n - 1, n);
+            assert!((c - param.c_end).abs() < 1e-9, "c {} at n {}", c, n);
+            assert!((r - param.r_end).abs() < 1e-12, "r {} at n {}", r, n);
+            assert!(param.schedule(0, n).0 >= c);
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = std::env::temp_dir().join(format!("hobbes_spsa_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let tuned = || {
+            [
+                param("asp_delta", 10, 4, 36),
+                param("rfp_base", 14, -50, 50),
+            ]
+        };
+        let mut params = tuned();
+        params[0].value = 12.34567;
+        params[1].value = -7.5;
+        save_checkpoint(path, &params, 42).unwrap();
+
+        let mut loaded = tuned();
+        assert_eq!(load_checkpoint(path, &mut loaded), Ok(42));
+        assert!((loaded[0].value - 12.3457).abs() < 1e-9);
+        assert_eq!(loaded[1].value, -7.5);
+
+        // Out-of-range values are clamped, and unknown parameters are rejected.
+        fs::write(path, "asp_delta=99\n").unwrap();
+        assert_eq!(load_checkpoint(path, &mut loaded), Ok(0));
+        assert_eq!(loaded[0].value, 36.0);
+        fs::write(path, "# spsa iteration 3\nlmr_base=5\n").unwrap();
+        assert!(load_checkpoint(path, &mut loaded).is_err_and(|e| e.contains("unknown")));
+        fs::remove_file(path).unwrap();
+    }
+}
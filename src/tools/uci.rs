@@ -1,26 +1,56 @@
 use crate::board::movegen::MoveFilter;
-use crate::board::moves::{Move, MoveList};
+use crate::board::moves::{Move, MoveList, MAX_MOVES};
 use crate::board::observer::NullBoardObserver;
 use crate::board::side::Side::{Black, White};
-use crate::board::Board;
+use crate::board::{castling, Board};
+use crate::evaluation;
 use crate::evaluation::stats;
+use crate::evaluation::stats::StatsOptions;
+use crate::evaluation::texel::{self, TexelOptions};
 use crate::search::engine::{Engine, MAX_THREADS};
 #[cfg(feature = "tuning")]
 use crate::search::parameters::{list_params, print_params_ob, set_param, list_array_params, print_array_params_ob, set_array_param};
-use crate::search::time::SearchLimits;
-use crate::search::{score, tt};
-use crate::tools::bench::bench;
-use crate::tools::datagen::generate_random_openings;
-use crate::tools::perft::perft;
+use crate::search::skill::{Skill, MAX_SKILL_LEVEL};
+use crate::search::time::{
+    FischerTime, SearchLimits, DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS,
+};
+use crate::search::{score, tt, wdl, MAX_PLY};
+use crate::tools::bench::{bench, BenchConfig};
+use crate::tools::dataformat::{convert, DataFormat};
+use crate::tools::datagen::{generate_random_openings, run_datagen, DatagenConfig};
+use crate::tools::engine_match::{run_match, Adjudication, EngineConfig, MatchConfig, TimeControl};
+use crate::tools::epd::{run_epd_test, EpdConfig};
+use crate::tools::json::{build_info, Json};
+use crate::tools::param_file::{format_params, load_params, reset_params, save_params};
+use crate::tools::perft::{divide, parse_perft_epd, PerftTable};
+use crate::tools::rescore::{run_rescore, Filters, RescoreConfig};
+use crate::tools::sprt::Sprt;
+#[cfg(feature = "tuning")]
+use crate::tools::spsa::{run_spsa, SpsaConfig};
+use crate::tools::wdl_fit::{run_wdl_fit, WdlFitOptions};
 use crate::tools::{fen, pretty};
 use crate::VERSION;
 use std::io;
 use std::path::Path;
 use std::time::Instant;
 use crate::search::thread::ThreadData;
-use crate::search::tt::TTFlag;
 use crate::search::tt::TTFlag::{Exact, Lower, Upper};
 
+/// The EvalFile value which selects the network embedded in the binary.
+const EMBEDDED_EVAL_FILE: &str = "<embedded>";
+
+/// The ParamFile value which restores the declared defaults of the search parameters.
+const DEFAULT_PARAM_FILE: &str = "<default>";
+
+/// The flag which switches the bench, perft and eval_stats tools to JSON output.
+const JSON_FLAG: &str = "--json";
+
+/// The parameters which may follow 'go', used to find the end of the 'searchmoves' list.
+const GO_PARAMS: [&str; 13] = [
+    "searchmoves", "ponder", "wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes",
+    "softnodes", "mate", "movetime", "infinite",
+];
+
 pub struct UCI {
     pub board: Board,
     pub engine: Engine,
@@ -43,9 +73,32 @@ impl UCI {
     }
 
     pub fn run(&mut self, args: &[String]) {
+        // The network and the search parameters may be loaded from files with '--evalfile <path>'
+        // and '--params <path>', before any other command.
+        let mut args = args.to_vec();
+        for flag in ["--evalfile", "--params"] {
+            if let Some(index) = args.iter().position(|arg| arg == flag) {
+                match args.get(index + 1).cloned() {
+                    Some(path) => {
+                        match flag {
+                            "--evalfile" => self.set_eval_file(&path),
+                            _ => self.set_param_file(&path),
+                        }
+                        args.drain(index..=index + 1);
+                    }
+                    None => {
+                        println!("info error: {} is missing a value", flag);
+                        args.remove(index);
+                    }
+                }
+            }
+        }
+
         if args.len() > 1 && args[1] == "bench" {
-            println!("Running benchmark...");
-            self.handle_bench();
+            if !args.iter().any(|arg| arg == JSON_FLAG) {
+                println!("Running benchmark...");
+            }
+            self.handle_bench(args[1..].to_vec());
             return;
         }
         if args.len() > 1 && args[1].contains("genfens") {
@@ -53,6 +106,48 @@ impl UCI {
             self.handle_genfens(tokens);
             return;
         }
+        if args.len() > 1 && (args[1] == "perft" || args[1] == "eval_stats") {
+            let tokens = args[1..].to_vec();
+            match args[1].as_str() {
+                "perft" => self.handle_perft(tokens),
+                _ => self.handle_eval_stats(tokens),
+            }
+            return;
+        }
+        if args.len() > 1 && args[1] == "texel" {
+            self.handle_texel(args[1..].to_vec());
+            return;
+        }
+        if args.len() > 1 && args[1] == "wdl_fit" {
+            self.handle_wdl_fit(args[1..].to_vec());
+            return;
+        }
+        if args.len() > 1 && args[1] == "match" {
+            self.handle_match(args[1..].to_vec());
+            return;
+        }
+        #[cfg(feature = "tuning")]
+        if args.len() > 1 && args[1] == "spsa" {
+            self.handle_spsa(args[1..].to_vec());
+            return;
+        }
+        if args.len() > 1 && args[1] == "epdtest" {
+            self.handle_epdtest(args[1..].to_vec());
+            return;
+        }
+        if args.len() > 1 && args[1] == "convert" {
+            self.handle_convert(args[1..].to_vec());
+            return;
+        }
+        if args.len() > 1 && args[1] == "rescore" {
+            self.handle_rescore(args[1..].to_vec());
+            return;
+        }
+        if args.len() > 1 && args[1].starts_with("datagen") {
+            let tokens = self.split_args(args[1..].join(" "));
+            self.handle_datagen(tokens);
+            return;
+        }
 
         pretty::print_uci_info();
 
@@ -66,10 +161,11 @@ impl UCI {
             self.engine.try_reclaim();
 
             if let Some(command) = line.split_ascii_whitespace().next() {
-                // If a search is running, only 'stop', 'quit', and 'isready' are handled.
-                // All other commands are ignored until the search finishes.
+                // If a search is running, only 'stop', 'ponderhit', 'quit', and 'isready' are
+                // handled. All other commands are ignored until the search finishes.
                 match command {
                     "stop" => self.handle_stop(),
+                    "ponderhit" => self.handle_ponderhit(),
                     "quit" => self.handle_quit(),
                     "isready" => self.handle_isready(),
                     _ => {
@@ -78,17 +174,27 @@ impl UCI {
                             "uci" => self.handle_uci(),
                             "setoption" => self.handle_setoption(tokens),
                             "ucinewgame" => self.handle_ucinewgame(),
-                            "bench" => self.handle_bench(),
+                            "bench" => self.handle_bench(tokens),
                             "position" => self.handle_position(tokens),
                             "go" => self.handle_go(tokens),
                             "fen" => self.handle_fen(),
                             "eval" => self.handle_eval(),
                             "eval_stats" => self.handle_eval_stats(tokens),
+                            "texel" => self.handle_texel(tokens),
+                            "wdl_fit" => self.handle_wdl_fit(tokens),
+                            "dumpparams" => self.handle_dumpparams(tokens),
                             "perft" => self.handle_perft(tokens),
+                            "epdtest" => self.handle_epdtest(tokens),
+                            "match" => self.handle_match(tokens),
                             "genfens" => self.handle_genfens(tokens),
+                            "datagen" => self.handle_datagen(tokens),
+                            "convert" => self.handle_convert(tokens),
+                            "rescore" => self.handle_rescore(tokens),
                             "help" => self.handle_help(),
                             #[cfg(feature = "tuning")]
                             "params" => { print_params_ob(); print_array_params_ob(); }
+                            #[cfg(feature = "tuning")]
+                            "spsa" => self.handle_spsa(tokens),
                             _ => println!("info error: unknown command"),
                         }
                     },
@@ -116,6 +222,19 @@ impl UCI {
         );
         println!("option name Minimal type check default false");
         println!("option name UseSoftNodes type check default false");
+        println!("option name MultiPV type spin default 1 min 1 max {}", MAX_MOVES);
+        println!("option name Ponder type check default false");
+        println!("option name UCI_ShowWDL type check default false");
+        println!(
+            "option name Skill Level type spin default {} min 0 max {}",
+            MAX_SKILL_LEVEL, MAX_SKILL_LEVEL
+        );
+        println!("option name EvalFile type string default {}", EMBEDDED_EVAL_FILE);
+        println!("option name ParamFile type string default {}", DEFAULT_PARAM_FILE);
+        println!(
+            "option name Move Overhead type spin default {} min 0 max {}",
+            DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS
+        );
         #[cfg(feature = "tuning")]
         {
             list_params();
@@ -129,6 +248,18 @@ impl UCI {
     }
 
     fn handle_setoption(&mut self, tokens: Vec<String>) {
+        // File paths are case-sensitive, so the EvalFile value is taken from the original tokens.
+        if tokens.len() > 4 && tokens[2].eq_ignore_ascii_case("evalfile") && tokens[3] == "value"
+        {
+            self.set_eval_file(&tokens[4..].join(" "));
+            return;
+        }
+        if tokens.len() > 4 && tokens[2].eq_ignore_ascii_case("paramfile") && tokens[3] == "value"
+        {
+            self.set_param_file(&tokens[4..].join(" "));
+            return;
+        }
+
         let tokens: Vec<String> = tokens.iter().map(|s| s.to_lowercase()).collect();
         let tokens: Vec<&str> = tokens.iter().map(|s| s.as_str()).collect();
 
@@ -142,6 +273,13 @@ impl UCI {
             ["setoption", "name", "usesoftnodes", "value", bool_str] => {
                 self.set_use_soft_nodes(bool_str)
             }
+            ["setoption", "name", "multipv", "value", n_str] => self.set_multi_pv(n_str),
+            ["setoption", "name", "ponder", "value", bool_str] => self.set_ponder(bool_str),
+            ["setoption", "name", "uci_showwdl", "value", bool_str] => self.set_show_wdl(bool_str),
+            ["setoption", "name", "skill", "level", "value", n_str] => self.set_skill_level(n_str),
+            ["setoption", "name", "move", "overhead", "value", n_str] => {
+                self.set_move_overhead(n_str)
+            }
             #[cfg(feature = "tuning")]
             ["setoption", "name", name, "value", value_str] => self.set_tunable(name, *value_str),
             _ => println!("info error unknown option"),
@@ -210,6 +348,108 @@ impl UCI {
         println!("info string UseSoftNodes {}", value);
     }
 
+    fn set_ponder(&mut self, bool_str: &str) {
+        let value = match bool_str {
+            "true" => true,
+            "false" => false,
+            _ => {
+                println!("info error: invalid value '{}'", bool_str);
+                return;
+            }
+        };
+        self.engine.set_ponder(value);
+        println!("info string Ponder {}", value);
+    }
+
+    fn set_show_wdl(&mut self, bool_str: &str) {
+        let value = match bool_str {
+            "true" => true,
+            "false" => false,
+            _ => {
+                println!("info error: invalid value '{}'", bool_str);
+                return;
+            }
+        };
+        self.engine.set_show_wdl(value);
+        println!("info string UCI_ShowWDL {}", value);
+    }
+
+    /// Load the network from an untransposed (Bullet output) network file, falling back to the
+    /// embedded network if the file can't be loaded.
+    fn set_eval_file(&mut self, path: &str) {
+        // The hash table and the accumulator caches hold evaluations by the previous network.
+        self.engine.new_game();
+        if path == EMBEDDED_EVAL_FILE {
+            evaluation::use_embedded_network();
+            println!("info string EvalFile {}", EMBEDDED_EVAL_FILE);
+            return;
+        }
+        match evaluation::load_network(path) {
+            Ok(()) => println!("info string EvalFile {}", path),
+            Err(e) => {
+                println!("info error: {}", e);
+                evaluation::use_embedded_network();
+                println!("info string EvalFile {}", EMBEDDED_EVAL_FILE);
+            }
+        }
+    }
+
+    /// Load the search parameters from a parameter file, or restore the defaults. An invalid file
+    /// leaves the parameters unchanged.
+    fn set_param_file(&mut self, path: &str) {
+        if path == DEFAULT_PARAM_FILE {
+            reset_params();
+            println!("info string ParamFile {}", DEFAULT_PARAM_FILE);
+            return;
+        }
+        match load_params(path) {
+            Ok(count) => println!("info string ParamFile {} ({} parameters)", path, count),
+            Err(e) => println!("info error: {}", e),
+        }
+    }
+
+    /// Handle 'dumpparams [file]', printing every search parameter as a parameter file, or saving
+    /// it to the file.
+    fn handle_dumpparams(&self, tokens: Vec<String>) {
+        match tokens.get(1) {
+            Some(path) => match save_params(path) {
+                Ok(()) => println!("info string parameters saved to {}", path),
+                Err(e) => println!("info error: {}", e),
+            },
+            None => print!("{}", format_params()),
+        }
+    }
+
+    fn set_multi_pv(&mut self, value_str: &str) {
+        match value_str.parse::<usize>() {
+            Ok(n) if (1..=MAX_MOVES).contains(&n) => {
+                self.engine.set_multi_pv(n);
+                println!("info string MultiPV {}", n);
+            }
+            _ => println!("info error: invalid value '{}'", value_str),
+        }
+    }
+
+    fn set_skill_level(&mut self, value_str: &str) {
+        match value_str.parse::<i32>() {
+            Ok(n) if (0..=MAX_SKILL_LEVEL).contains(&n) => {
+                self.engine.set_skill(Some(Skill::from_level(n)));
+                println!("info string Skill Level {}", n);
+            }
+            _ => println!("info error: invalid value '{}'", value_str),
+        }
+    }
+
+    fn set_move_overhead(&mut self, value_str: &str) {
+        match value_str.parse::<u64>() {
+            Ok(n) if n <= MAX_MOVE_OVERHEAD_MS => {
+                self.engine.set_move_overhead(n);
+                println!("info string Move Overhead {}", n);
+            }
+            _ => println!("info error: invalid value '{}'", value_str),
+        }
+    }
+
     #[cfg(feature = "tuning")]
     fn set_tunable(&self, name: &str, value_str: &str) {
         let value: i32 = match value_str.parse() {
@@ -219,8 +459,8 @@ impl UCI {
                 return;
             }
         };
-        if !set_array_param(name, value) {
-            set_param(name, value);
+        if !set_array_param(name, value) && !set_param(name, value) {
+            println!("info error unknown option");
         }
     }
 
@@ -228,8 +468,234 @@ impl UCI {
         self.engine.new_game();
     }
 
-    fn handle_bench(&mut self) {
-        bench(self.engine.td_mut());
+    /// Handle 'bench [depth] [hash] [threads] [fenfile]', where each argument is optional but
+    /// must be given in order.
+    fn handle_bench(&mut self, mut tokens: Vec<String>) {
+        let mut config = BenchConfig {
+            json: self.take_flag(&mut tokens, JSON_FLAG),
+            ..BenchConfig::default()
+        };
+        let parse = |index: usize, name: &str| -> Result<Option<u64>, String> {
+            match tokens.get(index) {
+                Some(value) => match value.parse::<u64>() {
+                    Ok(n) if n > 0 => Ok(Some(n)),
+                    _ => Err(format!("info error: {} '{}' is not a valid number", name, value)),
+                },
+                None => Ok(None),
+            }
+        };
+        let parsed = (|| -> Result<(), String> {
+            if let Some(depth) = parse(1, "depth")? {
+                config.depth = depth.min(MAX_PLY as u64);
+            }
+            config.hash_mb = parse(2, "hash")?.map(|mb| mb as usize);
+            if let Some(threads) = parse(3, "threads")? {
+                config.threads = (threads as usize).min(MAX_THREADS);
+            }
+            config.fen_file = tokens.get(4).cloned();
+            Ok(())
+        })();
+        if let Err(e) = parsed {
+            println!("{}", e);
+            return;
+        }
+        if let Err(e) = bench(&mut self.engine, &config) {
+            println!("info error: {}", e);
+        }
+    }
+
+    fn handle_epdtest(&mut self, tokens: Vec<String>) {
+        let Some(path) = tokens.get(1).cloned() else {
+            println!("info error: usage is 'epdtest <file> [movetime <ms>] [nodes <n>]'");
+            return;
+        };
+        let limit = |name: &str| -> Result<Option<u64>, String> {
+            if tokens.iter().any(|token| token == name) {
+                self.parse_uint(&tokens, name).map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let config = match (limit("movetime"), limit("nodes")) {
+            (Ok(movetime), Ok(nodes)) => EpdConfig {
+                path,
+                movetime,
+                nodes,
+                frc: self.frc,
+            },
+            (Err(e), _) | (_, Err(e)) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if let Err(e) = run_epd_test(&mut self.engine, &config) {
+            println!("info error: {}", e);
+        }
+    }
+
+    fn handle_match(&mut self, tokens: Vec<String>) {
+        let (Some(first), Some(second)) = (tokens.get(1), tokens.get(2)) else {
+            println!("info error: usage is 'match <engine> <engine> [games <n>] [tc <base+inc>]'");
+            return;
+        };
+        // 'self' stands for this binary, so that it can be matched against itself with different
+        // options.
+        let command = |engine: &String| match engine.as_str() {
+            "self" => std::env::current_exe()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|_| engine.clone()),
+            _ => engine.clone(),
+        };
+        let options = |flag: &str| -> Result<Vec<(String, String)>, String> {
+            tokens
+                .windows(2)
+                .filter(|pair| pair[0] == flag)
+                .map(|pair| match pair[1].split_once('=') {
+                    Some((name, value)) => Ok((name.to_string(), value.to_string())),
+                    None => Err(format!("info error: {} '{}' is not name=value", flag, pair[1])),
+                })
+                .collect()
+        };
+        let float = |name: &str, default: f64| -> Result<f64, String> {
+            match tokens.iter().position(|token| token == name) {
+                Some(index) => tokens
+                    .get(index + 1)
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .ok_or_else(|| format!("info error: {} is not a valid number", name)),
+                None => Ok(default),
+            }
+        };
+        let uint = |name: &str, default: u64| -> Result<u64, String> {
+            if tokens.iter().any(|token| token == name) {
+                self.parse_uint(&tokens, name)
+            } else {
+                Ok(default)
+            }
+        };
+        let value = |name: &str| {
+            let index = tokens.iter().position(|token| token == name)?;
+            tokens.get(index + 1).cloned()
+        };
+
+        let config = (|| -> Result<MatchConfig, String> {
+            let time_control = self.parse_time_control(&tokens)?;
+            let sprt = match tokens.iter().position(|token| token == "sprt") {
+                Some(index) => {
+                    let bound = |offset: usize| {
+                        tokens.get(index + offset).and_then(|value| value.parse::<f64>().ok())
+                    };
+                    let (Some(elo0), Some(elo1)) = (bound(1), bound(2)) else {
+                        return Err("info error: sprt needs elo0 and elo1".to_string());
+                    };
+                    Some(Sprt {
+                        elo0,
+                        elo1,
+                        alpha: float("alpha", 0.05)?,
+                        beta: float("beta", 0.05)?,
+                    })
+                }
+                None => None,
+            };
+            let adjudication = Adjudication {
+                enabled: !tokens.iter().any(|token| token == "noadjudication"),
+                ..Adjudication::default()
+            };
+            Ok(MatchConfig {
+                engines: [
+                    EngineConfig {
+                        command: command(first),
+                        options: options("option1")?,
+                    },
+                    EngineConfig {
+                        command: command(second),
+                        options: options("option2")?,
+                    },
+                ],
+                openings: value("openings"),
+                first_opening: 0,
+                games: uint("games", 100)?.max(2),
+                time_control,
+                concurrency: uint("concurrency", 1)? as usize,
+                pgn: value("pgn"),
+                sprt,
+                seed: uint("seed", 0)?,
+                adjudication,
+                quiet: false,
+            })
+        })();
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if let Err(e) = run_match(&mut self.engine, &config) {
+            println!("info error: {}", e);
+        }
+    }
+
+    /// Parse the time control of a match: 'nodes <n>', 'movetime <ms>', or 'tc <base+inc>' in
+    /// seconds, defaulting to 8+0.08.
+    fn parse_time_control(&self, tokens: &[String]) -> Result<TimeControl, String> {
+        if tokens.iter().any(|token| token == "nodes") {
+            Ok(TimeControl::Nodes(self.parse_uint(tokens, "nodes")?))
+        } else if tokens.iter().any(|token| token == "movetime") {
+            Ok(TimeControl::MoveTime(self.parse_uint(tokens, "movetime")?))
+        } else {
+            let tc = match tokens.iter().position(|token| token == "tc") {
+                Some(index) => tokens.get(index + 1).cloned().unwrap_or_default(),
+                None => "8+0.08".to_string(),
+            };
+            TimeControl::parse_fischer(&tc)
+                .ok_or_else(|| format!("info error: tc '{}' is not a valid time control", tc))
+        }
+    }
+
+    #[cfg(feature = "tuning")]
+    fn handle_spsa(&mut self, tokens: Vec<String>) {
+        let uint = |name: &str, default: u64| -> Result<u64, String> {
+            if tokens.iter().any(|token| token == name) {
+                self.parse_uint(&tokens, name)
+            } else {
+                Ok(default)
+            }
+        };
+        let value = |name: &str| {
+            let index = tokens.iter().position(|token| token == name)?;
+            tokens.get(index + 1).cloned()
+        };
+
+        let config = (|| -> Result<SpsaConfig, String> {
+            let options = tokens
+                .windows(2)
+                .filter(|pair| pair[0] == "option")
+                .map(|pair| match pair[1].split_once('=') {
+                    Some((name, value)) => Ok((name.to_string(), value.to_string())),
+                    None => Err(format!("info error: option '{}' is not name=value", pair[1])),
+                })
+                .collect::<Result<Vec<(String, String)>, String>>()?;
+            Ok(SpsaConfig {
+                iterations: uint("iterations", 1000)?,
+                pairs: uint("pairs", 4)?.max(1),
+                time_control: self.parse_time_control(&tokens)?,
+                concurrency: uint("concurrency", 1)? as usize,
+                openings: value("openings"),
+                options,
+                checkpoint: value("checkpoint").unwrap_or("spsa.txt".to_string()),
+                seed: uint("seed", 0)?,
+            })
+        })();
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if let Err(e) = run_spsa(&mut self.engine, &config) {
+            println!("info error: {}", e);
+        }
     }
 
     fn handle_position(&mut self, tokens: Vec<String>) {
@@ -343,12 +809,24 @@ impl UCI {
                 0
             });
 
+            let moves_to_go = if tokens.contains(&String::from("movestogo")) {
+                match self.parse_uint(&tokens, "movestogo") {
+                    Ok(movestogo) => Some(movestogo),
+                    Err(_) => {
+                        println!("info error: movestogo is not a valid number");
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
             let (time, inc) = match self.board.stm {
                 White => (wtime, winc),
                 Black => (btime, binc),
             };
 
-            Some((time, inc))
+            Some(FischerTime { time, inc, moves_to_go })
         } else {
             None
         };
@@ -392,17 +870,77 @@ impl UCI {
             }
         }
 
-        let limits = SearchLimits::new(
-            fischer,
-            movetime,
-            softnodes,
-            nodes,
-            depth,
-            self.board.fm as usize,
-        );
+        let search_moves = match self.parse_search_moves(&tokens) {
+            Ok(moves) => moves,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+
+        let mate = if tokens.contains(&String::from("mate")) {
+            match self.parse_uint(&tokens, "mate") {
+                Ok(moves) if moves > 0 => Some(moves),
+                _ => {
+                    println!("info error: mate is not a valid number");
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        // An infinite search ignores all other limits, and runs until it is stopped.
+        let infinite = tokens.contains(&String::from("infinite"));
+
+        let mut limits = if infinite {
+            SearchLimits::new(None, None, None, None, None, 0, 0)
+        } else {
+            SearchLimits::new(
+                fischer,
+                movetime,
+                softnodes,
+                nodes,
+                depth,
+                self.board.fm as usize,
+                self.engine.move_overhead(),
+            )
+        };
+        limits.search_moves = search_moves;
+        limits.ponder = tokens.contains(&String::from("ponder"));
+        limits.infinite = infinite;
+        limits.mate = mate;
         self.engine.go(self.board, limits);
     }
 
+    /// Parse the list of moves following 'searchmoves' in a 'go' command, up until the next 'go'
+    /// parameter. Each move is validated against the legal moves in the current position.
+    fn parse_search_moves(&self, tokens: &[String]) -> Result<Vec<Move>, String> {
+        let Some(index) = tokens.iter().position(|x| x == "searchmoves") else {
+            return Ok(Vec::new());
+        };
+
+        let mut legal_moves = MoveList::new();
+        self.board.gen_moves(MoveFilter::All, &mut legal_moves);
+
+        let mut search_moves = Vec::new();
+        for token in tokens
+            .iter()
+            .skip(index + 1)
+            .take_while(|token| !GO_PARAMS.contains(&token.as_str()))
+        {
+            match legal_moves.iter().map(|e| e.mv).find(|mv| mv.to_uci() == *token) {
+                Some(mv) => search_moves.push(mv),
+                None => return Err(format!("info error: illegal searchmove {}", token)),
+            }
+        }
+
+        if search_moves.is_empty() {
+            return Err(String::from("info error: searchmoves is missing a value"));
+        }
+        Ok(search_moves)
+    }
+
     fn sync(&mut self) {
         if self.engine.searching() {
             self.engine.stop();
@@ -414,24 +952,139 @@ impl UCI {
         self.engine.stop();
     }
 
+    fn handle_ponderhit(&mut self) {
+        self.engine.ponderhit();
+    }
+
     fn handle_eval(&mut self) {
         let eval = self.engine.eval(self.board);
         println!("{}", eval);
     }
 
-    fn handle_eval_stats(&mut self, tokens: Vec<String>) {
+    fn handle_eval_stats(&mut self, mut tokens: Vec<String>) {
+        let json = self.take_flag(&mut tokens, JSON_FLAG);
         if tokens.len() < 2 {
             println!("info error: missing input file argument");
             return;
         }
-        stats::eval_stats(self.engine.td_mut(), Path::new(&tokens[1]));
+        let wdl_lambda = match tokens.iter().position(|x| x == "wdl") {
+            Some(i) => match tokens.get(i + 1).and_then(|v| v.parse::<f64>().ok()) {
+                Some(lambda) if (0.0..=1.0).contains(&lambda) => lambda,
+                _ => {
+                    println!("info error: wdl must be a number between 0 and 1");
+                    return;
+                }
+            },
+            None => 0.5,
+        };
+        let options = StatsOptions {
+            compare: tokens
+                .iter()
+                .position(|x| x == "compare")
+                .and_then(|i| tokens.get(i + 1))
+                .cloned(),
+            wdl_lambda,
+            json,
+        };
+        stats::eval_stats(self.engine.td_mut(), Path::new(&tokens[1]), &options);
+    }
+
+    /// Handle 'texel <file> [epochs <n>] [lr <rate>] [wdl <lambda>]', fitting the eval scaling
+    /// parameters to the labelled positions in the file.
+    fn handle_texel(&mut self, tokens: Vec<String>) {
+        let Some(input) = tokens.get(1) else {
+            println!("info error: missing input file argument");
+            return;
+        };
+        let float = |name: &str, default: f64| -> Result<f64, String> {
+            match tokens.iter().position(|token| token == name) {
+                Some(index) => tokens
+                    .get(index + 1)
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .ok_or_else(|| format!("info error: {} is not a valid number", name)),
+                None => Ok(default),
+            }
+        };
+        let defaults = TexelOptions::default();
+        let options = (|| -> Result<TexelOptions, String> {
+            let wdl_lambda = float("wdl", defaults.wdl_lambda)?;
+            if !(0.0..=1.0).contains(&wdl_lambda) {
+                return Err("info error: wdl must be a number between 0 and 1".to_string());
+            }
+            Ok(TexelOptions {
+                epochs: if tokens.iter().any(|token| token == "epochs") {
+                    self.parse_uint(&tokens, "epochs")? as usize
+                } else {
+                    defaults.epochs
+                },
+                learning_rate: float("lr", defaults.learning_rate)?,
+                wdl_lambda,
+            })
+        })();
+        match options {
+            Ok(options) => texel::run_texel(self.engine.td_mut(), Path::new(input), &options),
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// Handle 'wdl_fit <file> [epochs <n>] [lr <rate>]', fitting the WDL model to the scores and
+    /// results of the labelled positions in the file.
+    fn handle_wdl_fit(&self, tokens: Vec<String>) {
+        let Some(input) = tokens.get(1) else {
+            println!("info error: missing input file argument");
+            return;
+        };
+        let defaults = WdlFitOptions::default();
+        let options = (|| -> Result<WdlFitOptions, String> {
+            Ok(WdlFitOptions {
+                epochs: if tokens.iter().any(|token| token == "epochs") {
+                    self.parse_uint(&tokens, "epochs")? as usize
+                } else {
+                    defaults.epochs
+                },
+                learning_rate: match tokens.iter().position(|token| token == "lr") {
+                    Some(index) => tokens
+                        .get(index + 1)
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .ok_or_else(|| "info error: lr is not a valid number".to_string())?,
+                    None => defaults.learning_rate,
+                },
+            })
+        })();
+        match options {
+            Ok(options) => run_wdl_fit(Path::new(input), &options),
+            Err(e) => println!("{}", e),
+        }
     }
 
     fn handle_fen(&self) {
         println!("{}", self.board.to_fen());
     }
 
-    fn handle_perft(&self, tokens: Vec<String>) {
+    /// Handle 'perft <depth> [bulk] [threads <n>] [hash <mb>]' on the current position, or
+    /// 'perft epd <file> <depth> [threads <n>] [hash <mb>]' to check every position in an EPD file
+    /// against its expected node counts up to the given depth.
+    fn handle_perft(&self, mut tokens: Vec<String>) {
+        let json = self.take_flag(&mut tokens, JSON_FLAG);
+        let threads = self.parse_uint(&tokens, "threads").unwrap_or(1) as usize;
+        let threads = threads.clamp(1, MAX_THREADS);
+        let table = match self.parse_uint(&tokens, "hash") {
+            Ok(mb) if mb > 0 => Some(PerftTable::new(mb as usize)),
+            _ => None,
+        };
+
+        if tokens.get(1).is_some_and(|t| t == "epd") {
+            let (Some(path), Some(depth)) = (tokens.get(2), tokens.get(3)) else {
+                println!("info error: usage is 'perft epd <file> <depth>'");
+                return;
+            };
+            match depth.parse::<u8>() {
+                Ok(depth) => self.perft_epd(path, depth, threads, table.as_ref()),
+                Err(_) => println!("info error: depth argument is not a valid number"),
+            }
+            return;
+        }
+
         if tokens.len() < 2 {
             println!("info error: missing depth argument");
             return;
@@ -447,7 +1100,7 @@ impl UCI {
 
         let bulk = match tokens.get(2).map(|s| s.as_str()) {
             Some("false") => false,
-            Some("true") | None => true,
+            Some("true") | Some("threads") | Some("hash") | None => true,
             Some(other) => {
                 println!(
                     "info error: bulk argument '{}' is not a valid boolean",
@@ -458,17 +1111,117 @@ impl UCI {
         };
 
         let t = Instant::now();
-        let n = if bulk {
-            perft::<true>(&self.board, depth)
+        let entries = if bulk {
+            divide::<true>(&self.board, depth, threads, table.as_ref())
         } else {
-            perft::<false>(&self.board, depth)
+            divide::<false>(&self.board, depth, threads, table.as_ref())
         };
         let d = t.elapsed();
+        let n = entries.iter().map(|(_, count)| count).sum::<u64>();
+
+        if json {
+            let divide = entries
+                .into_iter()
+                .map(|(mv, count)| Json::object([("move", mv.into()), ("nodes", count.into())]))
+                .collect::<Vec<Json>>();
+            let doc = Json::object([
+                ("build", build_info()),
+                ("fen", self.board.to_fen().into()),
+                ("depth", (depth as u64).into()),
+                ("bulk", bulk.into()),
+                ("threads", threads.into()),
+                ("nodes", n.into()),
+                ("time_ms", (d.as_millis() as u64).into()),
+                ("nps", ((n as f64 / d.as_secs_f64().max(1e-9)) as u64).into()),
+                ("divide", divide.into()),
+            ]);
+            println!("{}", doc);
+            return;
+        }
+
+        for (mv, count) in &entries {
+            println!("{} - {}", mv, count);
+        }
         let mnps = (n as f64) / d.as_secs_f64() / 1e6;
         println!("info nodes: {n}");
         println!("info {d:.2?} ({mnps:.2}Mnps)\n");
     }
 
+    /// Run perft on every position in an EPD file, checking each of its ';Dn' entries up to the
+    /// given depth. Mismatches are reported along with the divide breakdown of the position.
+    fn perft_epd(&self, path: &str, max_depth: u8, threads: usize, table: Option<&PerftTable>) {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                println!("info error: could not read {}: {}", path, e);
+                return;
+            }
+        };
+
+        let start = Instant::now();
+        let (mut positions, mut checked, mut failed, mut nodes) = (0, 0, 0, 0);
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = match parse_perft_epd(line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    println!("info error: {} line {}: {}", path, i + 1, e);
+                    return;
+                }
+            };
+            let mut board = match Board::from_fen(&entry.fen) {
+                Ok(board) => board,
+                Err(e) => {
+                    println!("info error: {} line {}: {}", path, i + 1, e);
+                    return;
+                }
+            };
+            board.set_frc(self.frc || castling::requires_frc(&board));
+            // The castling rights only contribute a few bits to the hash, so entries are not
+            // shared between positions, where the castling rooks may be on different files.
+            if let Some(table) = table {
+                table.clear();
+            }
+            positions += 1;
+
+            for &(depth, expected) in entry.expected.iter().filter(|(d, _)| *d <= max_depth) {
+                let entries = divide::<true>(&board, depth, threads, table);
+                let actual = entries.iter().map(|(_, count)| count).sum::<u64>();
+                checked += 1;
+                nodes += actual;
+                if actual == expected {
+                    continue;
+                }
+                failed += 1;
+                println!(
+                    "info string perft mismatch line {} depth {} expected {} actual {} fen {}",
+                    i + 1,
+                    depth,
+                    expected,
+                    actual,
+                    entry.fen
+                );
+                for (mv, count) in &entries {
+                    println!("{} - {}", mv, count);
+                }
+            }
+        }
+
+        let seconds = start.elapsed().as_secs_f64();
+        println!(
+            "info string perft epd {} positions, {} checked, {} failed, {} nodes in {:.2}s \
+             ({:.2}Mnps)",
+            positions,
+            checked,
+            failed,
+            nodes,
+            seconds,
+            nodes as f64 / seconds.max(1e-9) / 1e6
+        );
+    }
+
     /// Handle genfens command, an OpenBench utility that generates random openings from a seed to
     /// be used in an OB datagen workload.
     fn handle_genfens(&mut self, tokens: Vec<String>) {
@@ -495,6 +1248,126 @@ impl UCI {
         }
     }
 
+    fn handle_datagen(&mut self, tokens: Vec<String>) {
+        let format_name = match tokens.iter().position(|x| x == "format") {
+            Some(i) => tokens.get(i + 1).map_or("", |f| f.as_str()),
+            None => "text",
+        };
+        let Some(format) = DataFormat::parse(format_name) else {
+            println!("info error: unknown data format '{}'", format_name);
+            return;
+        };
+        let config = DatagenConfig {
+            games: self.parse_uint(&tokens, "games").unwrap_or(1000),
+            threads: self.parse_uint(&tokens, "threads").unwrap_or(1) as usize,
+            soft_nodes: self.parse_uint(&tokens, "nodes").unwrap_or(5000),
+            seed: self.parse_uint(&tokens, "seed").unwrap_or(0),
+            dfrc_percent: self.parse_uint(&tokens, "dfrc").unwrap_or(0),
+            random_moves: self.parse_uint(&tokens, "random_moves").unwrap_or(8) as usize,
+            hash_mb: self.parse_uint(&tokens, "hash").unwrap_or(16) as usize,
+            format,
+            output: tokens
+                .iter()
+                .position(|x| x == "out")
+                .and_then(|i| tokens.get(i + 1))
+                .cloned()
+                .unwrap_or_else(|| String::from("datagen.txt")),
+        };
+        println!(
+            "info string datagen games {} threads {} nodes {} seed {} dfrc {}% format {} out {}",
+            config.games,
+            config.threads,
+            config.soft_nodes,
+            config.seed,
+            config.dfrc_percent,
+            format_name,
+            config.output
+        );
+        if let Err(e) = run_datagen(&config) {
+            println!("info error: {}", e);
+        }
+    }
+
+    fn handle_convert(&self, tokens: Vec<String>) {
+        let [_, from, input, to, output] = tokens.as_slice() else {
+            println!("info error: usage is 'convert <from-format> <input> <to-format> <output>'");
+            return;
+        };
+        let (Some(from), Some(to)) = (DataFormat::parse(from), DataFormat::parse(to)) else {
+            println!("info error: formats must be one of 'text', 'bullet' or 'viri'");
+            return;
+        };
+        match convert(input, from, output, to) {
+            Ok(stats) => println!(
+                "info string convert read {} records from {}, wrote {} records to {}",
+                stats.read, input, stats.written, output
+            ),
+            Err(e) => println!("info error: {}", e),
+        }
+    }
+
+    fn handle_rescore(&self, tokens: Vec<String>) {
+        let [_, from, input, to, output, ..] = tokens.as_slice() else {
+            println!(
+                "info error: usage is 'rescore <from-format> <input> <to-format> <output> \
+                 [nodes <n>] [hash <mb>] [incheck <bool>] [noisy <bool>] [mate <bool>] \
+                 [maxeval <cp>] [minply <n>] [maxply <n>] [dedup <bool>]'"
+            );
+            return;
+        };
+        let (Some(from), Some(to)) = (DataFormat::parse(from), DataFormat::parse(to)) else {
+            println!("info error: formats must be one of 'text', 'bullet' or 'viri'");
+            return;
+        };
+        let options = &tokens[5..];
+        let parse_filters = || -> Result<Filters, String> {
+            Ok(Filters {
+                in_check: self.parse_bool(options, "incheck", true)?,
+                noisy: self.parse_bool(options, "noisy", true)?,
+                mate: self.parse_bool(options, "mate", true)?,
+                max_eval: self.parse_uint(options, "maxeval").ok().map(|n| n as i32),
+                min_ply: self.parse_uint(options, "minply").ok().map(|n| n as u32),
+                max_ply: self.parse_uint(options, "maxply").ok().map(|n| n as u32),
+                duplicates: self.parse_bool(options, "dedup", false)?,
+            })
+        };
+        let filters = match parse_filters() {
+            Ok(filters) => filters,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        let config = RescoreConfig {
+            input: input.clone(),
+            from,
+            output: output.clone(),
+            to,
+            nodes: self.parse_uint(options, "nodes").ok(),
+            hash_mb: self.parse_uint(options, "hash").unwrap_or(16) as usize,
+            filters,
+        };
+        match run_rescore(&config) {
+            Ok(stats) => {
+                println!(
+                    "info string rescore read {} records from {}, wrote {} positions to {}",
+                    stats.read, input, stats.written, output
+                );
+                println!(
+                    "info string rescore filtered incheck {} noisy {} mate {} maxeval {} ply {} \
+                     dedup {}",
+                    stats.in_check,
+                    stats.noisy,
+                    stats.mate,
+                    stats.extreme,
+                    stats.ply,
+                    stats.duplicate
+                );
+            }
+            Err(e) => println!("info error: {}", e),
+        }
+    }
+
     fn handle_help(&self) {
         println!("the following commands are available:");
         println!("uci         -- print engine info");
@@ -505,7 +1378,19 @@ impl UCI {
         println!("go          -- start searching for the best move");
         println!("stop        -- stop searching and return the best move");
         println!("eval        -- evaluate the current position");
-        println!("perft       -- run perft on the current position");
+        println!("bench       -- run the benchmark: bench [depth] [hash] [threads] [fenfile]");
+        println!("perft       -- run perft on the current position or an epd file");
+        println!("epdtest     -- run an epd test suite with a movetime or node limit");
+        println!("match       -- play a match between two uci engines, with elo and sprt");
+        println!("dumpparams  -- print the search parameters, or save them to a file");
+        println!("eval_stats  -- evaluation statistics and validation loss over a file");
+        println!("texel       -- fit the eval scaling parameters to labelled positions");
+        println!("wdl_fit     -- fit the wdl model to the scores and results of training data");
+        println!("datagen     -- generate self-play training data");
+        println!("convert     -- convert training data between text, bullet and viri formats");
+        println!("rescore     -- rescore and filter training data with the current network");
+        #[cfg(feature = "tuning")]
+        println!("spsa        -- tune the search parameters with self-play spsa");
         println!("quit        -- exit the application");
     }
 
@@ -528,6 +1413,13 @@ impl UCI {
         }
     }
 
+    /// Remove a flag such as '--json' from the tokens, returning whether it was present.
+    fn take_flag(&self, tokens: &mut Vec<String>, flag: &str) -> bool {
+        let len = tokens.len();
+        tokens.retain(|token| token != flag);
+        tokens.len() != len
+    }
+
     fn parse_bool(&self, tokens: &[String], name: &str, default: bool) -> Result<bool, String> {
         match tokens.iter().position(|x| x == name) {
             Some(index) => match tokens.get(index + 1) {
@@ -550,13 +1442,15 @@ impl UCI {
     }
 }
 
-pub fn print_search_info(td: &mut ThreadData, score: i32, bound: TTFlag, force: bool) {
-    // Don't print info if we're not in the main thread, or the UCI option Minimal is enabled, and
-    // we're not printing the final line of the search.
-    if !td.main || (td.minimal_output && !force) {
+pub fn print_search_info(td: &ThreadData, pv_idx: usize, force: bool) {
+    // Don't print info if we're not in the main thread, the search is run internally by a tool, or
+    // the UCI option Minimal is enabled, and we're not printing the final line of the search.
+    if !td.main || td.quiet || (td.minimal_output && !force) {
         return;
     }
-    let depth = td.depth;
+    let Some(line) = td.root_lines.get(pv_idx) else {
+        return;
+    };
     let seldepth = td.seldepth;
     let nodes = td.nodes();
     let time = td.start_time.elapsed().as_millis();
@@ -566,23 +1460,34 @@ pub fn print_search_info(td: &mut ThreadData, score: i32, bound: TTFlag, force:
         0
     };
     let hashfull = td.tt().fill();
-    let bound = match bound {
+    let bound = match line.bound {
         Lower => " lowerbound",
         Upper => " upperbound",
         _ => "",
     };
+    // Report scores normalised by the WDL model, so that 100 cp means a 50% chance of winning, once
+    // the model is fitted to the embedded network.
+    let display_score = wdl::normalise(line.display_score, td.root_material);
+    let wdl = if td.show_wdl {
+        let (win, draw, loss) = wdl::wdl(line.display_score, td.root_material);
+        format!(" wdl {} {} {}", win, draw, loss)
+    } else {
+        String::new()
+    };
     print!(
-        "info depth {} seldepth {} score {}{} nodes {} time {} nps {} hashfull {} pv",
-        depth,
+        "info depth {} seldepth {} multipv {} score {}{}{} nodes {} time {} nps {} hashfull {} pv",
+        line.depth,
         seldepth,
-        score::format_score(score),
+        pv_idx + 1,
+        score::format_score(display_score),
         bound,
+        wdl,
         nodes,
         time,
         nps,
         hashfull
     );
-    for mv in td.pv.line().iter().take(24) {
+    for mv in line.pv.iter().take(24) {
         print!(" {}", mv.to_uci());
     }
     println!();
@@ -594,10 +1499,35 @@ pub fn handle_one_legal_move(board: &Board, td: &mut ThreadData, root_moves: &Mo
     td.depth = 1;
     td.best_move = mv;
     td.best_score = static_eval;
-    print_search_info(td, static_eval, Exact, true);
+    td.pv.clear(1);
+    td.pv.update(0, mv);
+    td.record_root_line(static_eval, static_eval, Exact);
+    print_search_info(td, 0, true);
     (td.best_move, td.best_score)
 }
 
+/// In emergency mode, play the TT move instantly if it is one of the root moves.
+pub fn handle_emergency_move(
+    board: &Board,
+    td: &mut ThreadData,
+    root_moves: &MoveList,
+) -> Option<(Move, i32)> {
+    let entry = td.tt().probe(board.hash_with_50mr_bucket())?;
+    let mv = entry.best_move();
+    if !root_moves.iter().any(|e| e.mv == mv) {
+        return None;
+    }
+    let score = entry.score(0) as i32;
+    td.depth = 1;
+    td.best_move = mv;
+    td.best_score = score;
+    td.pv.clear(1);
+    td.pv.update(0, mv);
+    td.record_root_line(score, score, entry.flag());
+    print_search_info(td, 0, true);
+    Some((td.best_move, td.best_score))
+}
+
 pub fn handle_no_legal_moves(board: &Board, td: &mut ThreadData) -> (Move, i32) {
     println!("info error no legal moves");
     let in_check = board.threats.contains(board.our_king_sq());
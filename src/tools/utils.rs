@@ -3,7 +3,6 @@
 macro_rules! tunable_params {
 
     ($($name:ident = $val:expr, $min:literal ..= $max:literal, $spsa:expr;)*) => {
-        #[cfg(feature = "tuning")]
         use std::sync::atomic::Ordering;
 
         #[cfg(feature = "tuning")]
@@ -19,34 +18,40 @@ macro_rules! tunable_params {
             )*
         }
 
-        #[cfg(feature = "tuning")]
-        pub fn set_param(name: &str, val: i32) {
+        /// Set a parameter by name, returning whether it exists. The value is not range-checked.
+        pub fn set_param(name: &str, val: i32) -> bool {
             match name {
                 $(
                     stringify!($name) => vals::$name.store(val, Ordering::Relaxed),
                 )*
-                _ => println!("info error unknown option"),
+                _ => return false,
             }
+            true
+        }
+
+        pub fn params() -> Vec<$crate::tools::utils::TunableParam> {
+            vec![
+                $(
+                    $crate::tools::utils::TunableParam {
+                        name: stringify!($name).to_string(),
+                        value: $name(),
+                        default: $val,
+                        min: $min,
+                        max: $max,
+                        step: ($max - $min) / 20,
+                        spsa: $spsa,
+                    },
+                )*
+            ]
         }
 
         #[cfg(feature = "tuning")]
         pub fn print_params_ob() {
-            $(
-                if $spsa {
-                    let step = ($max - $min) / 20;
-                    println!(
-                        "{}, int, {}.0, {}.0, {}.0, {}, 0.002",
-                        stringify!($name),
-                        $name(),
-                        $min,
-                        $max,
-                        step,
-                    );
-                }
-            )*
+            for param in params().iter().filter(|param| param.spsa) {
+                println!("{}", param.ob_line());
+            }
         }
 
-        #[cfg(feature = "tuning")]
         mod vals {
             use std::sync::atomic::AtomicI32;
             $(
@@ -56,17 +61,10 @@ macro_rules! tunable_params {
         }
 
         $(
-        #[cfg(feature = "tuning")]
         #[inline]
         pub fn $name() -> i32 {
             vals::$name.load(Ordering::Relaxed)
         }
-
-        #[cfg(not(feature = "tuning"))]
-        #[inline]
-        pub fn $name() -> i32 {
-            $val
-        }
         )*
     };
 
@@ -81,7 +79,6 @@ macro_rules! tunable_arrays {
                     pub const DEFAULTS: &[i32] = &[$($val),*];
                     pub const LEN: usize = DEFAULTS.len();
 
-                    #[cfg(feature = "tuning")]
                     pub static VALS: [std::sync::atomic::AtomicI32; LEN] =
                         [$(std::sync::atomic::AtomicI32::new($val)),*];
                 }
@@ -89,17 +86,10 @@ macro_rules! tunable_arrays {
         }
 
         $(
-            #[cfg(feature = "tuning")]
             #[inline]
             pub fn $name(i: usize) -> i32 {
                 arrays::$name::VALS[i].load(std::sync::atomic::Ordering::Relaxed)
             }
-
-            #[cfg(not(feature = "tuning"))]
-            #[inline]
-            pub const fn $name(i: usize) -> i32 {
-                arrays::$name::DEFAULTS[i]
-            }
         )*
 
         #[cfg(feature = "tuning")]
@@ -114,7 +104,8 @@ macro_rules! tunable_arrays {
             )*
         }
 
-        #[cfg(feature = "tuning")]
+        /// Set an array entry by its name and index, as in `lmr_factor_1_3`, returning whether it
+        /// exists. The value is not range-checked.
         pub fn set_array_param(name: &str, val: i32) -> bool {
             $(
                 if let Some(rest) = name.strip_prefix(stringify!($name)) {
@@ -132,21 +123,59 @@ macro_rules! tunable_arrays {
             false
         }
 
-        #[cfg(feature = "tuning")]
-        pub fn print_array_params_ob() {
+        /// Every array entry as a separate parameter, all of which are tuned by SPSA.
+        pub fn array_params() -> Vec<$crate::tools::utils::TunableParam> {
+            let mut params = Vec::new();
             $(
-                let step = ($max - $min) / 20;
                 for i in 0..arrays::$name::LEN {
-                    println!(
-                        "{}_{}, int, {}.0, {}.0, {}.0, {}, 0.002",
-                        stringify!($name), i, $name(i), $min, $max, step
-                    );
+                    params.push($crate::tools::utils::TunableParam {
+                        name: format!("{}_{}", stringify!($name), i),
+                        value: $name(i),
+                        default: arrays::$name::DEFAULTS[i],
+                        min: $min,
+                        max: $max,
+                        step: ($max - $min) / 20,
+                        spsa: true,
+                    });
                 }
             )*
+            params
+        }
+
+        #[cfg(feature = "tuning")]
+        pub fn print_array_params_ob() {
+            for param in array_params() {
+                println!("{}", param.ob_line());
+            }
         }
     };
 }
 
+/// A tunable parameter with its current value and its declaration: the default, the range, and
+/// whether SPSA tunes it, with a step size of a twentieth of the range.
+pub struct TunableParam {
+    pub name: String,
+    pub value: i32,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+    pub spsa: bool,
+}
+
+/// The learning rate at the end of an SPSA run, as passed to OpenBench.
+pub const SPSA_R_END: f64 = 0.002;
+
+impl TunableParam {
+    /// The parameter in OpenBench's SPSA input format.
+    pub fn ob_line(&self) -> String {
+        format!(
+            "{}, int, {}.0, {}.0, {}.0, {}, {}",
+            self.name, self.value, self.min, self.max, self.step, SPSA_R_END
+        )
+    }
+}
+
 /// Gravity formula for history updates, using the current value of the entry as the base for the update.
 pub fn gravity(current: i32, update: i32, max: i32) -> i32 {
     gravity_with_base(current, update, current, max)
@@ -0,0 +1,231 @@
+use crate::evaluation::stats::read_samples;
+use crate::search::score;
+use crate::search::wdl::{material, material_ratio, polynomial, WDL_A, WDL_B};
+use crate::tools::dataformat::GameResult;
+use std::path::Path;
+
+/// The decay rates of Adam's moment estimates.
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+/// The smallest value the centre and spread of the model may take while fitting, which keeps the
+/// draw probability positive.
+const MIN_PARAM: f64 = 1.0;
+
+pub struct WdlFitOptions {
+    pub epochs: usize,
+    /// The step size of the optimiser, in units of the coefficients.
+    pub learning_rate: f64,
+}
+
+impl Default for WdlFitOptions {
+    fn default() -> Self {
+        WdlFitOptions {
+            epochs: 20000,
+            learning_rate: 1.0,
+        }
+    }
+}
+
+/// A labelled position, reduced to what the WDL model depends on.
+struct Entry {
+    /// The search score, from white's perspective.
+    score: f64,
+    /// The material on the board, as the input of the model's polynomials.
+    x: f64,
+    result: GameResult,
+}
+
+/// The coefficients of the centre and the spread polynomials, in the order of `WDL_A` and `WDL_B`.
+type Coefficients = [[f64; 4]; 2];
+
+/// Fit the coefficients of the WDL model in `search/wdl.rs` to the scores and game results of the
+/// labelled positions in a file, such as the output of `datagen`. The coefficients are fitted by
+/// full-batch gradient descent with Adam on the negative log-likelihood of the results, starting
+/// from the current coefficients, and printed as a block for `search/wdl.rs`. Positions with mate
+/// scores are skipped, as the model does not cover them.
+pub fn run_wdl_fit(input: &Path, options: &WdlFitOptions) {
+    let samples = match read_samples(input) {
+        Ok(samples) => samples,
+        Err(e) => {
+            println!("info error: {}", e);
+            return;
+        }
+    };
+
+    let entries: Vec<Entry> = samples
+        .iter()
+        .filter_map(|sample| {
+            let (score, result) = (sample.score?, sample.result?);
+            (!score::is_mate(score)).then(|| Entry {
+                score: score as f64,
+                x: material_ratio(material(&sample.board)),
+                result,
+            })
+        })
+        .collect();
+    if entries.is_empty() {
+        println!(
+            "info error: {} has no positions with a score and a result",
+            input.display()
+        );
+        return;
+    }
+    println!(
+        "fitting on {} of {} positions",
+        entries.len(),
+        samples.len()
+    );
+
+    let initial = [WDL_A, WDL_B];
+    let initial_loss = loss(&entries, &initial);
+    println!("epoch {:>5} loss {:.6}", 0, initial_loss);
+
+    let mut coefficients = initial;
+    let mut m = [[0.0; 4]; 2];
+    let mut v = [[0.0; 4]; 2];
+    for epoch in 1..=options.epochs {
+        let gradient = gradient(&entries, &coefficients);
+        for poly in 0..2 {
+            for i in 0..4 {
+                let g = gradient[poly][i];
+                m[poly][i] = BETA1 * m[poly][i] + (1.0 - BETA1) * g;
+                v[poly][i] = BETA2 * v[poly][i] + (1.0 - BETA2) * g * g;
+                let m_hat = m[poly][i] / (1.0 - BETA1.powi(epoch as i32));
+                let v_hat = v[poly][i] / (1.0 - BETA2.powi(epoch as i32));
+                coefficients[poly][i] -= options.learning_rate * m_hat / (v_hat.sqrt() + 1e-8);
+            }
+        }
+        if epoch % 2000 == 0 || epoch == options.epochs {
+            println!(
+                "epoch {:>5} loss {:.6}",
+                epoch,
+                loss(&entries, &coefficients)
+            );
+        }
+    }
+
+    let fitted = coefficients.map(|poly| poly.map(|c| (c * 10.0).round() / 10.0));
+    println!("loss {:.6} -> {:.6}", initial_loss, loss(&entries, &fitted));
+    for (name, poly) in ["WDL_A", "WDL_B"].iter().zip(fitted) {
+        let values: Vec<String> = poly.iter().map(|c| format!("{:.1}", c)).collect();
+        println!("const {}: [f64; 4] = [{}];", name, values.join(", "));
+    }
+}
+
+/// The centre and the spread of the model for an entry.
+fn params(entry: &Entry, coefficients: &Coefficients) -> (f64, f64) {
+    let a = polynomial(&coefficients[0], entry.x).max(MIN_PARAM);
+    let b = polynomial(&coefficients[1], entry.x).max(MIN_PARAM);
+    (a, b)
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// The probabilities of a white win and a white loss.
+fn probabilities(entry: &Entry, a: f64, b: f64) -> (f64, f64) {
+    (
+        sigmoid((entry.score - a) / b),
+        sigmoid((-entry.score - a) / b),
+    )
+}
+
+/// The mean negative log-likelihood of the game results.
+fn loss(entries: &[Entry], coefficients: &Coefficients) -> f64 {
+    let total: f64 = entries
+        .iter()
+        .map(|entry| {
+            let (a, b) = params(entry, coefficients);
+            let (win, loss) = probabilities(entry, a, b);
+            let p = match entry.result {
+                GameResult::WhiteWin => win,
+                GameResult::BlackWin => loss,
+                GameResult::Draw => 1.0 - win - loss,
+            };
+            -p.max(f64::MIN_POSITIVE).ln()
+        })
+        .sum();
+    total / entries.len() as f64
+}
+
+/// The gradient of the loss with respect to each coefficient.
+fn gradient(entries: &[Entry], coefficients: &Coefficients) -> Coefficients {
+    let mut gradient = [[0.0; 4]; 2];
+    for entry in entries {
+        let (a, b) = params(entry, coefficients);
+        let (win, loss) = probabilities(entry, a, b);
+
+        // The derivatives of the win and loss probabilities with respect to the centre and spread.
+        let (d_win, d_loss) = (win * (1.0 - win) / b, loss * (1.0 - loss) / b);
+        let win_ab = [-d_win, -d_win * (entry.score - a) / b];
+        let loss_ab = [-d_loss, d_loss * (entry.score + a) / b];
+
+        let d_ab = match entry.result {
+            GameResult::WhiteWin => win_ab.map(|d| -d / win),
+            GameResult::BlackWin => loss_ab.map(|d| -d / loss),
+            GameResult::Draw => {
+                let draw = (1.0 - win - loss).max(f64::MIN_POSITIVE);
+                [0, 1].map(|i| (win_ab[i] + loss_ab[i]) / draw)
+            }
+        };
+
+        let powers = [entry.x.powi(3), entry.x.powi(2), entry.x, 1.0];
+        for (poly, d) in d_ab.iter().enumerate() {
+            // The centre and the spread are held at their minimum below it.
+            if polynomial(&coefficients[poly], entry.x) < MIN_PARAM {
+                continue;
+            }
+            for (g, power) in gradient[poly].iter_mut().zip(powers) {
+                *g += d * power;
+            }
+        }
+    }
+    gradient.map(|poly| poly.map(|g| g / entries.len() as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient_matches_finite_differences() {
+        let entries = [
+            Entry {
+                score: 150.0,
+                x: 1.2,
+                result: GameResult::WhiteWin,
+            },
+            Entry {
+                score: -40.0,
+                x: 0.6,
+                result: GameResult::Draw,
+            },
+            Entry {
+                score: 80.0,
+                x: 0.9,
+                result: GameResult::BlackWin,
+            },
+        ];
+        let coefficients = [WDL_A, WDL_B];
+        let gradient = gradient(&entries, &coefficients);
+        for poly in 0..2 {
+            for i in 0..4 {
+                let h = 1e-4;
+                let (mut up, mut down) = (coefficients, coefficients);
+                up[poly][i] += h;
+                down[poly][i] -= h;
+                let numeric = (loss(&entries, &up) - loss(&entries, &down)) / (2.0 * h);
+                assert!(
+                    (gradient[poly][i] - numeric).abs() < 1e-6 * numeric.abs().max(1e-3),
+                    "{} {}: {} vs {}",
+                    poly,
+                    i,
+                    gradient[poly][i],
+                    numeric
+                );
+            }
+        }
+    }
+}